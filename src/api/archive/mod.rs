@@ -0,0 +1,283 @@
+///! Archive API.
+///! Articles are grouped by the year and month of a datetime metadata key.
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+use chrono::Datelike;
+use serde_json::Value as JsonValue;
+use writium::prelude::*;
+use writium_auth::{Authority, DumbAuthority};
+use self::header::ContentType;
+use super::index::{is_indexable, DateTime, KeyKind, KeyValue};
+use super::observer::PostObserver;
+use super::schedule::Schedule;
+
+#[cfg(test)]
+mod tests;
+
+const DEFAULT_ENTRIES_PER_REQUEST: usize = 5;
+
+const ERR_NOT_FOUND: &'static str = "No article is archived in the requested \
+    period.";
+const ERR_PERIOD: &'static str = "A valid period should be `<year>` or \
+    `<year>/<month>`.";
+
+#[derive(Default)]
+struct ArchiveMap {
+    /// Articles in each month, ordered by time.
+    buckets: BTreeMap<(i32, u32), BTreeSet<(DateTime, String)>>,
+    /// Article to its time, used to clean up buckets when an article is
+    /// updated.
+    posts: HashMap<String, DateTime>,
+}
+impl ArchiveMap {
+    fn insert(&mut self, id: &str, dt: DateTime) {
+        self.buckets.entry((dt.year(), dt.month()))
+            .or_insert_with(BTreeSet::new)
+            .insert((dt, id.to_owned()));
+        self.posts.insert(id.to_owned(), dt);
+    }
+    fn remove(&mut self, id: &str) {
+        if let Some(dt) = self.posts.remove(id) {
+            let bucket = (dt.year(), dt.month());
+            let is_empty = match self.buckets.get_mut(&bucket) {
+                Some(ids) => {
+                    ids.remove(&(dt, id.to_owned()));
+                    ids.is_empty()
+                },
+                None => false,
+            };
+            if is_empty {
+                self.buckets.remove(&bucket);
+            }
+        }
+    }
+}
+
+/// Number of articles archived in a month.
+#[derive(Clone, Serialize)]
+pub struct MonthBucket {
+    pub month: u32,
+    pub count: usize,
+}
+/// Number of articles archived in a year, and in each month of the year.
+#[derive(Clone, Serialize)]
+pub struct YearBucket {
+    pub year: i32,
+    pub count: usize,
+    pub months: Vec<MonthBucket>,
+}
+
+#[derive(Clone)]
+pub struct Archive {
+    key: String,
+    /// Type of the key, which should be one of the datetime types.
+    kind: KeyKind,
+    map: Arc<RwLock<ArchiveMap>>,
+}
+impl Archive {
+    /// Make a new archive by RFC 3339 datetime metadata key `key`.
+    pub fn new(key: &str) -> Archive {
+        Archive::with_kind(key, KeyKind::DateTime)
+    }
+    /// Make a new archive by metadata key `key` of datetime type `kind`, e.g.,
+    /// a date-only key.
+    pub fn with_kind(key: &str, kind: KeyKind) -> Archive {
+        Archive {
+            key: key.to_owned(),
+            kind: kind,
+            map: Arc::new(RwLock::new(ArchiveMap::default())),
+        }
+    }
+    /// All the years and months having archived articles, newest first.
+    /// Articles in `hidden` are not counted.
+    pub fn years(&self, hidden: &HashSet<String>) -> Vec<YearBucket> {
+        let map = self.map.read().unwrap();
+        let mut rv: Vec<YearBucket> = Vec::new();
+        for (&(year, month), ids) in map.buckets.iter().rev() {
+            let count = ids.iter()
+                .filter(|&&(_, ref id)| !hidden.contains(id))
+                .count();
+            if count == 0 {
+                continue
+            }
+            let month = MonthBucket { month: month, count: count };
+            let is_same_year = rv.last()
+                .map(|x| x.year == year)
+                .unwrap_or(false);
+            if is_same_year {
+                let last = rv.last_mut().unwrap();
+                last.count += month.count;
+                last.months.push(month);
+            } else {
+                rv.push(YearBucket {
+                    year: year,
+                    count: month.count,
+                    months: vec![month],
+                });
+            }
+        }
+        rv
+    }
+    /// Articles archived in `year`, or in `month` of `year` if `month` is
+    /// given, newest first. Articles in `hidden` are excluded.
+    pub fn posts(&self, year: i32, month: Option<u32>,
+        hidden: &HashSet<String>) -> Vec<String> {
+        let map = self.map.read().unwrap();
+        // Bounds are inclusive so that they never overflow.
+        let (beg, end) = match month {
+            Some(month) => ((year, month), (year, month)),
+            None => ((year, 0), (year, u32::max_value())),
+        };
+        map.buckets.range(beg..=end)
+            .rev()
+            .flat_map(|(_, ids)| ids.iter().rev())
+            .map(|&(_, ref id)| id)
+            .filter(|id| !hidden.contains(*id))
+            .cloned()
+            .collect()
+    }
+}
+impl PostObserver for Archive {
+    fn update_metadata(&self, id: &str, meta: &JsonValue) {
+        let mut map = self.map.write().unwrap();
+        map.remove(id);
+        if !is_indexable(meta) {
+            return
+        }
+        if let Some(KeyValue::DateTime(dt)) = meta.get(&self.key)
+            .and_then(|x| self.kind.value_of(x)) {
+            map.insert(id, dt);
+        }
+    }
+    fn remove(&self, id: &str) {
+        self.map.write().unwrap().remove(id);
+    }
+    fn dump(&self, id: &str) -> JsonValue {
+        match self.map.read().unwrap().posts.get(id) {
+            Some(dt) => JsonValue::String(dt.to_rfc3339()),
+            None => JsonValue::Null,
+        }
+    }
+    fn restore(&self, id: &str, state: &JsonValue) {
+        if let Some(dt) = state.as_str()
+            .and_then(|x| DateTime::parse_from_rfc3339(x).ok()) {
+            self.map.write().unwrap().insert(id, dt);
+        }
+    }
+}
+
+/// Parse path segments `<year>[/<month>]` into a period.
+pub fn parse_period(segs: &[String]) -> Result<(i32, Option<u32>)> {
+    match segs.len() {
+        1 | 2 => {},
+        _ => return Err(Error::bad_request(ERR_PERIOD)),
+    }
+    let year = segs[0].parse::<i32>()
+        .map_err(|_| Error::bad_request(ERR_PERIOD))?;
+    let month = match segs.get(1) {
+        Some(month) => match month.parse::<u32>() {
+            Ok(month) if month >= 1 && month <= 12 => Some(month),
+            _ => return Err(Error::bad_request(ERR_PERIOD)),
+        },
+        None => None,
+    };
+    Ok((year, month))
+}
+
+#[derive(Serialize)]
+struct Period {
+    count: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    months: Option<Vec<MonthBucket>>,
+    posts: Vec<String>,
+}
+
+pub struct ArchiveApi {
+    archive: Archive,
+    auth: Arc<Authority<Privilege=()>>,
+    schedule: Schedule,
+    entries_per_request: usize,
+}
+impl ArchiveApi {
+    pub fn new(archive: Archive) -> ArchiveApi {
+        ArchiveApi {
+            archive: archive,
+            auth: Arc::new(DumbAuthority::new()),
+            schedule: Schedule::default(),
+            entries_per_request: DEFAULT_ENTRIES_PER_REQUEST,
+        }
+    }
+    /// Authority of requests listing articles not published yet.
+    pub fn set_auth(&mut self, auth: Arc<Authority<Privilege=()>>) {
+        self.auth = auth;
+    }
+    pub fn set_schedule(&mut self, schedule: Schedule) {
+        self.schedule = schedule;
+    }
+    pub fn set_entries_per_request(&mut self, entries_per_request: usize) {
+        self.entries_per_request = entries_per_request;
+    }
+
+    /// GET `/archive`
+    fn get_years(&self, req: &mut Request) -> ApiResult {
+        let hidden = self.schedule.hidden_from(req, &*self.auth);
+        Response::new()
+            .with_header(ContentType(
+                "application/json; charset=UTF-8".parse().unwrap()))
+            .with_json(&self.archive.years(&hidden))
+    }
+    /// GET `/archive/<year>[/<month>]?[from]`
+    fn get_period(&self, req: &mut Request) -> ApiResult {
+        #[derive(Deserialize)]
+        struct Param {
+            /// The index of the first article to be included.
+            from: Option<usize>,
+        }
+        let param = req.to_param::<Param>()?;
+        let (year, month) = parse_period(req.path_segs())?;
+        let hidden = self.schedule.hidden_from(req, &*self.auth);
+        let posts = self.archive.posts(year, month, &hidden);
+        if posts.is_empty() {
+            return Err(Error::not_found(ERR_NOT_FOUND))
+        }
+        let months = if month.is_none() {
+            self.archive.years(&hidden).into_iter()
+                .find(|x| x.year == year)
+                .map(|x| x.months)
+        } else {
+            None
+        };
+        let period = Period {
+            count: posts.len(),
+            months: months,
+            posts: posts.into_iter()
+                .skip(param.from.unwrap_or(0))
+                .take(self.entries_per_request)
+                .collect(),
+        };
+        Response::new()
+            .with_header(ContentType(
+                "application/json; charset=UTF-8".parse().unwrap()))
+            .with_json(&period)
+    }
+}
+impl Api for ArchiveApi {
+    fn name(&self) -> &[&str] {
+        &["archive"]
+    }
+
+    fn route(&self, req: &mut Request) -> ApiResult {
+        use self::header::Allow;
+        use self::Method::*;
+        match req.method() {
+            Options => Ok(Response::new()
+                .with_header(Allow(vec![Options, Get]))),
+            Get => if req.path_segs().len() == 0 {
+                self.get_years(req)
+            } else {
+                self.get_period(req)
+            },
+            _ => Err(Error::method_not_allowed()),
+        }
+    }
+}
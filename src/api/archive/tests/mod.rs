@@ -0,0 +1,146 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use writium::prelude::*;
+use self::header::{Authorization, Bearer};
+use api::observer::PostObserver;
+use api::schedule::Schedule;
+use api::test_common::*;
+use auth::SimpleAuthority;
+use super::{Archive, ArchiveApi};
+
+fn archive() -> Archive {
+    let archive = Archive::new("published");
+    archive.update_metadata("foo",
+        &json!({ "published": "2018-03-01T00:00:00+08:00" }));
+    archive.update_metadata("bar",
+        &json!({ "published": "2018-03-20T00:00:00+08:00" }));
+    archive.update_metadata("baz",
+        &json!({ "published": "2018-01-05T00:00:00+08:00" }));
+    archive.update_metadata("qux",
+        &json!({ "published": "2017-12-31T00:00:00+08:00" }));
+    archive.update_metadata("quux", &json!({ "published": "Someday" }));
+    archive
+}
+fn api() -> ArchiveApi {
+    let mut api = ArchiveApi::new(archive());
+    api.set_entries_per_request(2);
+    api
+}
+/// `later` is scheduled in the future.
+fn scheduled_api() -> ArchiveApi {
+    let archive = archive();
+    let schedule = Schedule::new("published");
+    let meta = json!({ "published": "2999-01-01T00:00:00+08:00" });
+    archive.update_metadata("later", &meta);
+    schedule.update_metadata("later", &meta);
+    let mut api = ArchiveApi::new(archive);
+    api.set_auth(Arc::new(SimpleAuthority::new("PASSWORD")));
+    api.set_schedule(schedule);
+    api
+}
+
+#[test]
+fn test_posts() {
+    let archive = archive();
+    let mut hidden = HashSet::new();
+    assert_eq!(archive.posts(2018, None, &hidden), vec!["bar", "foo", "baz"]);
+    assert_eq!(archive.posts(2018, Some(3), &hidden), vec!["bar", "foo"]);
+    assert!(archive.posts(2018, Some(2), &hidden).is_empty());
+    assert!(archive.posts(i32::max_value(), None, &hidden).is_empty());
+    hidden.insert("foo".to_owned());
+    assert_eq!(archive.posts(2018, Some(3), &hidden), vec!["bar"]);
+}
+#[test]
+fn test_posts_by_date() {
+    use chrono::FixedOffset;
+    use api::index::KeyKind;
+    let tz = FixedOffset::east(8 * 3600);
+    let archive = Archive::with_kind("published", KeyKind::Date(tz));
+    archive.update_metadata("foo", &json!({ "published": "2018-03-01" }));
+    archive.update_metadata("bar", &json!({ "published": "2018-02-28" }));
+    let hidden = HashSet::new();
+    assert_eq!(archive.posts(2018, Some(3), &hidden), vec!["foo"]);
+    assert_eq!(archive.posts(2018, Some(2), &hidden), vec!["bar"]);
+    let archive = Archive::with_kind("published", KeyKind::NaiveDateTime(tz));
+    archive.update_metadata("foo",
+        &json!({ "published": "2018-03-01 00:30" }));
+    assert_eq!(archive.posts(2018, Some(3), &hidden), vec!["foo"]);
+}
+#[test]
+fn test_update() {
+    let archive = archive();
+    let hidden = HashSet::new();
+    archive.update_metadata("baz",
+        &json!({ "published": "2018-03-10T00:00:00+08:00" }));
+    assert_eq!(archive.posts(2018, Some(3), &hidden),
+        vec!["bar", "baz", "foo"]);
+    assert!(archive.posts(2018, Some(1), &hidden).is_empty());
+    archive.remove("qux");
+    assert!(archive.posts(2017, None, &hidden).is_empty());
+}
+#[test]
+fn test_get_years() {
+    let req = Request::new(Method::Get);
+    let res = test_ok(&api(), req);
+    check_type(&res, "application", "json");
+    check_content(&res, concat!(
+        r#"[{"year":2018,"count":3,"months":[{"month":3,"count":2},"#,
+        r#"{"month":1,"count":1}]},"#,
+        r#"{"year":2017,"count":1,"months":[{"month":12,"count":1}]}]"#));
+}
+#[test]
+fn test_get_year() {
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["2018"])
+        .with_query("from=1");
+    let res = test_ok(&api(), req);
+    check_type(&res, "application", "json");
+    check_content(&res, concat!(
+        r#"{"count":3,"months":[{"month":3,"count":2},"#,
+        r#"{"month":1,"count":1}],"posts":["foo","baz"]}"#));
+}
+#[test]
+fn test_get_month() {
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["2018", "03"]);
+    let res = test_ok(&api(), req);
+    check_type(&res, "application", "json");
+    check_content(&res, r#"{"count":2,"posts":["bar","foo"]}"#);
+}
+#[test]
+fn fail_get_month() {
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["2018", "13"]);
+    let err = test_err(&api(), req);
+    assert_eq!(err.status(), StatusCode::BadRequest);
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["2018", "02"]);
+    let err = test_err(&api(), req);
+    assert_eq!(err.status(), StatusCode::NotFound);
+}
+#[test]
+fn test_get_years_scheduled() {
+    let req = Request::new(Method::Get);
+    let res = test_ok(&scheduled_api(), req);
+    check_content(&res, concat!(
+        r#"[{"year":2018,"count":3,"months":[{"month":3,"count":2},"#,
+        r#"{"month":1,"count":1}]},"#,
+        r#"{"year":2017,"count":1,"months":[{"month":12,"count":1}]}]"#));
+    let req = Request::new(Method::Get)
+        .with_header(Authorization(Bearer { token: "PASSWORD".to_owned() }));
+    let res = test_ok(&scheduled_api(), req);
+    assert!(res.to_str().unwrap()
+        .starts_with(r#"[{"year":2999,"count":1,"#));
+}
+#[test]
+fn test_get_period_scheduled() {
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["2999"]);
+    let err = test_err(&scheduled_api(), req);
+    assert_eq!(err.status(), StatusCode::NotFound);
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["2999", "01"])
+        .with_header(Authorization(Bearer { token: "PASSWORD".to_owned() }));
+    let res = test_ok(&scheduled_api(), req);
+    check_content(&res, r#"{"count":1,"posts":["later"]}"#);
+}
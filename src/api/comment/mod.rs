@@ -6,6 +6,9 @@ use writium::prelude::*;
 use writium_auth::{Authority, DumbAuthority};
 use writium_cache::{Cache, DumbCacheSource};
 use model::comment::{Comment, Comments};
+use super::etag::{check_match, etag_of_json, respond_json};
+use super::draft::Drafts;
+use super::schedule::Schedule;
 
 #[cfg(test)]
 mod tests;
@@ -16,6 +19,7 @@ const ERR_PRIVILEGE: &'static str = "Requested operation need a matching \
     privilege token to execute.";
 const ERR_NOT_FOUND: &'static str = "Cannot find a comment matching the \
     requested index. Maybe it's been deleted already.";
+const ERR_NOT_PUBLISHED: &'static str = "The article is not published yet.";
 const ERR_RANGE: &'static str = "The requested range is not valid. A valid \
     range should be one of `from={from}`, `to={to}`, or `from={from}&to={to}` \
     where `{from}` < `{to}`.";
@@ -23,6 +27,8 @@ const ERR_RANGE: &'static str = "The requested range is not valid. A valid \
 pub struct CommentApi {
     cache: Arc<Cache<Comments>>,
     auth: Arc<Authority<Privilege=()>>,
+    schedule: Schedule,
+    drafts: Drafts,
     entries_per_request: usize,
 }
 impl CommentApi {
@@ -30,6 +36,8 @@ impl CommentApi {
         CommentApi {
             cache: Arc::new(Cache::new(0, DumbCacheSource::new())),
             auth: Arc::new(DumbAuthority::new()),
+            schedule: Schedule::default(),
+            drafts: Drafts::default(),
             entries_per_request: DEFAULT_ENTRIES_PER_REQUEST,
         }
     }
@@ -43,6 +51,27 @@ impl CommentApi {
     pub fn set_entries_per_request(&mut self, entries_per_request: usize) {
         self.entries_per_request = entries_per_request;
     }
+    /// Set the schedule of articles to be published in the future. Comments
+    /// of these articles are only available to authorized requests.
+    pub fn set_schedule(&mut self, schedule: Schedule) {
+        self.schedule = schedule;
+    }
+    /// Set the drafts, whose comments are only available to authorized
+    /// requests.
+    pub fn set_drafts(&mut self, drafts: Drafts) {
+        self.drafts = drafts;
+    }
+
+    /// Comments of drafts and of articles not published yet are hidden from,
+    /// and cannot be posted by, unauthorized requests.
+    fn check_published(&self, req: &Request, id: &str) -> Result<()> {
+        if (self.drafts.contains(id) || self.schedule.is_hidden(id)) &&
+            self.auth.authorize((), req).is_err() {
+            Err(Error::not_found(ERR_NOT_PUBLISHED))
+        } else {
+            Ok(())
+        }
+    }
 
     /// DELETE `/comments/<path..>?{index}`  
     /// DELETE `/comments/<path..>?[from][to][{from, to}]`  
@@ -92,6 +121,9 @@ impl CommentApi {
                     .ok_or(err)
                     .and_then(|priv_token| auth(priv_token, req))
             })?;
+        // The comment is compared as it's responded to `GET ?index=<index>`.
+        let etag = cache.get(&index).map(etag_of_json);
+        check_match(req, etag.as_ref())?;
         cache.remove(&index);
         Ok(Response::new())
     }
@@ -102,6 +134,9 @@ impl CommentApi {
         let lock = self.cache.get(&id)?;
         let mut cache = lock.write().unwrap();
         let from = from.unwrap_or(0);
+        // Comments are compared as they're responded to `GET ?from=<from>`.
+        let etag = etag_of_json(&self.page(&cache, from));
+        check_match(req, Some(&etag))?;
         let to = if let Some(to) = to {
             to
         } else if let Some(ref last) = cache.iter().last() {
@@ -120,6 +155,10 @@ impl CommentApi {
     fn delete_all(&self, req: &mut Request) -> ApiResult {
         self.auth.authorize((), req)?;
         let id = req.path_segs().join("/");
+        let etag = self.cache.get(&id)
+            .map(|cache| etag_of_json(&*cache.read().unwrap()))
+            .ok();
+        check_match(req, etag.as_ref())?;
         self.cache.remove(&id)?;
         Ok(Response::new())
     }
@@ -143,30 +182,36 @@ impl CommentApi {
     /// If index is present. Remove only the one indicated by the index.
     fn get_one(&self, req: &mut Request, index: usize) -> ApiResult {
         let id = req.path_segs().join("/");
+        self.check_published(req, &id)?;
         let lock = self.cache.get(&id)?;
         let cache = lock.read().unwrap();
         cache.get(&index)
             .ok_or(Error::not_found(ERR_NOT_FOUND))
-            .and_then(|comment| Response::new().with_json(comment))
+            .and_then(|comment| respond_json(req, Response::new(), comment))
     }
     /// If from specifier present, get the item indexed form and several
     /// following items. In case some of the items are missing, ignore
     /// them.
     fn get_from(&self, req: &mut Request, from: usize) -> ApiResult {
         let id = req.path_segs().join("/");
+        self.check_published(req, &id)?;
         let lock = self.cache.get(&id)?;
         let cache = lock.read().unwrap();
-
-        let res_content: BTreeMap<&usize, &Comment> = cache.iter()
+        respond_json(req, Response::new(), &self.page(&cache, from))
+    }
+    /// Comments from the `from`-th one, as many as requested at a time.
+    fn page<'a>(&self, comments: &'a Comments, from: usize)
+        -> BTreeMap<&'a usize, &'a Comment> {
+        comments.iter()
             .skip(from)
             .take(self.entries_per_request)
-            .collect();
-        Response::new().with_json(&res_content)
+            .collect()
     }
 
     /// POST `/comments/<path..>`
     fn post(&self, req: &mut Request) -> ApiResult {
         let id = req.path_segs().join("/");
+        self.check_published(req, &id)?;
         let comment = req.to_json::<Comment>()?;
         let cache = self.cache.create(&id)?;
         // There are comments already loaded, start indexing from the last one.
@@ -201,3 +201,126 @@ fn test_post() {
     check_type(&res, "application", "json");
     check_content(&res, POST_JSON);
 }
+#[test]
+fn test_scheduled() {
+    use api::observer::PostObserver;
+    use api::schedule::Schedule;
+    let mut api = api();
+    let schedule = Schedule::new("published");
+    schedule.update_metadata("foo",
+        &json!({ "published": "2999-01-01T00:00:00+08:00" }));
+    api.set_schedule(schedule);
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["foo"]);
+    let err = test_err(&api, req);
+    assert_eq!(err.status(), StatusCode::NotFound);
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["foo"])
+        .with_query("index=0");
+    let err = test_err(&api, req);
+    assert_eq!(err.status(), StatusCode::NotFound);
+    let req = Request::new(Method::Post)
+        .with_path_segs(&["foo"])
+        .with_json(&Comment { metadata: HashMap::new(), content: "Panda!".to_owned() })
+        .unwrap();
+    let err = test_err(&api, req);
+    assert_eq!(err.status(), StatusCode::NotFound);
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["foo"])
+        .with_header(Authorization(Bearer { token: "PASSWORD".to_owned() }));
+    let res = test_ok(&api, req);
+    check_content(&res, DEFAULT_JSON);
+}
+#[test]
+fn test_draft() {
+    use api::draft::Drafts;
+    use api::observer::PostObserver;
+    let mut api = api();
+    let drafts = Drafts::new();
+    drafts.update_metadata("foo", &json!({ "draft": true }));
+    api.set_drafts(drafts);
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["foo"]);
+    let err = test_err(&api, req);
+    assert_eq!(err.status(), StatusCode::NotFound);
+    let req = Request::new(Method::Post)
+        .with_path_segs(&["foo"])
+        .with_json(&Comment { metadata: HashMap::new(), content: "Panda!".to_owned() })
+        .unwrap();
+    let err = test_err(&api, req);
+    assert_eq!(err.status(), StatusCode::NotFound);
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["foo"])
+        .with_header(Authorization(Bearer { token: "PASSWORD".to_owned() }));
+    let res = test_ok(&api, req);
+    check_content(&res, DEFAULT_JSON);
+}
+#[test]
+fn test_get_not_modified() {
+    use self::header::{ETag, IfNoneMatch};
+    let api = api();
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["foo"])
+        .with_query("index=0");
+    let etag = test_ok(&api, req).header::<ETag>().unwrap().0.clone();
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["foo"])
+        .with_query("index=0")
+        .with_header(IfNoneMatch::Items(vec![etag]));
+    let res = test_ok(&api, req);
+    assert_eq!(res.status(), StatusCode::NotModified);
+}
+#[test]
+fn test_delete_one_if_match() {
+    use self::header::{ETag, IfMatch};
+    let api = api();
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["foo"])
+        .with_query("index=0");
+    let etag = test_ok(&api, req).header::<ETag>().unwrap().0.clone();
+    let req = Request::new(Method::Delete)
+        .with_path_segs(&["foo"])
+        .with_query("index=0")
+        .with_header(IfMatch::Items(vec![etag]))
+        .with_header(Authorization(Bearer { token: "PASSWORD".to_owned() }));
+    let _ = test_ok(&api, req);
+}
+#[test]
+fn fail_delete_one_if_match() {
+    use self::header::{ETag, IfMatch};
+    let api = api_with_many_comments();
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["foo"])
+        .with_query("index=2");
+    let etag = test_ok(&api, req).header::<ETag>().unwrap().0.clone();
+    // Another comment is fetched and its tag doesn't match.
+    let req = Request::new(Method::Delete)
+        .with_path_segs(&["foo"])
+        .with_query("index=0")
+        .with_header(IfMatch::Items(vec![etag]))
+        .with_header(Authorization(Bearer { token: "PASSWORD".to_owned() }));
+    let err = test_err(&api, req);
+    assert_eq!(err.status(), StatusCode::PreconditionFailed);
+}
+#[test]
+fn test_delete_range_if_match() {
+    use self::header::{ETag, IfMatch};
+    let api = api_with_many_comments();
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["foo"]);
+    let etag = test_ok(&api, req).header::<ETag>().unwrap().0.clone();
+    let req = Request::new(Method::Delete)
+        .with_path_segs(&["foo"])
+        .with_query("from=0&to=3")
+        .with_header(IfMatch::Items(vec![etag.clone()]))
+        .with_header(Authorization(Bearer { token: "PASSWORD".to_owned() }));
+    let _ = test_ok(&api, req);
+    // The comments have been changed since they were fetched.
+    let req = Request::new(Method::Delete)
+        .with_path_segs(&["foo"])
+        .with_query("from=0&to=5")
+        .with_header(IfMatch::Items(vec![etag]))
+        .with_header(Authorization(Bearer { token: "PASSWORD".to_owned() }));
+    let err = test_err(&api, req);
+    assert_eq!(err.status(), StatusCode::PreconditionFailed);
+}
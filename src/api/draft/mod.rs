@@ -0,0 +1,111 @@
+///! Drafts API.
+///! Articles with metadata `draft` set true are drafts. They are excluded from
+///! all the indices and are only available to authorized requests.
+use std::collections::BTreeSet;
+use std::sync::{Arc, RwLock};
+use serde_json::Value as JsonValue;
+use writium::prelude::*;
+use writium_auth::{Authority, DumbAuthority};
+use self::header::ContentType;
+use super::index::is_draft;
+use super::observer::PostObserver;
+
+const DEFAULT_ENTRIES_PER_REQUEST: usize = 5;
+
+/// All the drafts, ordered by their IDs.
+#[derive(Clone, Default)]
+pub struct Drafts {
+    drafts: Arc<RwLock<BTreeSet<String>>>,
+}
+impl Drafts {
+    pub fn new() -> Drafts {
+        Drafts::default()
+    }
+    pub fn drafts(&self) -> Vec<String> {
+        self.drafts.read().unwrap().iter().cloned().collect()
+    }
+    pub fn contains(&self, id: &str) -> bool {
+        self.drafts.read().unwrap().contains(id)
+    }
+}
+impl PostObserver for Drafts {
+    fn update_metadata(&self, id: &str, meta: &JsonValue) {
+        let mut drafts = self.drafts.write().unwrap();
+        if is_draft(meta) {
+            drafts.insert(id.to_owned());
+        } else {
+            drafts.remove(id);
+        }
+    }
+    fn remove(&self, id: &str) {
+        self.drafts.write().unwrap().remove(id);
+    }
+    fn dump(&self, id: &str) -> JsonValue {
+        JsonValue::Bool(self.drafts.read().unwrap().contains(id))
+    }
+    fn restore(&self, id: &str, state: &JsonValue) {
+        if state == &JsonValue::Bool(true) {
+            self.drafts.write().unwrap().insert(id.to_owned());
+        }
+    }
+}
+
+pub struct DraftApi {
+    drafts: Drafts,
+    auth: Arc<Authority<Privilege=()>>,
+    entries_per_request: usize,
+}
+impl DraftApi {
+    pub fn new(drafts: Drafts) -> DraftApi {
+        DraftApi {
+            drafts: drafts,
+            auth: Arc::new(DumbAuthority::new()),
+            entries_per_request: DEFAULT_ENTRIES_PER_REQUEST,
+        }
+    }
+    pub fn set_auth(&mut self, auth: Arc<Authority<Privilege=()>>) {
+        self.auth = auth;
+    }
+    pub fn set_entries_per_request(&mut self, entries_per_request: usize) {
+        self.entries_per_request = entries_per_request;
+    }
+
+    /// GET `/drafts?[from]`
+    fn get(&self, req: &mut Request) -> ApiResult {
+        #[derive(Deserialize)]
+        struct Param {
+            /// The index of the first draft to be included.
+            from: Option<usize>,
+        }
+        self.auth.authorize((), &req)?;
+        let param = req.to_param::<Param>()?;
+        let drafts: Vec<String> = self.drafts.drafts().into_iter()
+            .skip(param.from.unwrap_or(0))
+            .take(self.entries_per_request)
+            .collect();
+        Response::new()
+            .with_header(ContentType(
+                "application/json; charset=UTF-8".parse().unwrap()))
+            .with_json(&drafts)
+    }
+}
+impl Api for DraftApi {
+    fn name(&self) -> &[&str] {
+        &["drafts"]
+    }
+
+    fn route(&self, req: &mut Request) -> ApiResult {
+        use self::header::Allow;
+        use self::Method::*;
+        match req.method() {
+            Options => Ok(Response::new()
+                .with_header(Allow(vec![Options, Get]))),
+            Get => self.get(req),
+            _ => Err(Error::method_not_allowed()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
+
@@ -0,0 +1,34 @@
+use std::sync::Arc;
+use writium::prelude::*;
+use self::header::{Authorization, Bearer};
+use auth::SimpleAuthority;
+use api::observer::PostObserver;
+use api::test_common::*;
+use super::{DraftApi, Drafts};
+
+fn api() -> DraftApi {
+    let drafts = Drafts::new();
+    drafts.update_metadata("foo", &json!({ "draft": true }));
+    drafts.update_metadata("bar", &json!({ "draft": true }));
+    drafts.update_metadata("baz", &json!({ "draft": false }));
+    let mut api = DraftApi::new(drafts);
+    api.set_auth(Arc::new(SimpleAuthority::new("PASSWORD")));
+    api
+}
+
+#[test]
+fn test_get() {
+    let req = Request::new(Method::Get)
+        .with_header(Authorization(Bearer {
+            token: "PASSWORD".to_owned()
+        }));
+    let res = test_ok(&api(), req);
+    check_type(&res, "application", "json");
+    check_content(&res, r#"["bar","foo"]"#);
+}
+#[test]
+fn fail_get_auth() {
+    let req = Request::new(Method::Get);
+    let err = test_err(&api(), req);
+    assert_eq!(err.status(), StatusCode::Unauthorized);
+}
@@ -0,0 +1,110 @@
+///! Entity tags and conditional requests.
+///! Entity tags are strong ones made from hashes of representations, so that
+///! clients can revalidate their caches with `If-None-Match`, and editors can
+///! avoid overwriting changes made by others with `If-Match`.
+use serde::Serialize;
+use writium::prelude::*;
+use self::header::{ETag, EntityTag, IfMatch, IfNoneMatch};
+
+const ERR_PRECONDITION: &'static str = "The resource has been changed since \
+    it was fetched. Fetch it again before making changes.";
+const ERR_JSON: &'static str = "Unable to serialize the response.";
+
+/// Strong entity tag of representation `data`, which is its 64-bit FNV-1a
+/// hash. The hash is stable across runs and platforms.
+pub fn etag_of(data: &[u8]) -> EntityTag {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for &byte in data {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    EntityTag::strong(format!("{:016x}", hash))
+}
+/// Strong entity tag of `value` serialized in JSON.
+pub fn etag_of_json<T: Serialize>(value: &T) -> EntityTag {
+    etag_of(&::serde_json::to_vec(value).unwrap_or_default())
+}
+
+/// Check `If-Match` of a request changing a resource whose current entity tag
+/// is `etag`, or `None` if the resource doesn't exist. 412 Precondition Failed
+/// is returned if the client is not changing the version it has.
+pub fn check_match(req: &Request, etag: Option<&EntityTag>) -> Result<()> {
+    let is_match = match (req.header::<IfMatch>(), etag) {
+        (None, _) => true,
+        (Some(IfMatch::Any), etag) => etag.is_some(),
+        (Some(IfMatch::Items(tags)), Some(etag)) =>
+            tags.iter().any(|x| x.strong_eq(etag)),
+        (Some(IfMatch::Items(_)), None) => false,
+    };
+    if is_match {
+        Ok(())
+    } else {
+        Err(Error::new(StatusCode::PreconditionFailed, ERR_PRECONDITION))
+    }
+}
+/// Make `res` the response of representation `data` with its entity tag. If
+/// `If-None-Match` shows the client has the same representation already, 304
+/// Not Modified is responded instead.
+pub fn respond(req: &Request, res: Response, data: Vec<u8>) -> Response {
+    let etag = etag_of(&data);
+    let is_fresh = match req.header::<IfNoneMatch>() {
+        Some(IfNoneMatch::Any) => true,
+        Some(IfNoneMatch::Items(tags)) =>
+            tags.iter().any(|x| x.weak_eq(&etag)),
+        None => false,
+    };
+    if is_fresh {
+        Response::new()
+            .with_status(StatusCode::NotModified)
+            .with_header(ETag(etag))
+    } else {
+        res.with_header(ETag(etag))
+            .with_body(data)
+    }
+}
+/// Make `res` the response of `value` serialized in JSON, like `respond`.
+pub fn respond_json<T: Serialize>(req: &Request, res: Response, value: &T)
+    -> ApiResult {
+    let data = ::serde_json::to_vec(value)
+        .map_err(|err| Error::internal(ERR_JSON).with_cause(err))?;
+    Ok(respond(req, res, data))
+}
+
+#[cfg(test)]
+mod tests {
+    use writium::prelude::*;
+    use self::header::{ETag, IfMatch, IfNoneMatch};
+    use super::{check_match, etag_of, respond};
+
+    #[test]
+    fn test_etag() {
+        assert_eq!(etag_of(b"").tag(), "cbf29ce484222325");
+        assert!(etag_of(b"foo").strong_eq(&etag_of(b"foo")));
+        assert!(!etag_of(b"foo").strong_eq(&etag_of(b"bar")));
+    }
+    #[test]
+    fn test_if_none_match() {
+        let req = Request::new(Method::Get)
+            .with_header(IfNoneMatch::Items(vec![etag_of(b"foo")]));
+        let res = respond(&req, Response::new(), b"foo".to_vec());
+        assert_eq!(res.status(), StatusCode::NotModified);
+        let res = respond(&req, Response::new(), b"bar".to_vec());
+        assert_eq!(res.status(), StatusCode::Ok);
+        assert_eq!(res.header::<ETag>().unwrap().0, etag_of(b"bar"));
+    }
+    #[test]
+    fn test_if_match() {
+        let etag = etag_of(b"foo");
+        let req = Request::new(Method::Put)
+            .with_header(IfMatch::Items(vec![etag.clone()]));
+        assert!(check_match(&req, Some(&etag)).is_ok());
+        let err = check_match(&req, Some(&etag_of(b"bar"))).unwrap_err();
+        assert_eq!(err.status(), StatusCode::PreconditionFailed);
+        assert!(check_match(&req, None).is_err());
+        let req = Request::new(Method::Put)
+            .with_header(IfMatch::Any);
+        assert!(check_match(&req, None).is_err());
+        let req = Request::new(Method::Put);
+        assert!(check_match(&req, None).is_ok());
+    }
+}
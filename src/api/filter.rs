@@ -0,0 +1,274 @@
+///! Filtering of article listings by metadata.
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock};
+use serde_json::Value as JsonValue;
+use writium::prelude::*;
+use super::index::{Index, KeyPart, KeyValue};
+use super::observer::PostObserver;
+
+const ERR_RANGE: &'static str = "`after` and `before` are only available for \
+    indices of known key type, and should be valid values of the type.";
+const ERR_PARAM: &'static str = "Unknown query parameter. Metadata values are \
+    filtered by `filter.<key>=<value>`.";
+
+/// Query parameters with special meanings in filters.
+const HAS: &str = "has";
+const AFTER: &str = "after";
+const BEFORE: &str = "before";
+/// Prefix of query parameters filtering by values of metadata keys.
+const EQUALS_PREFIX: &str = "filter.";
+
+/// Predicates on article metadata, built from query parameters:
+///
+/// * `filter.<key>=<value>`: value of metadata key `key` equals to `value`, or
+///   is an array containing `value`;
+/// * `has=<key1>,<key2>..`: all the metadata keys are present;
+/// * `after=<value>`: value of the index key is no less than `value`;
+/// * `before=<value>`: value of the index key is less than `value`.
+#[derive(Default)]
+pub struct Filter {
+    equals: Vec<(String, String)>,
+    has: Vec<String>,
+    range_key: Option<KeyPart>,
+    after: Option<KeyValue>,
+    before: Option<KeyValue>,
+    /// Parameters the filter is made from.
+    params: Vec<(String, String)>,
+}
+impl Filter {
+    /// Make a filter from query parameters `params`, ignoring those listed in
+    /// `reserved`. Ranges are applied to the primary key of `index`. Other
+    /// parameters not recognized by filters are rejected.
+    pub fn from_params(params: BTreeMap<String, String>, reserved: &[&str],
+        index: &Index) -> Result<Filter> {
+        let mut filter = Filter::default();
+        for (key, value) in params.into_iter() {
+            if reserved.contains(&&key[..]) {
+                continue
+            }
+            match &key[..] {
+                HAS => filter.has.extend(value.split(',')
+                    .map(|x| x.trim())
+                    .filter(|x| !x.is_empty())
+                    .map(|x| x.to_owned())),
+                AFTER | BEFORE => {
+                    let part = index.primary_key_part()
+                        .ok_or(Error::bad_request(ERR_RANGE))?;
+                    let bound = part.kind.parse_value(&value)
+                        .ok_or(Error::bad_request(ERR_RANGE))?;
+                    if key == AFTER {
+                        filter.after = Some(bound);
+                    } else {
+                        filter.before = Some(bound);
+                    }
+                    filter.range_key = Some(part.clone());
+                },
+                _ if key.starts_with(EQUALS_PREFIX) => filter.equals.push(
+                    (key[EQUALS_PREFIX.len()..].to_owned(), value.clone())),
+                _ => return Err(Error::bad_request(ERR_PARAM)),
+            }
+            filter.params.push((key, value));
+        }
+        Ok(filter)
+    }
+    /// Check if query parameter `key` is recognized by filters, so that
+    /// parameters irrelevant to filters can be left out, e.g., those added by
+    /// tracking links.
+    pub fn is_param(key: &str) -> bool {
+        key == HAS || key == AFTER || key == BEFORE ||
+            key.starts_with(EQUALS_PREFIX)
+    }
+    pub fn is_empty(&self) -> bool {
+        self.params.is_empty()
+    }
+    /// Parameters the filter is made from, so that the filter can be kept
+    /// when navigating between pages.
+    pub fn params(&self) -> &[(String, String)] {
+        &self.params
+    }
+    pub fn is_match(&self, meta: &JsonValue) -> bool {
+        let is_present = |key: &str| meta.get(key)
+            .map(|x| !x.is_null())
+            .unwrap_or(false);
+        if !self.has.iter().all(|key| is_present(key.as_str())) {
+            return false
+        }
+        let is_equal = |json: &JsonValue, value: &str| match *json {
+            JsonValue::String(ref x) => x == value,
+            JsonValue::Number(_) | JsonValue::Bool(_) =>
+                json.to_string() == value,
+            _ => false,
+        };
+        for &(ref key, ref value) in self.equals.iter() {
+            let is_match = match meta.get(key) {
+                Some(&JsonValue::Array(ref arr)) => arr.iter()
+                    .any(|x| is_equal(x, value.as_str())),
+                Some(json) => is_equal(json, value.as_str()),
+                None => false,
+            };
+            if !is_match {
+                return false
+            }
+        }
+        if let Some(ref part) = self.range_key {
+            let value = match meta.get(&part.field)
+                .and_then(|x| part.kind.value_of(x)) {
+                Some(value) => value,
+                None => return false,
+            };
+            if self.after.as_ref().map(|x| &value < x).unwrap_or(false) ||
+                self.before.as_ref().map(|x| &value >= x).unwrap_or(false) {
+                return false
+            }
+        }
+        true
+    }
+    /// Filter articles listed in `ids`, keeping their order. Articles not in
+    /// `index` are excluded.
+    pub fn filter(&self, ids: Vec<String>, index: &FilterIndex)
+        -> Vec<String> {
+        let guard = index.fields.read().unwrap();
+        ids.into_iter()
+            .filter(|id| guard.get(id)
+                .map(|fields| self.is_match(fields))
+                .unwrap_or(false))
+            .collect()
+    }
+}
+
+/// Metadata fields of all the articles filters are applied to, so that
+/// listings are filtered without loading metadata from local storage.
+#[derive(Clone, Default)]
+pub struct FilterIndex {
+    fields: Arc<RwLock<HashMap<String, JsonValue>>>,
+}
+impl FilterIndex {
+    pub fn new() -> FilterIndex {
+        FilterIndex::default()
+    }
+}
+/// Fields of `meta` filters can match. Scalars and arrays are kept as they
+/// are, while objects are kept empty since only their presence is checked.
+fn filterable(meta: &JsonValue) -> JsonValue {
+    let fields = match *meta {
+        JsonValue::Object(ref obj) => obj.iter()
+            .map(|(key, value)| {
+                let value = match *value {
+                    JsonValue::Object(_) => json!({}),
+                    ref value => value.clone(),
+                };
+                (key.clone(), value)
+            })
+            .collect(),
+        _ => ::serde_json::Map::new(),
+    };
+    JsonValue::Object(fields)
+}
+impl PostObserver for FilterIndex {
+    fn update_metadata(&self, id: &str, meta: &JsonValue) {
+        self.fields.write().unwrap().insert(id.to_owned(), filterable(meta));
+    }
+    fn remove(&self, id: &str) {
+        self.fields.write().unwrap().remove(id);
+    }
+    fn dump(&self, id: &str) -> JsonValue {
+        self.fields.read().unwrap().get(id)
+            .cloned()
+            .unwrap_or(JsonValue::Null)
+    }
+    fn restore(&self, id: &str, state: &JsonValue) {
+        if state.is_object() {
+            self.fields.write().unwrap().insert(id.to_owned(), state.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use api::index::Index;
+    use api::observer::PostObserver;
+    use super::{Filter, FilterIndex};
+
+    fn filter(query: &[(&str, &str)]) -> Filter {
+        let index = Index::new("published", "-datetime", None);
+        let params: BTreeMap<String, String> = query.iter()
+            .map(|&(k, v)| (k.to_owned(), v.to_owned()))
+            .collect();
+        Filter::from_params(params, &["from"], &index).unwrap()
+    }
+
+    #[test]
+    fn test_equals() {
+        let filter = filter(&[("filter.author", "alice"),
+            ("filter.tags", "rust"), ("from", "1")]);
+        assert!(filter.is_match(&json!({
+            "author": "alice",
+            "tags": ["async", "rust"],
+        })));
+        assert!(!filter.is_match(&json!({
+            "author": "bob",
+            "tags": ["rust"],
+        })));
+        assert!(!filter.is_match(&json!({ "author": "alice" })));
+        assert_eq!(filter.params().len(), 2);
+    }
+    #[test]
+    fn test_has() {
+        let filter = filter(&[("has", "series,cover")]);
+        assert!(filter.is_match(&json!({ "series": "a", "cover": "b.png" })));
+        assert!(!filter.is_match(&json!({ "series": "a", "cover": null })));
+    }
+    #[test]
+    fn test_range() {
+        let filter = filter(&[("after", "2018-01-01"),
+            ("before", "2018-06-01T00:00:00+08:00")]);
+        assert!(filter.is_match(&json!({
+            "published": "2018-01-01T00:00:00+00:00",
+        })));
+        assert!(filter.is_match(&json!({
+            "published": "2018-05-31T23:00:00+08:00",
+        })));
+        assert!(!filter.is_match(&json!({
+            "published": "2018-06-01T00:00:00+08:00",
+        })));
+        assert!(!filter.is_match(&json!({
+            "published": "2017-12-31T23:00:00+00:00",
+        })));
+        assert!(!filter.is_match(&json!({})));
+    }
+    #[test]
+    fn test_filter_index() {
+        let index = FilterIndex::new();
+        index.update_metadata("foo", &json!({
+            "author": "alice",
+            "series": { "name": "a" },
+        }));
+        index.update_metadata("bar", &json!({ "author": "bob" }));
+        index.update_metadata("baz", &json!({ "author": "alice" }));
+        index.remove("baz");
+        let ids = vec!["foo".to_owned(), "bar".to_owned(), "baz".to_owned()];
+        assert_eq!(filter(&[("filter.author", "alice")])
+            .filter(ids.clone(), &index), &["foo"]);
+        assert_eq!(filter(&[("has", "series")]).filter(ids, &index), &["foo"]);
+        assert_eq!(index.dump("foo"), json!({
+            "author": "alice",
+            "series": {},
+        }));
+    }
+    #[test]
+    fn fail_range() {
+        let index = Index::new("published", "-datetime", None);
+        let mut params = BTreeMap::new();
+        params.insert("after".to_owned(), "yesterday".to_owned());
+        assert!(Filter::from_params(params, &[], &index).is_err());
+    }
+    #[test]
+    fn fail_unknown_param() {
+        let index = Index::new("published", "-datetime", None);
+        let mut params = BTreeMap::new();
+        params.insert("utm_source".to_owned(), "feed".to_owned());
+        assert!(!Filter::is_param("utm_source"));
+        assert!(Filter::from_params(params, &[], &index).is_err());
+    }
+}
@@ -1,8 +1,20 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use chrono::{FixedOffset, NaiveDate, NaiveDateTime, TimeZone};
 use serde_json::Value as JsonValue;
+use super::ranked_set::RankedSet;
 
 pub type DateTime = ::chrono::DateTime<::chrono::FixedOffset>;
 
-pub trait IndexKeyType: Sized + Send + Sync + Ord {
+/// Formats of datetimes without timezone, tried in order.
+const NAIVE_DATETIME_FORMATS: &[&str] = &[
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%d %H:%M",
+    "%Y-%m-%dT%H:%M:%S",
+    "%Y-%m-%dT%H:%M",
+];
+
+pub trait IndexKeyType: Sized + Send + Sync + Ord + Clone {
     fn try_from_json(json: &JsonValue) -> Option<Self>;
 }
 impl IndexKeyType for i64 {
@@ -31,99 +43,381 @@ impl IndexKeyType for DateTime {
         None
     }
 }
+impl IndexKeyType for Float {
+    fn try_from_json(json: &JsonValue) -> Option<Float> {
+        json.as_f64().and_then(Float::new)
+    }
+}
+impl IndexKeyType for Version {
+    fn try_from_json(json: &JsonValue) -> Option<Version> {
+        json.as_str().and_then(Version::parse)
+    }
+}
+
+/// A float which is not NaN, so that it's totally ordered.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Float(f64);
+impl Float {
+    pub fn new(x: f64) -> Option<Float> {
+        if x.is_nan() { None } else { Some(Float(x)) }
+    }
+}
+impl Eq for Float {}
+impl Ord for Float {
+    fn cmp(&self, other: &Float) -> Ordering {
+        self.0.partial_cmp(&other.0).unwrap()
+    }
+}
+impl PartialOrd for Float {
+    fn partial_cmp(&self, other: &Float) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+enum PreRelease {
+    Numeric(u64),
+    Alphanumeric(String),
+}
+/// Semver-style version like `v1.2.10-beta.2`. Versions are ordered by their
+/// numeric components, and pre-releases precede the release. Build metadata
+/// following `+` is ignored.
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub struct Version {
+    /// Numeric components without trailing zeros, so that `1.2` equals to
+    /// `1.2.0`.
+    nums: Vec<u64>,
+    is_release: bool,
+    pre: Vec<PreRelease>,
+}
+impl Version {
+    pub fn parse(text: &str) -> Option<Version> {
+        let text = text.trim();
+        let text = if text.starts_with('v') { &text[1..] } else { text };
+        let text = text.splitn(2, '+').next().unwrap_or_default();
+        let mut split = text.splitn(2, '-');
+        let mut nums = split.next().unwrap_or_default()
+            .split('.')
+            .map(|x| x.parse().ok())
+            .collect::<Option<Vec<u64>>>()?;
+        while nums.last() == Some(&0) {
+            nums.pop();
+        }
+        let pre = match split.next() {
+            Some(pre) => pre.split('.')
+                .map(|x| if x.is_empty() {
+                    None
+                } else if let Ok(num) = x.parse() {
+                    Some(PreRelease::Numeric(num))
+                } else {
+                    Some(PreRelease::Alphanumeric(x.to_owned()))
+                })
+                .collect::<Option<Vec<_>>>()?,
+            None => Vec::new(),
+        };
+        Some(Version {
+            nums: nums,
+            is_release: pre.is_empty(),
+            pre: pre,
+        })
+    }
+}
 
-struct IndexItem<T: IndexKeyType> {
-    pub key: T,
-    pub path: String,
+/// Parse timezone like `+08:00`, `-0500` or `Z`.
+pub fn parse_timezone(text: &str) -> Option<FixedOffset> {
+    let text = text.trim();
+    if text == "Z" || text == "UTC" {
+        return Some(FixedOffset::east(0))
+    }
+    let sign = match text.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let digits: String = text[1..].chars().filter(|&x| x != ':').collect();
+    if digits.len() != 4 {
+        return None
+    }
+    let hours: i32 = digits[..2].parse().ok()?;
+    let minutes: i32 = digits[2..].parse().ok()?;
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+/// Type of values of an index key. Dates and datetimes without timezone are
+/// interpreted in the timezone they carry.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KeyKind {
+    Integer,
+    String,
+    DateTime,
+    /// Date like `2018-03-01`, as the midnight of the day.
+    Date(FixedOffset),
+    /// Datetime without timezone like `2018-03-01 10:00`.
+    NaiveDateTime(FixedOffset),
+    Float,
+    Version,
+}
+impl KeyKind {
+    /// Parse index key type `ty`. Dates and datetimes without timezone are
+    /// interpreted in timezone `tz`.
+    pub fn parse(ty: &str, tz: FixedOffset) -> Option<KeyKind> {
+        match ty {
+            "integer" => Some(KeyKind::Integer),
+            "string" => Some(KeyKind::String),
+            "datetime" => Some(KeyKind::DateTime),
+            "date" => Some(KeyKind::Date(tz)),
+            "naive_datetime" => Some(KeyKind::NaiveDateTime(tz)),
+            "float" => Some(KeyKind::Float),
+            "version" => Some(KeyKind::Version),
+            _ => None,
+        }
+    }
+    /// Check if values of this type are datetimes.
+    pub fn is_datetime(&self) -> bool {
+        match *self {
+            KeyKind::DateTime | KeyKind::Date(_) | KeyKind::NaiveDateTime(_) =>
+                true,
+            _ => false,
+        }
+    }
+    /// Parse `json` into a key value of this type.
+    pub fn value_of(&self, json: &JsonValue) -> Option<KeyValue> {
+        match *self {
+            KeyKind::Integer => i64::try_from_json(json).map(KeyValue::Integer),
+            KeyKind::String => String::try_from_json(json)
+                .map(KeyValue::String),
+            KeyKind::DateTime => DateTime::try_from_json(json)
+                .map(KeyValue::DateTime),
+            KeyKind::Date(_) | KeyKind::NaiveDateTime(_) => json.as_str()
+                .and_then(|x| self.parse_value(x)),
+            KeyKind::Float => Float::try_from_json(json).map(KeyValue::Float),
+            KeyKind::Version => Version::try_from_json(json)
+                .map(KeyValue::Version),
+        }
+    }
+    /// Parse `text`, e.g., a query parameter, into a key value of this type.
+    /// Dates without time, like `2018-01-01`, are accepted as the midnight in
+    /// UTC, or in the timezone of the type if it has one.
+    pub fn parse_value(&self, text: &str) -> Option<KeyValue> {
+        let parse_date = |tz: &FixedOffset| {
+            let date = NaiveDate::parse_from_str(text, "%Y-%m-%d").ok()?;
+            tz.from_local_datetime(&date.and_hms(0, 0, 0)).single()
+        };
+        let dt = match *self {
+            KeyKind::Integer =>
+                return text.parse().ok().map(KeyValue::Integer),
+            KeyKind::String => return Some(KeyValue::String(text.to_owned())),
+            KeyKind::Float => return text.parse().ok()
+                .and_then(Float::new)
+                .map(KeyValue::Float),
+            KeyKind::Version => return Version::parse(text)
+                .map(KeyValue::Version),
+            KeyKind::DateTime => DateTime::parse_from_rfc3339(text).ok()
+                .or_else(|| parse_date(&FixedOffset::east(0))),
+            KeyKind::Date(tz) => parse_date(&tz),
+            KeyKind::NaiveDateTime(tz) => NAIVE_DATETIME_FORMATS.iter()
+                .filter_map(|fmt| NaiveDateTime::parse_from_str(text, fmt).ok())
+                .next()
+                .and_then(|dt| tz.from_local_datetime(&dt).single())
+                .or_else(|| parse_date(&tz)),
+        };
+        dt.map(KeyValue::DateTime)
+    }
+}
+
+/// Value of an index key. Values of the same key part are always of the same
+/// kind. Dates and datetimes without timezone are converted into datetimes.
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum KeyValue {
+    Integer(i64),
+    String(String),
+    DateTime(DateTime),
+    Float(Float),
+    Version(Version),
+}
+
+/// A part of a (compound) index key. Articles are ordered by the first part,
+/// and then by the following ones when the former parts are equal.
+#[derive(Clone, Debug)]
+pub struct KeyPart {
+    /// Metadata key.
+    pub field: String,
+    pub kind: KeyKind,
+    pub asc: bool,
+}
+impl KeyPart {
+    /// Parse index type `ty` into key parts. `ty` is a comma-separated list of
+    /// `[+|-]<kind>[:<field>]`, e.g., `-datetime,+string:title`. Parts without
+    /// an explicit field use metadata key `key`. Order is ascending if the sign
+    /// is omitted. Dates and datetimes without timezone are interpreted in
+    /// timezone `tz`.
+    pub fn parse_all(key: &str, ty: &str, tz: FixedOffset)
+        -> Option<Vec<KeyPart>> {
+        let mut parts = Vec::new();
+        for part in ty.split(',') {
+            let mut part = part.trim();
+            let asc = if part.starts_with('+') {
+                // Prefixed by '+', the index order is ascending. Larger value
+                // will be placed at the back.
+                part = &part[1..];
+                true
+            } else if part.starts_with('-') {
+                // Prefixed by '-', the index order is descending, Larger value
+                // will be placed at the front.
+                part = &part[1..];
+                false
+            } else {
+                true
+            };
+            let mut split = part.splitn(2, ':');
+            let kind = KeyKind::parse(split.next().unwrap_or_default(), tz)?;
+            let field = match split.next() {
+                Some(field) if field.is_empty() => return None,
+                Some(field) => field,
+                None => key,
+            };
+            parts.push(KeyPart {
+                field: field.to_owned(),
+                kind: kind,
+                asc: asc,
+            });
+        }
+        Some(parts)
+    }
+}
+
+/// Value of a key part, ordered as the part specified. Missing values are
+/// always placed after present ones.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct KeyCell {
+    value: Option<KeyValue>,
+    asc: bool,
+}
+impl Ord for KeyCell {
+    fn cmp(&self, other: &KeyCell) -> Ordering {
+        match (&self.value, &other.value) {
+            (&Some(ref a), &Some(ref b)) => if self.asc {
+                a.cmp(b)
+            } else {
+                b.cmp(a)
+            },
+            (&Some(_), &None) => Ordering::Less,
+            (&None, &Some(_)) => Ordering::Greater,
+            (&None, &None) => Ordering::Equal,
+        }
+    }
+}
+impl PartialOrd for KeyCell {
+    fn partial_cmp(&self, other: &KeyCell) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
 }
 
 pub trait IndexCollection: Send + Sync {
-    fn insert(&mut self, path: &str, key: &JsonValue);
+    /// Insert or update article `path` with its metadata `meta`.
+    fn insert(&mut self, path: &str, meta: &JsonValue);
     fn get_range(&self, skip: usize, take: usize) -> Vec<String>;
     fn remove(&mut self, path: &str);
     fn len(&self) -> usize;
+    /// Position of article `path` in the index, or `None` if it's not indexed.
+    fn rank(&self, path: &str) -> Option<usize>;
 }
 
-pub struct DefaultIndexCollection<T: IndexKeyType> {
-    index: Vec<IndexItem<T>>,
-    asc: bool,
+/// Index collection ordered by a (compound) key. Articles with the same key are
+/// ordered by their paths, so the order is stable no matter in which order the
+/// articles were indexed.
+///
+/// Insertion, removal and rank take O(log n) time, and a range of `take`
+/// articles takes O(log n + take) time. Articles are looked up by path in a
+/// side table, so that the original entry can be found without scanning the
+/// whole index when an article is updated.
+pub struct DefaultIndexCollection {
+    parts: Vec<KeyPart>,
+    /// Index entries in index order.
+    index: RankedSet<(Vec<KeyCell>, String)>,
+    /// Article path to its key in `index`.
+    keys: HashMap<String, Vec<KeyCell>>,
 }
-impl<T: IndexKeyType> DefaultIndexCollection<T> {
-    pub fn new(asc: bool) -> DefaultIndexCollection<T> {
+impl DefaultIndexCollection {
+    /// Make a new index collection ordered by `parts`. There must be at least
+    /// one part.
+    pub fn new(parts: Vec<KeyPart>) -> DefaultIndexCollection {
+        assert!(!parts.is_empty(), "Index key should have at least one part.");
         DefaultIndexCollection {
-            index: Vec::new(),
-            asc: asc,
+            parts: parts,
+            index: RankedSet::new(),
+            keys: HashMap::new(),
         }
     }
-    fn search_by_key(&self, key: &T) -> usize {
-        match if self.asc {
-            self.index.binary_search_by(|item| item.key.cmp(key))
-        } else {
-            self.index.binary_search_by(|item| item.key.cmp(key).reverse())
-        } {
-            Ok(pos) => pos,
-            Err(pos) => pos,
+    /// Extract key from metadata. `None` is returned if the value of the first
+    /// part is missing or invalid.
+    fn make_key(&self, meta: &JsonValue) -> Option<Vec<KeyCell>> {
+        let mut key = Vec::with_capacity(self.parts.len());
+        for (i, part) in self.parts.iter().enumerate() {
+            let value = meta.get(&part.field)
+                .and_then(|x| part.kind.value_of(x));
+            if i == 0 && value.is_none() {
+                return None
+            }
+            key.push(KeyCell { value: value, asc: part.asc });
+        }
+        Some(key)
+    }
+    /// Remove the entry of `path`. Returns true if the article was indexed.
+    fn remove_entry(&mut self, path: &str) -> bool {
+        match self.keys.remove(path) {
+            Some(key) => {
+                self.index.remove(&(key, path.to_owned()));
+                true
+            },
+            None => false,
         }
     }
 }
-impl<T: IndexKeyType> IndexCollection for DefaultIndexCollection<T> {
-    fn insert(&mut self, path: &str, key: &JsonValue) {
-        let key = if let Some(key) = T::try_from_json(key) {
+impl IndexCollection for DefaultIndexCollection {
+    fn insert(&mut self, path: &str, meta: &JsonValue) {
+        let key = if let Some(key) = self.make_key(meta) {
             key
         } else {
-            if let Ok(pos) = self.index.binary_search_by(|item| {
-                let str_ref: &str = item.path.as_ref();
-                str_ref.cmp(path)
-            }) {
-                self.index.remove(pos);
+            if self.remove_entry(path) {
                 warn!("`{}` is updated with a new key which cannot be parsed \
-                    into `DateTime`. So it's removed from the index.", path)
+                    into the index key type. So it's removed from the index.",
+                    path)
             } else {
-                warn!("`{}` has a key which cannot be parsed into `DateTime`. \
-                    So it's not indexed.", path);
+                warn!("`{}` has a key which cannot be parsed into the index \
+                    key type. So it's not indexed.", path);
             }
             return
         };
-        match self.index.iter().position(|item| (&*item.path).eq(path)) {
-            Some(pos) => {
-                let mut item = self.index.remove(pos);
-                let new_pos = self.search_by_key(&key);
-                item.key = key;
-                self.index.insert(new_pos, item);
+        match self.keys.insert(path.to_owned(), key.clone()) {
+            Some(old_key) => {
+                self.index.remove(&(old_key, path.to_owned()));
                 info!("Updated index key for article: {}", path);
             },
-            None => {
-                let new_pos = self.search_by_key(&key);
-                self.index.insert(new_pos,
-                IndexItem {
-                    key: key,
-                    path: path.to_owned(),
-                });
-                info!("Indexed article: {}", path);
-            },
+            None => info!("Indexed article: {}", path),
         }
+        self.index.insert((key, path.to_owned()));
     }
     fn get_range(&self, skip: usize, take: usize) -> Vec<String> {
-        self.index.iter()
-            .skip(skip)
-            .take(take)
-            .map(|item| if cfg!(windows) {
-                    item.path.replace('\\', "/")
+        self.index.range(skip, take).into_iter()
+            .map(|&(_, ref path)| if cfg!(windows) {
+                    path.replace('\\', "/")
                 } else {
-                    item.path.to_owned()
+                    path.to_owned()
                 }
             )
             .collect::<Vec<_>>()
     }
     fn remove(&mut self, path: &str) {
-        if let Some(pos) = self.index.iter()
-            .position(|item| (&*item.path).eq(path)) {
-            self.index.remove(pos);
-        }
+        self.remove_entry(path);
     }
     fn len(&self) -> usize {
         self.index.len()
     }
+    fn rank(&self, path: &str) -> Option<usize> {
+        let entry = (self.keys.get(path)?.clone(), path.to_owned());
+        self.index.rank(&entry)
+    }
 }
 
 pub struct DumbIndexCollection();
@@ -133,66 +427,135 @@ impl DumbIndexCollection {
     }
 }
 impl IndexCollection for DumbIndexCollection {
-    fn insert(&mut self, _path: &str, _key: &JsonValue) {}
+    fn insert(&mut self, _path: &str, _meta: &JsonValue) {}
     fn get_range(&self, _skip: usize, _take: usize) -> Vec<String> {
         Vec::new()
     }
     fn remove(&mut self, _path: &str) {}
     fn len(&self) -> usize { 0 }
+    fn rank(&self, _path: &str) -> Option<usize> { None }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{DateTime, DefaultIndexCollection, IndexCollection};
+    use chrono::FixedOffset;
+    use serde_json::Value as JsonValue;
+    use super::{parse_timezone, DefaultIndexCollection, IndexCollection, KeyPart,
+        Version};
+
+    fn new_col(ty: &str) -> DefaultIndexCollection {
+        DefaultIndexCollection::new(
+            KeyPart::parse_all("key", ty, FixedOffset::east(0)).unwrap())
+    }
+    fn key<T: Into<JsonValue>>(key: T) -> JsonValue {
+        json!({ "key": key.into() })
+    }
     #[test]
     fn test_default_index_col_num() {
-        let mut col = DefaultIndexCollection::<i64>::new(true);
-        col.insert("4", &json!(4));
-        col.insert("3", &json!(3));
-        col.insert("2", &json!(2));
-        col.insert("1", &json!(1));
-        col.insert("0", &json!(0));
+        let mut col = new_col("+integer");
+        col.insert("4", &key(4));
+        col.insert("3", &key(3));
+        col.insert("2", &key(2));
+        col.insert("1", &key(1));
+        col.insert("0", &key(0));
         assert_eq!(col.get_range(0, 5), &["0", "1", "2", "3", "4"]);
     }
     #[test]
     fn test_default_index_col_str() {
-        let mut col = DefaultIndexCollection::<String>::new(true);
-        col.insert("4", &json!("4"));
-        col.insert("3", &json!("3"));
-        col.insert("2", &json!("2"));
-        col.insert("1", &json!("1"));
-        col.insert("0", &json!("0"));
+        let mut col = new_col("+string");
+        col.insert("4", &key("4"));
+        col.insert("3", &key("3"));
+        col.insert("2", &key("2"));
+        col.insert("1", &key("1"));
+        col.insert("0", &key("0"));
         assert_eq!(col.get_range(0, 5), &["0", "1", "2", "3", "4"]);
     }
     #[test]
     fn test_default_index_col_dt() {
-        let mut col = DefaultIndexCollection::<DateTime>::new(true);
-        col.insert("4", &json!("2018-01-01T00:00:04+00:00"));
-        col.insert("3", &json!("2018-01-01T00:00:03+00:00"));
-        col.insert("2", &json!("2018-01-01T00:00:02+00:00"));
-        col.insert("1", &json!("2018-01-01T00:00:01+00:00"));
-        col.insert("0", &json!("2018-01-01T00:00:00+00:00"));
+        let mut col = new_col("+datetime");
+        col.insert("4", &key("2018-01-01T00:00:04+00:00"));
+        col.insert("3", &key("2018-01-01T00:00:03+00:00"));
+        col.insert("2", &key("2018-01-01T00:00:02+00:00"));
+        col.insert("1", &key("2018-01-01T00:00:01+00:00"));
+        col.insert("0", &key("2018-01-01T00:00:00+00:00"));
         assert_eq!(col.get_range(0, 5), &["0", "1", "2", "3", "4"]);
     }
     #[test]
+    fn test_default_index_col_date() {
+        let parts = KeyPart::parse_all("key", "+date",
+            FixedOffset::east(8 * 3600)).unwrap();
+        let mut col = DefaultIndexCollection::new(parts);
+        col.insert("2", &key("2018-03-02"));
+        col.insert("1", &key("2018-03-01"));
+        col.insert("0", &key("2018-03-01T00:00:00+00:00"));
+        assert_eq!(col.get_range(0, 3), &["1", "2"]);
+    }
+    #[test]
+    fn test_default_index_col_naive_dt() {
+        let mut col = new_col("-naive_datetime");
+        col.insert("0", &key("2018-03-01 10:00"));
+        col.insert("1", &key("2018-03-01 10:00:30"));
+        col.insert("2", &key("2018-03-01T11:00:00"));
+        col.insert("3", &key("2018-03-01"));
+        assert_eq!(col.get_range(0, 4), &["2", "1", "0", "3"]);
+    }
+    #[test]
+    fn test_default_index_col_float() {
+        let mut col = new_col("-float");
+        col.insert("0", &key(0.5));
+        col.insert("1", &key(-1));
+        col.insert("2", &key(2.25));
+        col.insert("3", &key("1.0"));
+        assert_eq!(col.get_range(0, 4), &["2", "0", "1"]);
+    }
+    #[test]
+    fn test_default_index_col_version() {
+        let mut col = new_col("-version");
+        col.insert("0", &key("1.2.9"));
+        col.insert("1", &key("1.2.10"));
+        col.insert("2", &key("v1.10.0-beta"));
+        col.insert("3", &key("1.10"));
+        col.insert("4", &key("1.x"));
+        assert_eq!(col.get_range(0, 5), &["3", "2", "1", "0"]);
+    }
+    #[test]
+    fn test_version() {
+        let v = |x| Version::parse(x).unwrap();
+        assert_eq!(v("1.2"), v("1.2.0"));
+        assert_eq!(v("v1.0.0+build.5"), v("1.0.0"));
+        assert!(v("1.0.0-alpha") < v("1.0.0-alpha.1"));
+        assert!(v("1.0.0-alpha.1") < v("1.0.0-alpha.beta"));
+        assert!(v("1.0.0-beta.2") < v("1.0.0-beta.11"));
+        assert!(v("1.0.0-rc.1") < v("1.0.0"));
+        assert!(Version::parse("1.0.0-").is_none());
+    }
+    #[test]
+    fn test_parse_timezone() {
+        assert_eq!(parse_timezone("+08:00"), Some(FixedOffset::east(8 * 3600)));
+        assert_eq!(parse_timezone("-0530"),
+            Some(FixedOffset::west(5 * 3600 + 30 * 60)));
+        assert_eq!(parse_timezone("Z"), Some(FixedOffset::east(0)));
+        assert_eq!(parse_timezone("08:00"), None);
+    }
+    #[test]
     fn test_default_index_col_range() {
-        let mut col = DefaultIndexCollection::<i64>::new(true);
-        col.insert("4", &json!(4));
-        col.insert("3", &json!(3));
-        col.insert("2", &json!(2));
-        col.insert("1", &json!(1));
-        col.insert("0", &json!(0));
+        let mut col = new_col("+integer");
+        col.insert("4", &key(4));
+        col.insert("3", &key(3));
+        col.insert("2", &key(2));
+        col.insert("1", &key(1));
+        col.insert("0", &key(0));
         assert_eq!(col.get_range(1, 3), &["1", "2", "3"]);
     }
     #[test]
     fn test_default_index_col_range_multiple_times() {
-        let mut col = DefaultIndexCollection::<i64>::new(true);
-        col.insert("5", &json!(5));
-        col.insert("4", &json!(4));
-        col.insert("3", &json!(3));
-        col.insert("2", &json!(2));
-        col.insert("1", &json!(1));
-        col.insert("0", &json!(0));
+        let mut col = new_col("+integer");
+        col.insert("5", &key(5));
+        col.insert("4", &key(4));
+        col.insert("3", &key(3));
+        col.insert("2", &key(2));
+        col.insert("1", &key(1));
+        col.insert("0", &key(0));
         assert_eq!(col.get_range(0, 3), &["0", "1", "2"]);
         assert_eq!(col.get_range(1, 3), &["1", "2", "3"]);
         assert_eq!(col.get_range(2, 3), &["2", "3", "4"]);
@@ -200,32 +563,283 @@ mod tests {
     }
     #[test]
     fn test_default_index_col_num_reverse() {
-        let mut col = DefaultIndexCollection::<i64>::new(false);
-        col.insert("0", &json!(0));
-        col.insert("1", &json!(1));
-        col.insert("2", &json!(2));
-        col.insert("3", &json!(3));
-        col.insert("4", &json!(4));
+        let mut col = new_col("-integer");
+        col.insert("0", &key(0));
+        col.insert("1", &key(1));
+        col.insert("2", &key(2));
+        col.insert("3", &key(3));
+        col.insert("4", &key(4));
         assert_eq!(col.get_range(0, 5), &["4", "3", "2", "1", "0"]);
     }
     #[test]
     fn test_default_index_col_str_reverse() {
-        let mut col = DefaultIndexCollection::<String>::new(false);
-        col.insert("0", &json!("0"));
-        col.insert("1", &json!("1"));
-        col.insert("2", &json!("2"));
-        col.insert("3", &json!("3"));
-        col.insert("4", &json!("4"));
+        let mut col = new_col("-string");
+        col.insert("0", &key("0"));
+        col.insert("1", &key("1"));
+        col.insert("2", &key("2"));
+        col.insert("3", &key("3"));
+        col.insert("4", &key("4"));
         assert_eq!(col.get_range(0, 5), &["4", "3", "2", "1", "0"]);
     }
     #[test]
     fn test_default_index_col_dt_reverse() {
-        let mut col = DefaultIndexCollection::<DateTime>::new(false);
-        col.insert("0", &json!("2018-01-01T00:00:00+00:00"));
-        col.insert("1", &json!("2018-01-01T00:00:01+00:00"));
-        col.insert("2", &json!("2018-01-01T00:00:02+00:00"));
-        col.insert("3", &json!("2018-01-01T00:00:03+00:00"));
-        col.insert("4", &json!("2018-01-01T00:00:04+00:00"));
+        let mut col = new_col("-datetime");
+        col.insert("0", &key("2018-01-01T00:00:00+00:00"));
+        col.insert("1", &key("2018-01-01T00:00:01+00:00"));
+        col.insert("2", &key("2018-01-01T00:00:02+00:00"));
+        col.insert("3", &key("2018-01-01T00:00:03+00:00"));
+        col.insert("4", &key("2018-01-01T00:00:04+00:00"));
         assert_eq!(col.get_range(0, 5), &["4", "3", "2", "1", "0"]);
     }
+    #[test]
+    fn test_default_index_col_unparsable_key() {
+        let mut col = new_col("+integer");
+        col.insert("2", &key(2));
+        col.insert("1", &key(1));
+        col.insert("0", &key(0));
+        col.insert("1", &key("one"));
+        assert_eq!(col.len(), 2);
+        assert_eq!(col.get_range(0, 3), &["0", "2"]);
+        assert_eq!(col.rank("1"), None);
+    }
+    #[test]
+    fn test_default_index_col_tie_break() {
+        let mut col = new_col("-integer");
+        col.insert("b", &key(1));
+        col.insert("c", &key(1));
+        col.insert("a", &key(1));
+        col.insert("d", &key(2));
+        assert_eq!(col.get_range(0, 4), &["d", "a", "b", "c"]);
+    }
+    #[test]
+    fn test_default_index_col_compound() {
+        let mut col = new_col("-datetime,+string:title");
+        col.insert("0", &json!({
+            "key": "2018-01-01T00:00:00+00:00",
+            "title": "B",
+        }));
+        col.insert("1", &json!({
+            "key": "2018-01-01T00:00:00+00:00",
+            "title": "A",
+        }));
+        col.insert("2", &json!({ "key": "2018-01-01T00:00:00+00:00" }));
+        col.insert("3", &json!({
+            "key": "2018-01-02T00:00:00+00:00",
+            "title": "C",
+        }));
+        col.insert("4", &json!({ "title": "A" }));
+        assert_eq!(col.get_range(0, 5), &["3", "1", "0", "2"]);
+        assert_eq!(col.rank("0"), Some(2));
+    }
+    #[test]
+    fn test_key_part_parse() {
+        let utc = FixedOffset::east(0);
+        let parts = KeyPart::parse_all("published", "-datetime, string:title",
+            utc).unwrap();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].field, "published");
+        assert!(!parts[0].asc);
+        assert_eq!(parts[1].field, "title");
+        assert!(parts[1].asc);
+        assert!(KeyPart::parse_all("published", "-double", utc).is_none());
+        assert!(KeyPart::parse_all("published", "+string:", utc).is_none());
+    }
+    #[test]
+    fn test_default_index_col_rank() {
+        let mut col = new_col("-integer");
+        col.insert("0", &key(0));
+        col.insert("1", &key(1));
+        col.insert("2", &key(2));
+        assert_eq!(col.rank("2"), Some(0));
+        assert_eq!(col.rank("0"), Some(2));
+        col.insert("0", &key(3));
+        assert_eq!(col.rank("0"), Some(0));
+        assert_eq!(col.rank("2"), Some(1));
+        col.remove("2");
+        assert_eq!(col.rank("2"), None);
+        assert_eq!(col.rank("1"), Some(1));
+    }
+
+    /// Xorshift PRNG, so that failures are reproducible.
+    struct Rng(u64);
+    impl Rng {
+        fn next(&mut self, n: u64) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0 % n
+        }
+    }
+
+    /// Naive index in which each operation scans the entire index.
+    struct Model {
+        index: Vec<(i64, String)>,
+        asc: bool,
+    }
+    impl Model {
+        fn insert(&mut self, path: &str, key: Option<i64>) {
+            self.remove(path);
+            if let Some(key) = key {
+                self.index.push((key, path.to_owned()));
+                let asc = self.asc;
+                // Ties are always broken by path in ascending order.
+                self.index.sort_by(|a, b| if asc {
+                    a.0.cmp(&b.0)
+                } else {
+                    b.0.cmp(&a.0)
+                }.then_with(|| a.1.cmp(&b.1)));
+            }
+        }
+        fn remove(&mut self, path: &str) {
+            self.index.retain(|&(_, ref x)| x != path);
+        }
+        fn paths(&self) -> Vec<String> {
+            self.index.iter().map(|&(_, ref x)| x.to_owned()).collect()
+        }
+    }
+
+    fn check_against_model(asc: bool, seed: u64) {
+        let mut rng = Rng(seed);
+        let mut col = new_col(if asc { "+integer" } else { "-integer" });
+        let mut model = Model { index: Vec::new(), asc: asc };
+        for _ in 0..2000 {
+            // Few distinct keys so that many articles share the same key.
+            let path = rng.next(64).to_string();
+            match rng.next(10) {
+                x if x < 7 => {
+                    let val = rng.next(16) as i64;
+                    col.insert(&path, &key(val));
+                    model.insert(&path, Some(val));
+                },
+                7 => {
+                    col.insert(&path, &key("unparsable"));
+                    model.insert(&path, None);
+                },
+                _ => {
+                    col.remove(&path);
+                    model.remove(&path);
+                },
+            }
+            assert_eq!(col.len(), model.index.len());
+            let skip = rng.next(col.len() as u64 + 1) as usize;
+            let take = rng.next(8) as usize;
+            let expected: Vec<String> = model.paths().into_iter()
+                .skip(skip)
+                .take(take)
+                .collect();
+            assert_eq!(col.get_range(skip, take), expected);
+            let rank = model.index.iter().position(|&(_, ref x)| x == &path);
+            assert_eq!(col.rank(&path), rank);
+        }
+        assert_eq!(col.get_range(0, col.len()), model.paths());
+    }
+    #[test]
+    fn test_default_index_col_against_model() {
+        for seed in 1..9 {
+            check_against_model(true, seed);
+            check_against_model(false, seed);
+        }
+    }
+
+    /// Index collection as it was before `RankedSet`, where entries are kept in
+    /// a sorted `Vec` and articles are found by scanning it.
+    struct VecCollection {
+        index: Vec<(i64, String)>,
+    }
+    impl VecCollection {
+        fn insert(&mut self, path: &str, key: i64) {
+            self.remove(path);
+            let entry = (key, path.to_owned());
+            let pos = match self.index.binary_search(&entry) {
+                Ok(pos) | Err(pos) => pos,
+            };
+            self.index.insert(pos, entry);
+        }
+        fn remove(&mut self, path: &str) {
+            if let Some(pos) = self.rank(path) {
+                self.index.remove(pos);
+            }
+        }
+        fn rank(&self, path: &str) -> Option<usize> {
+            self.index.iter().position(|&(_, ref x)| x == path)
+        }
+        fn get_range(&self, skip: usize, take: usize) -> Vec<String> {
+            self.index.iter()
+                .skip(skip)
+                .take(take)
+                .map(|&(_, ref x)| x.to_owned())
+                .collect()
+        }
+    }
+    /// Run `f`, printing the time it takes as `name`.
+    fn time<T, F: FnOnce() -> T>(name: &str, f: F) -> T {
+        use std::time::Instant;
+        let start = Instant::now();
+        let out = f();
+        let elapsed = start.elapsed();
+        println!("{:>16}: {:>8.3}ms", name,
+            elapsed.as_secs() as f64 * 1e3 +
+            elapsed.subsec_nanos() as f64 / 1e6);
+        out
+    }
+
+    /// Compare the `Vec` collection and the current one with 100k articles,
+    /// where 1k articles are inserted, ranked, listed from and removed. Run
+    /// with `cargo test --release bench_ -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_default_index_col() {
+        const N: u64 = 100_000;
+        const M: u64 = 1_000;
+        let mut rng = Rng(42);
+        let mut col = new_col("+integer");
+        let mut vec = VecCollection { index: Vec::new() };
+        let entries: Vec<(String, i64)> = (0..N)
+            .map(|i| (i.to_string(), rng.next(N) as i64))
+            .collect();
+        time("treap build", || for &(ref path, val) in entries.iter() {
+            col.insert(path, &key(val));
+        });
+        vec.index = entries.iter()
+            .map(|&(ref path, val)| (val, path.to_owned()))
+            .collect();
+        vec.index.sort();
+        let new_entries: Vec<(String, i64)> = (N..N + M)
+            .map(|i| (i.to_string(), rng.next(N) as i64))
+            .collect();
+        time("treap insert", || for &(ref path, val) in new_entries.iter() {
+            col.insert(path, &key(val));
+        });
+        time("vec insert", || for &(ref path, val) in new_entries.iter() {
+            vec.insert(path, val);
+        });
+        let paths: Vec<String> = (0..M)
+            .map(|_| rng.next(N + M).to_string())
+            .collect();
+        let ranks = time("treap rank", || paths.iter()
+            .map(|x| col.rank(x))
+            .collect::<Vec<_>>());
+        let vec_ranks = time("vec rank", || paths.iter()
+            .map(|x| vec.rank(x))
+            .collect::<Vec<_>>());
+        assert_eq!(ranks, vec_ranks);
+        let skips: Vec<usize> = (0..M)
+            .map(|_| rng.next(N) as usize)
+            .collect();
+        let pages = time("treap get_range", || skips.iter()
+            .map(|&x| col.get_range(x, 10))
+            .collect::<Vec<_>>());
+        let vec_pages = time("vec get_range", || skips.iter()
+            .map(|&x| vec.get_range(x, 10))
+            .collect::<Vec<_>>());
+        assert_eq!(pages, vec_pages);
+        time("treap remove", || for path in paths.iter() {
+            col.remove(path);
+        });
+        time("vec remove", || for path in paths.iter() {
+            vec.remove(path);
+        });
+        assert_eq!(col.len(), vec.index.len());
+        assert_eq!(col.get_range(0, col.len()), vec.get_range(0, N as usize));
+    }
 }
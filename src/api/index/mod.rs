@@ -1,74 +1,171 @@
+use std::collections::{BTreeMap, HashSet};
 use std::ops::Deref;
 use std::path::Path;
 use std::sync::{Arc, RwLock};
+use chrono::FixedOffset;
 use serde_json::Value as JsonValue;
 use walkdir::WalkDir;
 
 mod index_map;
-use self::index_map::{DateTime, DumbIndexCollection,
-    DefaultIndexCollection};
-pub use self::index_map::IndexCollection;
+mod ranked_set;
+mod snapshot;
+use self::index_map::{DumbIndexCollection, DefaultIndexCollection};
+pub use self::index_map::{parse_timezone, DateTime, Float, IndexCollection,
+    IndexKeyType, KeyKind, KeyPart, KeyValue, Version};
+pub use self::snapshot::Snapshot;
 
-fn mk_idx(key: &str, mut col: Box<IndexCollection>, dir: Option<&str>)
-    -> Index {
-    Index {
-        index: {
-            if let Some(dir) = dir {
-                make_index(dir, key, &mut *col);
-            }
-            Arc::new(RwLock::new(col))
-        },
+/// Name of the index made from `index_key` and `index_key_type`.
+pub const DEFAULT_INDEX_NAME: &str = "default";
+
+fn mk_idx(key: &str, col: Box<IndexCollection>, parts: Vec<KeyPart>,
+    dir: Option<&str>) -> Index {
+    let index = Index {
+        index: Arc::new(RwLock::new(col)),
         key: key.to_owned(),
+        parts: parts,
+    };
+    if let Some(dir) = dir {
+        info!("Indexing files with key '{}'.", key);
+        walk_posts(dir, |id, meta, _| index.update(id, meta));
     }
+    index
 }
 #[derive(Clone)]
 pub struct Index {
     index: Arc<RwLock<Box<IndexCollection>>>,
     key: String,
+    /// Parts of the index key, empty if the index collection is not a default
+    /// one.
+    parts: Vec<KeyPart>,
 }
 impl Index {
     /// Make a new `Index` with given index collection and index key.
     pub fn with_index_collection<T>(key: &str, col: T, dir: Option<&str>)
         -> Index where T: 'static + IndexCollection {
-        mk_idx(key, Box::new(col), dir)
+        mk_idx(key, Box::new(col), Vec::new(), dir)
     }
     /// Make a new `Index` with given index key and corresponding default index
     /// collection. If `dir` has a value, index will be generated from local
     /// storage, searching for articles in that directory and its subdirectory.
-    pub fn new(key: &str, mut ty: &str, dir: Option<&str>) -> Index {
-        let ascending = if ty.starts_with('+') {
-            // Prefixed by '+', the index order is ascending. Larger value will
-            // be placed at the back.
-            ty = &ty[1..];
-            true
-        } else if ty.starts_with('-') {
-            // Prefixed by '-', the index order is descending, Larger value will
-            // be placed at the front.
-            ty = &ty[1..];
-            false
-        } else {
-            // Prefixed by nothing, the index order is by default ascending.
-            true
-        };
-        let col: Box<IndexCollection> = match ty {
-            "string" => Box::new(
-                DefaultIndexCollection::<String>::new(ascending)
-            ),
-            "integer" => Box::new(
-                DefaultIndexCollection::<i64>::new(ascending)
-            ),
-            "datetime" => Box::new(
-                DefaultIndexCollection::<DateTime>::new(ascending)
-            ),
-            _ => panic!("Index key type should be one of `datetime`, `string`, \
-                or `integer`."),
+    ///
+    /// `ty` can be a compound type like `-datetime,+string:title`, see
+    /// `KeyPart::parse_all` for details.
+    pub fn new(key: &str, ty: &str, dir: Option<&str>) -> Index {
+        Index::with_timezone(key, ty, FixedOffset::east(0), dir)
+    }
+    /// Make a new `Index` like `Index::new`, but dates and datetimes without
+    /// timezone are interpreted in timezone `tz`.
+    pub fn with_timezone(key: &str, ty: &str, tz: FixedOffset,
+        dir: Option<&str>) -> Index {
+        let parts = match KeyPart::parse_all(key, ty, tz) {
+            Some(parts) => parts,
+            None => panic!("Index key type should be a comma-separated list of \
+                `datetime`, `date`, `naive_datetime`, `string`, `integer`, \
+                `float` or `version`, each optionally prefixed by `+` or `-` \
+                and suffixed by `:<metadata key>`."),
         };
-        mk_idx(key, col, dir)
+        let col = DefaultIndexCollection::new(parts.clone());
+        mk_idx(key, Box::new(col), parts, dir)
     }
     /// Get the index key of the current index.
     pub fn index_key(&self) -> &String {
         &self.key
     }
+    /// Get the part of index key by which articles are primarily ordered. It's
+    /// unknown for custom index collections.
+    pub fn primary_key_part(&self) -> Option<&KeyPart> {
+        self.parts.first()
+    }
+    /// Metadata field which must be present for an article to be indexed. It's
+    /// the field of the first key part, which can differ from the index key
+    /// when the type is like `+string:title`.
+    fn primary_field(&self) -> &str {
+        self.parts.first().map(|part| &part.field).unwrap_or(&self.key)
+    }
+    /// Ranks of articles in `hidden` which are indexed in `col`, in ascending
+    /// order. The caller holds the lock, so that the index is not locked twice
+    /// by a thread, which can deadlock with a waiting writer.
+    fn hidden_ranks(col: &IndexCollection, hidden: &HashSet<String>)
+        -> Vec<usize> {
+        let mut ranks: Vec<usize> = hidden.iter()
+            .filter_map(|id| col.rank(id))
+            .collect();
+        ranks.sort();
+        ranks
+    }
+    /// Number of indexed articles, excluding those in `hidden`.
+    pub fn visible_len(&self, hidden: &HashSet<String>) -> usize {
+        let guard = self.read().unwrap();
+        guard.len() - Index::hidden_ranks(&**guard, hidden).len()
+    }
+    /// Get a range of articles as if those in `hidden` were not indexed.
+    pub fn get_visible_range(&self, skip: usize, take: usize,
+        hidden: &HashSet<String>) -> Vec<String> {
+        let guard = self.read().unwrap();
+        let ranks = Index::hidden_ranks(&**guard, hidden);
+        // Map the visible position to the position in the entire index.
+        let mut beg = skip;
+        for &rank in ranks.iter() {
+            if rank <= beg {
+                beg += 1;
+            }
+        }
+        guard.get_range(beg, take + ranks.len())
+            .into_iter()
+            .filter(|id| !hidden.contains(id))
+            .take(take)
+            .collect()
+    }
+    /// Articles right before and after article `id` in the index, skipping
+    /// those in `hidden`. Both are `None` if `id` is not indexed.
+    pub fn neighbors(&self, id: &str, hidden: &HashSet<String>)
+        -> (Option<String>, Option<String>) {
+        let guard = self.read().unwrap();
+        let rank = match guard.rank(id) {
+            Some(rank) => rank,
+            None => return (None, None),
+        };
+        // There must be a visible article in the windows if there is any.
+        let window = hidden.len() + 1;
+        let beg = rank.saturating_sub(window);
+        let prev = guard.get_range(beg, rank - beg).into_iter()
+            .rev()
+            .find(|x| !hidden.contains(x));
+        let next = guard.get_range(rank + 1, window).into_iter()
+            .find(|x| !hidden.contains(x));
+        (prev, next)
+    }
+    /// Update the entry of article `id` with its (new) metadata. The article
+    /// is removed from the index if it's marked `noIndex` or the field of the
+    /// primary key part is missing.
+    pub fn update(&self, id: &str, meta: &JsonValue) {
+        let mut guard = self.write().unwrap();
+        if !is_indexable(meta) {
+            guard.remove(id);
+        } else if meta.get(self.primary_field()).is_some() {
+            guard.insert(id, meta);
+        } else {
+            guard.remove(id);
+        }
+    }
+    /// Metadata fields of the entry of an article in the index, from which the
+    /// entry can be restored with `update`. `Null` if the article is not
+    /// indexed.
+    pub fn dump(&self, meta: &JsonValue) -> JsonValue {
+        if !is_indexable(meta) || meta.get(self.primary_field()).is_none() {
+            return JsonValue::Null
+        }
+        let fields = Some(&self.key).into_iter()
+            .chain(self.parts.iter().map(|part| &part.field))
+            .filter_map(|field| meta.get(field)
+                .map(|val| (field.to_owned(), val.to_owned())))
+            .collect::<::serde_json::Map<String, JsonValue>>();
+        if fields.is_empty() {
+            JsonValue::Null
+        } else {
+            JsonValue::Object(fields)
+        }
+    }
 }
 impl Default for Index {
     /// Make a `Index` that do literally nothing.
@@ -76,6 +173,7 @@ impl Default for Index {
         Index {
             index: Arc::new(RwLock::new(Box::new(DumbIndexCollection::new()))),
             key: String::new(),
+            parts: Vec::new(),
         }
     }
 }
@@ -85,10 +183,112 @@ impl Deref for Index {
         &*self.index
     }
 }
-fn make_index(dir: &str, key: &str, index: &mut IndexCollection) {
-    info!("Indexing files with key '{}'.", key);
+
+/// A set of named indices, each of which has its own key and order. One of
+/// them is the default index used when no index is specified in requests.
+#[derive(Clone)]
+pub struct Indices {
+    default: String,
+    indices: BTreeMap<String, Index>,
+}
+impl Indices {
+    /// Make a new set of indices with `index` as the default one.
+    pub fn new(default: &str, index: Index) -> Indices {
+        let mut indices = BTreeMap::new();
+        indices.insert(default.to_owned(), index);
+        Indices {
+            default: default.to_owned(),
+            indices: indices,
+        }
+    }
+    pub fn insert(&mut self, name: &str, index: Index) {
+        self.indices.insert(name.to_owned(), index);
+    }
+    /// Get the index of name `name`, or the default index if `name` is `None`.
+    pub fn get(&self, name: Option<&str>) -> Option<&Index> {
+        self.indices.get(name.unwrap_or(&self.default[..]))
+    }
+    pub fn default_index(&self) -> &Index {
+        &self.indices[&self.default]
+    }
+    pub fn default_name(&self) -> &str {
+        &self.default
+    }
+    pub fn iter(&self) -> ::std::collections::btree_map::Iter<String, Index> {
+        self.indices.iter()
+    }
+    /// Update article `id` in all the indices.
+    pub fn update(&self, id: &str, meta: &JsonValue) {
+        for index in self.indices.values() {
+            index.update(id, meta);
+        }
+    }
+    /// Entries of an article of metadata `meta` in all the indices, see
+    /// `Index::dump`.
+    pub fn dump(&self, meta: &JsonValue) -> BTreeMap<String, JsonValue> {
+        self.indices.iter()
+            .map(|(name, index)| (name.to_owned(), index.dump(meta)))
+            .collect()
+    }
+    /// Restore entries of article `id` dumped by `dump`.
+    pub fn restore(&self, id: &str, entries: &BTreeMap<String, JsonValue>) {
+        for (name, index) in self.indices.iter() {
+            match entries.get(name) {
+                Some(fields @ &JsonValue::Object(_)) =>
+                    index.update(id, fields),
+                _ => index.write().unwrap().remove(id),
+            }
+        }
+    }
+    /// Remove article `id` from all the indices.
+    pub fn remove(&self, id: &str) {
+        for index in self.indices.values() {
+            index.write().unwrap().remove(id);
+        }
+    }
+}
+impl Default for Indices {
+    fn default() -> Indices {
+        Indices::from(Index::default())
+    }
+}
+impl From<Index> for Indices {
+    fn from(index: Index) -> Indices {
+        Indices::new(DEFAULT_INDEX_NAME, index)
+    }
+}
+
+/// Check if an article should be shown in indices, according to its metadata.
+pub fn is_indexable(meta: &JsonValue) -> bool {
+    // If field `noIndex` presents and is set true, ignore the article.
+    if let Some(&JsonValue::Bool(true)) = meta.get("noIndex") {
+        false
+    } else if meta.get("redirect").is_some() {
+        // Redirects left by moved articles are not articles.
+        false
+    } else {
+        // Drafts are never indexed.
+        !is_draft(meta)
+    }
+}
+/// Check if an article is a draft, which is only available to authorized
+/// requests, according to its metadata.
+pub fn is_draft(meta: &JsonValue) -> bool {
+    if let Some(&JsonValue::Bool(true)) = meta.get("draft") {
+        true
+    } else {
+        false
+    }
+}
+
+/// Walk through `dir` for articles and call `f` with the ID and directory of
+/// each of them. Hidden files and directories, like revision history, are
+/// skipped.
+fn walk_post_dirs<F>(dir: &str, mut f: F) where F: FnMut(&str, &Path) {
     for entry in WalkDir::new(&dir)
         .into_iter()
+        .filter_entry(|x| x.depth() == 0 ||
+            !x.file_name().to_string_lossy().starts_with('.'))
         .filter_map(|x| x.ok()) {
         // Seek for `content.md`.
         if !entry.file_type().is_file() ||
@@ -96,15 +296,10 @@ fn make_index(dir: &str, key: &str, index: &mut IndexCollection) {
             continue
         }
         if let Some(parent) = entry.path().parent() {
-            info!("Indexing article '{}'...", &parent.to_string_lossy());
-            if let Some(val) = get_index_val_for(parent, key) {
-                let path = parent.strip_prefix(&dir).unwrap()
-                    .to_string_lossy()
-                    .to_string();
-                index.insert(&path, &val);
-            } else {
-                warn!("Article is not indexed.");
-            }
+            let id = parent.strip_prefix(&dir).unwrap()
+                .to_string_lossy()
+                .to_string();
+            f(&id, parent);
         } else {
             error!("Unexpected error accessing parent of: {}",
                 &entry.path().to_string_lossy());
@@ -112,7 +307,51 @@ fn make_index(dir: &str, key: &str, index: &mut IndexCollection) {
     }
 }
 
-fn get_index_val_for(parent: &Path, key: &str) -> Option<JsonValue> {
+/// IDs of all the articles in `dir`.
+pub fn list_posts(dir: &str) -> Vec<String> {
+    let mut ids = Vec::new();
+    walk_post_dirs(dir, |id, _| ids.push(id.to_owned()));
+    ids
+}
+
+/// Walk through `dir` for articles and call `f` with the ID, metadata and
+/// content of each of them.
+pub fn walk_posts<F>(dir: &str, mut f: F)
+    where F: FnMut(&str, &JsonValue, &str) {
+    walk_post_dirs(dir, |id, parent| {
+        info!("Indexing article '{}'...", &parent.to_string_lossy());
+        if let Some(meta) = get_metadata_for(parent) {
+            let content = get_content_for(parent);
+            f(id, &meta, &content);
+        } else {
+            warn!("Article is not indexed.");
+        }
+    });
+}
+
+/// Read metadata and content of article `id` in `dir`. `None` is returned if
+/// it's not an article or its metadata is unavailable.
+pub fn read_post(dir: &str, id: &str) -> Option<(JsonValue, String)> {
+    let parent = Path::new(dir).join(id.trim_left_matches('/'));
+    if !parent.join("content.md").is_file() {
+        return None
+    }
+    let meta = get_metadata_for(&parent)?;
+    Some((meta, get_content_for(&parent)))
+}
+
+fn get_content_for(parent: &Path) -> String {
+    use std::fs::File;
+    use std::io::Read;
+    let mut content = String::new();
+    if let Err(err) = File::open(path_buf![parent, "content.md"])
+        .and_then(|mut file| file.read_to_string(&mut content)) {
+        warn!("Unable to read content from '{}': {}",
+            parent.to_string_lossy(), err);
+    }
+    content
+}
+fn get_metadata_for(parent: &Path) -> Option<JsonValue> {
     use std::fs::File;
     use std::io::Read;
     // Find `metadata.json`.
@@ -125,14 +364,55 @@ fn get_index_val_for(parent: &Path, key: &str) -> Option<JsonValue> {
         .map_err(|err| error!("Unable to read metadata from '{}': {}",
             parent.to_string_lossy(), err))
         .ok()?;
-    let json = ::serde_json::from_slice::<JsonValue>(&text)
+    ::serde_json::from_slice::<JsonValue>(&text)
         .map_err(|err| warn!("Unable to serialize content of '{}': {}",
             parent.to_string_lossy(), err))
-        .ok()?;
-    // If field `noIndex` presents and is set true, ignore the article.
-    if let Some(&JsonValue::Bool(true)) = json.get("noIndex") {
-        return None
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use super::Index;
+
+    #[test]
+    fn test_neighbors() {
+        let index = Index::new("key", "+integer", None);
+        for i in 0..5 {
+            index.update(&i.to_string(), &json!({ "key": i }));
+        }
+        let mut hidden = HashSet::new();
+        assert_eq!(index.neighbors("2", &hidden),
+            (Some("1".to_owned()), Some("3".to_owned())));
+        assert_eq!(index.neighbors("0", &hidden),
+            (None, Some("1".to_owned())));
+        hidden.insert("1".to_owned());
+        hidden.insert("3".to_owned());
+        assert_eq!(index.neighbors("2", &hidden),
+            (Some("0".to_owned()), Some("4".to_owned())));
+        assert_eq!(index.neighbors("5", &hidden), (None, None));
+    }
+    #[test]
+    fn test_visible_range() {
+        let index = Index::new("key", "-integer", None);
+        for i in 0..5 {
+            index.update(&i.to_string(), &json!({ "key": i }));
+        }
+        let mut hidden = HashSet::new();
+        hidden.insert("4".to_owned());
+        hidden.insert("2".to_owned());
+        assert_eq!(index.visible_len(&hidden), 3);
+        assert_eq!(index.get_visible_range(0, 2, &hidden), &["3", "1"]);
+        assert_eq!(index.get_visible_range(1, 2, &hidden), &["1", "0"]);
+    }
+    #[test]
+    fn test_update_by_part_field() {
+        let index = Index::new("key", "+string:title", None);
+        index.update("foo", &json!({ "title": "Foo" }));
+        index.update("bar", &json!({ "key": "Bar" }));
+        assert_eq!(index.get_visible_range(0, 10, &HashSet::new()), &["foo"]);
+        assert_eq!(index.dump(&json!({ "title": "Foo" })),
+            json!({ "title": "Foo" }));
+        assert_eq!(index.dump(&json!({ "key": "Bar" })), json!(null));
     }
-    json.get(key)
-        .map(|x| x.to_owned())
 }
@@ -0,0 +1,215 @@
+///! Ordered set supporting rank and range queries in logarithmic time.
+///! It's a treap in which each node keeps the size of its subtree, so that the
+///! position of a value can be found without visiting the values before it.
+use std::cmp::Ordering;
+
+type Link<T> = Option<Box<Node<T>>>;
+
+struct Node<T> {
+    val: T,
+    /// Nodes of higher priorities are placed closer to the root.
+    priority: u64,
+    /// Number of nodes in the subtree rooted at this node.
+    size: usize,
+    left: Link<T>,
+    right: Link<T>,
+}
+impl<T> Node<T> {
+    fn update(&mut self) {
+        self.size = 1 + size(&self.left) + size(&self.right);
+    }
+}
+
+fn size<T>(link: &Link<T>) -> usize {
+    link.as_ref().map(|node| node.size).unwrap_or(0)
+}
+/// Split `link` into nodes for which `is_left` is true and the rest. `is_left`
+/// must be true for a prefix of the values in order.
+fn split<T, F>(link: Link<T>, is_left: &F) -> (Link<T>, Link<T>)
+    where F: Fn(&T) -> bool {
+    match link {
+        None => (None, None),
+        Some(mut node) => if is_left(&node.val) {
+            let (mid, right) = split(node.right.take(), is_left);
+            node.right = mid;
+            node.update();
+            (Some(node), right)
+        } else {
+            let (left, mid) = split(node.left.take(), is_left);
+            node.left = mid;
+            node.update();
+            (left, Some(node))
+        },
+    }
+}
+/// Merge `left` and `right`, where all the values in `left` are less than
+/// those in `right`.
+fn merge<T>(left: Link<T>, right: Link<T>) -> Link<T> {
+    match (left, right) {
+        (None, right) => right,
+        (left, None) => left,
+        (Some(mut left), Some(mut right)) => if left.priority > right.priority {
+            left.right = merge(left.right.take(), Some(right));
+            left.update();
+            Some(left)
+        } else {
+            right.left = merge(Some(left), right.left.take());
+            right.update();
+            Some(right)
+        },
+    }
+}
+/// Push at most `take` values after the first `skip` ones in `link` to `out`.
+fn collect<'a, T>(link: &'a Link<T>, mut skip: usize, take: usize,
+    out: &mut Vec<&'a T>) {
+    let node = match *link {
+        Some(ref node) => node,
+        None => return,
+    };
+    if out.len() >= take {
+        return
+    }
+    let left_size = size(&node.left);
+    if skip < left_size {
+        collect(&node.left, skip, take, out);
+        skip = 0;
+    } else {
+        skip -= left_size;
+    }
+    if out.len() >= take {
+        return
+    }
+    if skip == 0 {
+        out.push(&node.val);
+    } else {
+        skip -= 1;
+    }
+    collect(&node.right, skip, take, out);
+}
+
+pub struct RankedSet<T: Ord> {
+    root: Link<T>,
+    /// State of the xorshift generator of node priorities. Priorities only
+    /// need to be evenly distributed, so they are reproducible.
+    seed: u64,
+}
+impl<T: Ord> RankedSet<T> {
+    pub fn new() -> RankedSet<T> {
+        RankedSet {
+            root: None,
+            seed: 0x2545_F491_4F6C_DD1D,
+        }
+    }
+    fn next_priority(&mut self) -> u64 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+        self.seed
+    }
+    pub fn len(&self) -> usize {
+        size(&self.root)
+    }
+    /// Insert `val`. Returns false if it's already in the set.
+    pub fn insert(&mut self, val: T) -> bool {
+        if self.rank(&val).is_some() {
+            return false
+        }
+        let node = Box::new(Node {
+            val: val,
+            priority: self.next_priority(),
+            size: 1,
+            left: None,
+            right: None,
+        });
+        let (left, right) = split(self.root.take(), &|x: &T| x < &node.val);
+        self.root = merge(merge(left, Some(node)), right);
+        true
+    }
+    /// Remove `val`. Returns false if it's not in the set.
+    pub fn remove(&mut self, val: &T) -> bool {
+        let (left, rest) = split(self.root.take(), &|x: &T| x < val);
+        let (mid, right) = split(rest, &|x: &T| x <= val);
+        self.root = merge(left, right);
+        mid.is_some()
+    }
+    /// Number of values less than `val`, or `None` if `val` is not in the set.
+    pub fn rank(&self, val: &T) -> Option<usize> {
+        let mut link = &self.root;
+        let mut rank = 0;
+        while let Some(ref node) = *link {
+            match val.cmp(&node.val) {
+                Ordering::Less => link = &node.left,
+                Ordering::Equal => return Some(rank + size(&node.left)),
+                Ordering::Greater => {
+                    rank += size(&node.left) + 1;
+                    link = &node.right;
+                },
+            }
+        }
+        None
+    }
+    /// At most `take` values in order, after the first `skip` ones.
+    pub fn range(&self, skip: usize, take: usize) -> Vec<&T> {
+        let mut out = Vec::with_capacity(take.min(self.len()));
+        collect(&self.root, skip, take, &mut out);
+        out
+    }
+}
+impl<T: Ord> Default for RankedSet<T> {
+    fn default() -> RankedSet<T> {
+        RankedSet::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+    use super::{Link, RankedSet};
+
+    fn height<T>(link: &Link<T>) -> usize {
+        match *link {
+            Some(ref node) => 1 + height(&node.left).max(height(&node.right)),
+            None => 0,
+        }
+    }
+
+    #[test]
+    fn test_against_btree_set() {
+        let mut set = RankedSet::new();
+        let mut model = BTreeSet::new();
+        let mut seed = 7u64;
+        for _ in 0..5000 {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            let val = seed % 256;
+            if seed % 3 == 0 {
+                assert_eq!(set.remove(&val), model.remove(&val));
+            } else {
+                assert_eq!(set.insert(val), model.insert(val));
+            }
+            assert_eq!(set.len(), model.len());
+            assert_eq!(set.rank(&val), model.iter().position(|x| x == &val));
+            let skip = (seed % 64) as usize;
+            let expected: Vec<&u64> = model.iter().skip(skip).take(10)
+                .collect();
+            assert_eq!(set.range(skip, 10), expected);
+        }
+    }
+    #[test]
+    fn test_balance() {
+        // Values inserted in order would make an unbalanced binary tree a
+        // list.
+        let mut set = RankedSet::new();
+        for i in 0..100_000 {
+            set.insert(i);
+        }
+        assert!(height(&set.root) < 64);
+        assert_eq!(set.rank(&99_999), Some(99_999));
+        assert_eq!(set.range(50_000, 2), vec![&50_000, &50_001]);
+        for i in 0..100_000 {
+            set.remove(&i);
+        }
+        assert_eq!(set.len(), 0);
+    }
+}
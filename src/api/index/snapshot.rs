@@ -0,0 +1,292 @@
+///! Snapshot of the index state derived from articles, persisted between runs
+///! so that only changed articles need to be read when the indices are
+///! rebuilt.
+use std::collections::BTreeMap;
+use std::fs::{create_dir_all, read_dir, rename, File};
+use std::io::{BufReader, BufWriter, Result as IoResult};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+use serde_json::Value as JsonValue;
+use api::observer::PostObserver;
+use super::{get_content_for, get_metadata_for, walk_post_dirs, Indices};
+
+/// Bumped whenever the format of snapshots changes, so that stale snapshots
+/// are discarded.
+const SNAPSHOT_VERSION: u32 = 3;
+
+#[derive(Deserialize, Serialize)]
+struct SnapshotEntry {
+    /// Modification times of `metadata*.json` and `content*.md`, translations
+    /// included, in nanoseconds since UNIX epoch by file name.
+    mtimes: BTreeMap<String, u64>,
+    /// Entry of the article in each index, see `Indices::dump`. `None` if the
+    /// article was not indexed because its metadata cannot be read, so that
+    /// it's not read again until it's changed.
+    indices: Option<BTreeMap<String, JsonValue>>,
+    /// Data derived from the article by each observer, in the order the
+    /// observers are given, see `PostObserver::dump`.
+    observers: Vec<JsonValue>,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+pub struct Snapshot {
+    version: u32,
+    /// Description of the indices and observers the snapshot was taken with.
+    signature: String,
+    posts: BTreeMap<String, SnapshotEntry>,
+    /// The snapshot differs from the one saved.
+    #[serde(skip)]
+    is_stale: bool,
+}
+impl Snapshot {
+    /// Make an empty snapshot of indices and observers described by
+    /// `signature`.
+    pub fn new(signature: &str) -> Snapshot {
+        Snapshot {
+            version: SNAPSHOT_VERSION,
+            signature: signature.to_owned(),
+            posts: BTreeMap::new(),
+            is_stale: true,
+        }
+    }
+    /// Load snapshot from `path`. An empty snapshot is returned if the file
+    /// cannot be read, or it was taken with indices and observers other than
+    /// those described by `signature`, so that all the articles will be read
+    /// from local storage.
+    pub fn load(path: &Path, signature: &str) -> Snapshot {
+        let snapshot = File::open(path)
+            .map_err(|err| err.to_string())
+            .and_then(|file| {
+                ::serde_json::from_reader::<_, Snapshot>(BufReader::new(file))
+                    .map_err(|err| err.to_string())
+            });
+        match snapshot {
+            Ok(ref snapshot) if snapshot.version != SNAPSHOT_VERSION ||
+                snapshot.signature != signature => {
+                info!("Index snapshot is outdated, all articles will be \
+                    reindexed.");
+                Snapshot::new(signature)
+            },
+            Ok(snapshot) => snapshot,
+            Err(err) => {
+                info!("Unable to load index snapshot from '{}', all articles \
+                    will be reindexed: {}", path.to_string_lossy(), err);
+                Snapshot::new(signature)
+            },
+        }
+    }
+    /// Check if the snapshot has been changed since it was loaded, so it should
+    /// be saved again.
+    pub fn is_stale(&self) -> bool {
+        self.is_stale
+    }
+    /// Save the snapshot to `path`. The snapshot is written to a temporary
+    /// file first, so a broken snapshot will never replace a valid one.
+    pub fn save(&self, path: &Path) -> IoResult<()> {
+        use std::io::{Error, ErrorKind};
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)?;
+        }
+        let tmp_path = path.with_extension("tmp");
+        {
+            let writer = BufWriter::new(File::create(&tmp_path)?);
+            ::serde_json::to_writer(writer, self)
+                .map_err(|err| Error::new(ErrorKind::Other, err))?;
+        }
+        rename(&tmp_path, path)
+    }
+    /// Walk through `dir` for articles, and update `indices` and `observers`
+    /// with each of them. Articles unchanged since the snapshot was taken are
+    /// restored from the snapshot without being read from local storage. The
+    /// snapshot is updated to reflect the current state of `dir`.
+    pub fn walk(&mut self, dir: &str, indices: &Indices,
+        observers: &[Arc<PostObserver>]) {
+        let mut old_posts = ::std::mem::replace(&mut self.posts,
+            BTreeMap::new());
+        let mut reused = 0;
+        let mut is_stale = false;
+        walk_post_dirs(dir, |id, parent| {
+            let mtimes = mtimes(parent);
+            let old_entry = old_posts.remove(id);
+            let is_unchanged = old_entry.as_ref()
+                .map(|entry| entry.mtimes == mtimes &&
+                    (entry.indices.is_none() ||
+                        entry.observers.len() == observers.len()))
+                .unwrap_or(false);
+            if is_unchanged {
+                let entry = old_entry.unwrap();
+                if let Some(ref entry_indices) = entry.indices {
+                    indices.restore(id, entry_indices);
+                    for (observer, state) in observers.iter()
+                        .zip(entry.observers.iter()) {
+                        observer.restore(id, state);
+                    }
+                    reused += 1;
+                }
+                self.posts.insert(id.to_owned(), entry);
+                return
+            }
+            is_stale = true;
+            info!("Indexing article '{}'...", &parent.to_string_lossy());
+            let metadata = match get_metadata_for(parent) {
+                Some(meta) => meta,
+                None => {
+                    warn!("Article is not indexed.");
+                    self.posts.insert(id.to_owned(), SnapshotEntry {
+                        mtimes: mtimes,
+                        indices: None,
+                        observers: Vec::new(),
+                    });
+                    return
+                },
+            };
+            let content = get_content_for(parent);
+            indices.update(id, &metadata);
+            for observer in observers.iter() {
+                observer.update_metadata(id, &metadata);
+                observer.update_content(id, &content);
+            }
+            self.posts.insert(id.to_owned(), SnapshotEntry {
+                mtimes: mtimes,
+                indices: Some(indices.dump(&metadata)),
+                observers: observers.iter()
+                    .map(|observer| observer.dump(id))
+                    .collect(),
+            });
+        });
+        // Articles removed since the snapshot was taken.
+        if is_stale || !old_posts.is_empty() {
+            self.is_stale = true;
+        }
+        info!("{} articles are restored from index snapshot.", reused);
+    }
+}
+
+/// Modification times of the files an article is made of in directory
+/// `parent`, i.e., `metadata*.json` and `content*.md`.
+fn mtimes(parent: &Path) -> BTreeMap<String, u64> {
+    let entries = match read_dir(parent) {
+        Ok(entries) => entries,
+        Err(_) => return BTreeMap::new(),
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let is_tracked =
+                (name.starts_with("metadata") && name.ends_with(".json")) ||
+                (name.starts_with("content") && name.ends_with(".md"));
+            if !is_tracked {
+                return None
+            }
+            mtime(&entry.path()).map(|mtime| (name, mtime))
+        })
+        .collect()
+}
+
+fn mtime(path: &Path) -> Option<u64> {
+    let duration = path.metadata()
+        .and_then(|meta| meta.modified())
+        .ok()?
+        .duration_since(UNIX_EPOCH)
+        .ok()?;
+    Some(duration.as_secs() * 1_000_000_000 + duration.subsec_nanos() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::fs::{create_dir_all, remove_dir_all, File};
+    use std::io::Write;
+    use std::sync::Arc;
+    use api::{Index, Indices, Taxonomy, SearchIndex};
+    use api::observer::PostObserver;
+    use api::search::Query;
+    use super::Snapshot;
+
+    fn build() -> (Indices, Taxonomy, SearchIndex, Vec<Arc<PostObserver>>) {
+        let indices = Indices::from(Index::new("key", "+integer", None));
+        let taxonomy = Taxonomy::new("tags");
+        let search = SearchIndex::new(&["tags".to_owned()]);
+        let observers: Vec<Arc<PostObserver>> = vec![
+            Arc::new(taxonomy.clone()),
+            Arc::new(search.clone()),
+        ];
+        (indices, taxonomy, search, observers)
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let dir = ::std::env::temp_dir()
+            .join(format!("writus-snapshot-{}", ::std::process::id()));
+        let published = dir.join("published");
+        let path = dir.join("snapshot.json");
+        for &(id, key) in [("foo", 1), ("bar", 0)].iter() {
+            create_dir_all(published.join(id)).unwrap();
+            File::create(published.join(id).join("content.md")).unwrap()
+                .write_all(format!("# {}\n\nHello, Writus!", id).as_bytes())
+                .unwrap();
+            File::create(published.join(id).join("metadata.json")).unwrap()
+                .write_all(format!(r#"{{"key":{},"tags":["rust"]}}"#, key)
+                    .as_bytes())
+                .unwrap();
+        }
+        let published = published.to_string_lossy().into_owned();
+
+        let (indices, _, _, observers) = build();
+        let mut snapshot = Snapshot::load(&path, "sig");
+        snapshot.walk(&published, &indices, &observers);
+        assert!(snapshot.is_stale());
+        snapshot.save(&path).unwrap();
+
+        // Unchanged articles are restored from the snapshot.
+        let (indices, taxonomy, search, observers) = build();
+        let mut snapshot = Snapshot::load(&path, "sig");
+        snapshot.walk(&published, &indices, &observers);
+        assert!(!snapshot.is_stale());
+        assert_eq!(indices.default_index().read().unwrap().get_range(0, 5),
+            vec!["bar", "foo"]);
+        assert_eq!(taxonomy.terms(&HashSet::new()).get("rust"), Some(&2));
+        assert_eq!(search.search(&Query::parse("writus")).len(), 2);
+
+        // Changed translations are read again.
+        File::create(dir.join("published/foo/content.zh.md")).unwrap()
+            .write_all("# 你好".as_bytes()).unwrap();
+        let (indices, _, _, observers) = build();
+        let mut snapshot = Snapshot::load(&path, "sig");
+        snapshot.walk(&published, &indices, &observers);
+        assert!(snapshot.is_stale());
+        snapshot.save(&path).unwrap();
+
+        // Articles whose metadata cannot be read are remembered, not read
+        // again on every run.
+        create_dir_all(dir.join("published/baz")).unwrap();
+        File::create(dir.join("published/baz/content.md")).unwrap();
+        File::create(dir.join("published/baz/metadata.json")).unwrap()
+            .write_all(b"{").unwrap();
+        let (indices, _, _, observers) = build();
+        let mut snapshot = Snapshot::load(&path, "sig");
+        snapshot.walk(&published, &indices, &observers);
+        assert!(snapshot.is_stale());
+        snapshot.save(&path).unwrap();
+        let (indices, _, _, observers) = build();
+        let mut snapshot = Snapshot::load(&path, "sig");
+        snapshot.walk(&published, &indices, &observers);
+        assert!(!snapshot.is_stale());
+        assert_eq!(indices.default_index().read().unwrap().len(), 2);
+
+        // Removed articles make the snapshot stale.
+        remove_dir_all(dir.join("published/foo")).unwrap();
+        let (indices, _, _, observers) = build();
+        let mut snapshot = Snapshot::load(&path, "sig");
+        snapshot.walk(&published, &indices, &observers);
+        assert!(snapshot.is_stale());
+        assert_eq!(indices.default_index().read().unwrap().len(), 1);
+
+        // Snapshots taken with other settings are discarded.
+        let snapshot = Snapshot::load(&path, "other");
+        assert!(snapshot.posts.is_empty());
+        remove_dir_all(&dir).unwrap();
+    }
+}
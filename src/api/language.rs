@@ -0,0 +1,169 @@
+///! Languages of articles.
+///! Articles are written in the default language in `content.md` and
+///! `metadata.json`, and can be translated into other languages in
+///! `content.<lang>.md` and `metadata.<lang>.json`. Metadata of a translation
+///! overrides that of the default language key by key.
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use hyper::header::{Header, Raw, Vary};
+use serde_json::Value as JsonValue;
+use writium::prelude::*;
+use writium_cache::Cache;
+use self::header::{q, AcceptLanguage};
+use model::History;
+
+#[derive(Clone)]
+pub struct Translation {
+    pub post_cache: Arc<Cache<String>>,
+    pub metadata_cache: Arc<Cache<JsonValue>>,
+    /// History of translated content.
+    pub history: Arc<History>,
+}
+
+#[derive(Clone, Default)]
+pub struct Translations {
+    default: String,
+    translations: BTreeMap<String, Translation>,
+}
+impl Translations {
+    /// Make a new set of translations, where articles are written in language
+    /// `default` by default.
+    pub fn new(default: &str) -> Translations {
+        Translations {
+            default: default.to_owned(),
+            translations: BTreeMap::new(),
+        }
+    }
+    /// Add language `lang` whose translations are kept in `post_cache` and
+    /// `metadata_cache`.
+    pub fn insert(&mut self, lang: &str, post_cache: Arc<Cache<String>>,
+        metadata_cache: Arc<Cache<JsonValue>>) {
+        self.translations.insert(lang.to_owned(), Translation {
+            post_cache: post_cache,
+            metadata_cache: metadata_cache,
+            history: Arc::new(History::default()),
+        });
+    }
+    /// Keep the history of content translated into `lang` in `history`.
+    /// Nothing is done if `lang` is not added.
+    pub fn set_history(&mut self, lang: &str, history: Arc<History>) {
+        if let Some(translation) = self.translations.get_mut(lang) {
+            translation.history = history;
+        }
+    }
+    pub fn default_language(&self) -> &str {
+        &self.default
+    }
+    /// Get the caches of language `lang`, which is not the default language.
+    pub fn get(&self, lang: &str) -> Option<&Translation> {
+        self.translations.get(lang)
+    }
+    pub fn iter(&self)
+        -> ::std::collections::btree_map::Iter<String, Translation> {
+        self.translations.iter()
+    }
+
+    /// Find the configured language matching any of `requested`, preferring
+    /// the earlier ones. A requested language matches a configured one if they
+    /// are the same, or their primary subtags are the same, like `zh-CN` and
+    /// `zh`.
+    fn pick<'a, I>(&self, requested: I) -> Option<String>
+        where I: IntoIterator<Item=&'a str> {
+        let langs: Vec<&str> = Some(&self.default[..]).into_iter()
+            .filter(|x| !x.is_empty())
+            .chain(self.translations.keys().map(|x| &x[..]))
+            .collect();
+        let primary = |x: &str| x.split('-').next().unwrap_or_default()
+            .to_lowercase();
+        for req in requested {
+            let exact = langs.iter()
+                .find(|x| x.eq_ignore_ascii_case(req));
+            let similar = || langs.iter()
+                .find(|x| primary(*x) == primary(req));
+            if let Some(lang) = exact.or_else(similar) {
+                return Some((*lang).to_owned())
+            }
+        }
+        None
+    }
+    /// Select the language of request `req`, by `lang` given in the request,
+    /// or header `Accept-Language`. The default language is selected if none
+    /// of them matches.
+    pub fn negotiate(&self, req: &Request, lang: Option<&str>) -> String {
+        if let Some(lang) = lang.and_then(|x| self.pick(Some(x))) {
+            return lang
+        }
+        if let Some(accept) = req.header::<AcceptLanguage>() {
+            let mut items: Vec<_> = accept.iter()
+                .filter(|x| x.quality > q(0u16))
+                .collect();
+            // Sorting is stable so languages of the same quality are kept in
+            // order.
+            items.sort_by(|a, b| b.quality.cmp(&a.quality));
+            let tags: Vec<String> = items.iter()
+                .map(|x| x.item.to_string())
+                .collect();
+            if let Some(lang) = self.pick(tags.iter().map(|x| &x[..])) {
+                return lang
+            }
+        }
+        self.default.clone()
+    }
+    /// Content of article `id` translated into `lang`, if there is one.
+    pub fn content(&self, lang: &str, id: &str) -> Option<String> {
+        let cache = self.translations.get(lang)?.post_cache.get(id).ok()?;
+        let text = cache.read().unwrap().clone();
+        Some(text)
+    }
+    /// Metadata of article `id` translated into `lang`, which is `meta` in the
+    /// default language overridden by translated metadata.
+    pub fn metadata(&self, lang: &str, id: &str, meta: &JsonValue)
+        -> JsonValue {
+        let mut meta = meta.clone();
+        let translated = self.translations.get(lang)
+            .and_then(|x| x.metadata_cache.get(id).ok());
+        if let Some(translated) = translated {
+            let guard = translated.read().unwrap();
+            if let (Some(obj), Some(translated)) =
+                (meta.as_object_mut(), guard.as_object()) {
+                for (key, val) in translated.iter() {
+                    obj.insert(key.to_owned(), val.to_owned());
+                }
+            }
+        }
+        meta
+    }
+    /// Languages article `id` is translated into, excluding the default one.
+    pub fn translated(&self, id: &str) -> Vec<String> {
+        self.translations.iter()
+            .filter(|&(_, x)| x.post_cache.get(id).is_ok())
+            .map(|(lang, _)| lang.to_owned())
+            .collect()
+    }
+}
+
+/// Header `Vary: Accept-Language` of responses whose language is negotiated,
+/// so that caches don't serve them to readers of other languages.
+pub fn vary_language() -> Vary {
+    Vary::parse_header(&Raw::from("Accept-Language")).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use writium_cache::{Cache, DumbCacheSource};
+    use super::Translations;
+
+    #[test]
+    fn test_pick() {
+        let mut translations = Translations::new("en");
+        translations.insert("zh-CN",
+            Arc::new(Cache::new(0, DumbCacheSource::new())),
+            Arc::new(Cache::new(0, DumbCacheSource::new())));
+        assert_eq!(translations.pick(vec!["zh-cn"]), Some("zh-CN".to_owned()));
+        assert_eq!(translations.pick(vec!["zh-TW"]), Some("zh-CN".to_owned()));
+        assert_eq!(translations.pick(vec!["fr", "en-US"]),
+            Some("en".to_owned()));
+        assert_eq!(translations.pick(vec!["fr"]), None);
+    }
+}
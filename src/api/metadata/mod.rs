@@ -4,25 +4,37 @@ use writium::prelude::*;
 use self::header::ContentType;
 use writium_auth::{Authority, DumbAuthority};
 use writium_cache::{Cache, DumbCacheSource};
-use super::Index;
+use super::{Index, Indices, PostObserver};
+use super::etag::{check_match, etag_of_json, respond, respond_json};
+use super::index::is_draft;
+use super::post::author_of;
+use super::schedule::Schedule;
+use model::History;
 
 #[cfg(test)]
 mod tests;
 
 const ERR_MISSING_CONTENT_TYPE: &'static str = "Content type should be denoted for verification use.";
 const ERR_JSON: &'static str = "Invalid JSON.";
+const ERR_NOT_PUBLISHED: &'static str = "The article is not published yet.";
 
 pub struct MetadataApi {
     cache: Arc<Cache<JsonValue>>,
     auth: Arc<Authority<Privilege=()>>,
-    index: Index,
+    indices: Indices,
+    observers: Vec<Arc<PostObserver>>,
+    history: Arc<History>,
+    schedule: Schedule,
 }
 impl MetadataApi {
     pub fn new() -> MetadataApi {
         MetadataApi {
             cache: Arc::new(Cache::new(0, DumbCacheSource::new())),
             auth: Arc::new(DumbAuthority::new()),
-            index: Index::default(),
+            indices: Indices::default(),
+            observers: Vec::new(),
+            history: Arc::new(History::default()),
+            schedule: Schedule::default(),
         }
     }
     pub fn set_cache(&mut self, cache: Arc<Cache<JsonValue>>) {
@@ -32,7 +44,42 @@ impl MetadataApi {
         self.auth = auth;
     }
     pub fn set_index(&mut self, index: Index) {
-        self.index = index;
+        self.indices = Indices::from(index);
+    }
+    pub fn set_indices(&mut self, indices: Indices) {
+        self.indices = indices;
+    }
+    /// Add an observer to be notified when metadata is changed.
+    pub fn add_observer(&mut self, observer: Arc<PostObserver>) {
+        self.observers.push(observer);
+    }
+    /// Set the history in which revisions of metadata are kept.
+    pub fn set_history(&mut self, history: Arc<History>) {
+        self.history = history;
+    }
+    /// Set the schedule of articles to be published in the future. Metadata of
+    /// these articles is only available to authorized requests.
+    pub fn set_schedule(&mut self, schedule: Schedule) {
+        self.schedule = schedule;
+    }
+
+    fn notify_update(&self, id: &str, meta: &JsonValue) {
+        self.indices.update(id, meta);
+        for observer in self.observers.iter() {
+            observer.update_metadata(id, meta);
+        }
+    }
+    /// Keep the change of metadata in history. The metadata should not be
+    /// changed if it fails.
+    fn record_change(&self, req: &Request, id: &str, old: &JsonValue,
+        new: &JsonValue) -> Result<()> {
+        record_change(&self.history, req, id, old, new)
+    }
+    fn notify_remove(&self, id: &str) {
+        self.indices.remove(id);
+        for observer in self.observers.iter() {
+            observer.remove(id);
+        }
     }
 
     fn parse_json(&self, req: &mut Request) -> Result<JsonValue> {
@@ -68,47 +115,63 @@ impl MetadataApi {
         let param = req.to_param::<Param>()?;
         if let Some(keys) = param.keys {
             let cache = self.cache.get(&id)?;
-            // If the index key is removed, remove it from index.
-            if keys.contains(self.index.index_key()) {
-                self.index.write().unwrap().remove(&id);
-            // If `noIndex` is removed and the key is not removed, add it to
-            // index.
-            } else if keys.iter().any(|x| x == "noIndex") {
-                let guard = cache.read().unwrap();
-                // The original value is true, so it's indexing is suppressed
-                // before,
-                if let Some(&JsonValue::Bool(true)) = guard.get("noIndex") {
-                    // There is a index key in metadata.
-                    if let Some(key) = guard.get(self.index.index_key()) {
-                        self.index.write().unwrap().insert(&id, key);
-                    }
-                }
-            }
             let mut guard = cache.write().unwrap();
-            let obj_ref = guard.as_object_mut().unwrap();
-            for key in keys {
-                obj_ref.remove(&key);
+            check_match(req, Some(&etag_of_json(&*guard)))?;
+            let mut json = guard.clone();
+            {
+                let obj_ref = json.as_object_mut().unwrap();
+                for key in keys {
+                    obj_ref.remove(&key);
+                }
             }
+            self.record_change(req, &id, &guard, &json)?;
+            *guard = json;
+            // Index keys or `noIndex` might have been removed. Let the indices
+            // decide.
+            self.notify_update(&id, &guard);
         } else {
+            let etag = self.cache.get(&id)
+                .map(|cache| etag_of_json(&*cache.read().unwrap()))
+                .ok();
+            check_match(req, etag.as_ref())?;
             // All metadata are removed, remove it from index.
-            self.index.write().unwrap().remove(&id);
+            self.notify_remove(&id);
             self.cache.remove(&id)?
         }
         Ok(Response::new())
     }
 
     /// GET `metadata/<path..>?key=<key>`;
+    /// GET `metadata/<path..>?revisions`;
+    /// GET `metadata/<path..>?revision=<revision>`;
     /// GET `metadata/<path..>`;
     fn get(&self, req: &mut Request) -> ApiResult {
         let id = req.path_segs().join("/");
         #[derive(Deserialize)]
         struct Param {
             pub keys: Option<Vec<String>>,
+            /// List revisions instead of the metadata.
+            pub revisions: Option<String>,
+            /// Get a revision of the metadata.
+            pub revision: Option<usize>,
         }
         let cache = self.cache.get(&id)?;
         let param = req.to_param::<Param>()?;
 
         let guard = cache.read().unwrap();
+        if (is_draft(&guard) || self.schedule.is_hidden(&id)) &&
+            self.auth.authorize((), &req).is_err() {
+            return Err(Error::not_found(ERR_NOT_PUBLISHED))
+        }
+        let res = Response::new()
+            .with_header(ContentType("application/json; charset=UTF-8".parse().unwrap()));
+        if param.revisions.is_some() {
+            return respond_json(req, res, &self.history.revisions(&id)?)
+        }
+        if let Some(rev) = param.revision {
+            let text = self.history.get(&id, rev)?;
+            return Ok(respond(req, res, text.into_bytes()))
+        }
         let json = if param.keys.is_none() {
             guard.clone()
         } else {
@@ -120,29 +183,29 @@ impl MetadataApi {
                 .map(|(x, y)| (x.clone(), y.clone())));
             JsonValue::Object(rv)
         };
-        Response::new()
-            .with_header(ContentType("application/json; charset=UTF-8".parse().unwrap()))
-            .with_json(&json)
+        respond_json(req, res, &json)
     }
 
     /// PUT `metadata/<path..>`
     fn put(&self, req: &mut Request) -> ApiResult {
         let id = req.path_segs().join("/");
+        let exists = self.cache.get(&id).is_ok();
         let cache = self.cache.create(&id)?;
         let json = self.parse_json(req)?;
         let mut guard = cache.write().unwrap();
-        // If `noIndex` flag is set `true`, remove the article from index and
-        // stop from index updating. If it's set to `false` and there is a valid
-        // index key stored in metadata, the article should be indexed.
-        if let Some(&JsonValue::Bool(true)) = json.get("noIndex") {
-            self.index.write().unwrap().remove(&id);
-        // Update the index.
-        } else if let Some(ref key) = json.get(self.index.index_key()) {
-            self.index.write().unwrap().insert(&id, key);
-        }
+        // Changes made by others since the client fetched the metadata are not
+        // overwritten.
+        let etag = if exists { Some(etag_of_json(&*guard)) } else { None };
+        check_match(req, etag.as_ref())?;
+        self.record_change(req, &id, &guard, &json)?;
+        // If `noIndex` flag is set `true`, the article is removed from indices.
+        // Otherwise, the article is indexed by every index whose key is found
+        // in the new metadata.
+        self.notify_update(&id, &json);
         // Replace metadata with the new one.
+        let etag = etag_of_json(&json);
         *guard = json;
-        Ok(Response::new())
+        Ok(Response::new().with_header(header::ETag(etag)))
     }
     /// PATCH `metadata/<path..>`
     fn patch(&self, req: &mut Request) -> ApiResult {
@@ -150,23 +213,36 @@ impl MetadataApi {
         let cache = self.cache.get(&id)?;
         let json = self.parse_json(req)?;
         let mut guard = cache.write().unwrap();
-        let obj = guard.as_object_mut().unwrap();
-        for item in json.as_object().unwrap() {
-            obj.insert(item.0.to_string(), item.1.clone());
+        check_match(req, Some(&etag_of_json(&*guard)))?;
+        let mut patched = guard.clone();
+        {
+            let obj = patched.as_object_mut().unwrap();
+            for item in json.as_object().unwrap() {
+                obj.insert(item.0.to_string(), item.1.clone());
+            }
         }
-        
-        if let Some(&JsonValue::Bool(true)) = json.get("noIndex") {
-            self.index.write().unwrap().remove(&id);
-        // If index key has been changed, update the index.
-        } else if let Some(ref key) = json.get(self.index.index_key()) {
-            self.index.write().unwrap().insert(&id, key);
-        // No index key update, check if there is already a index key in the
-        // unchanged portion of metadata.
-        } else if let Some(ref key) = obj.get(self.index.index_key()) {
-            self.index.write().unwrap().insert(&id, key);
+        self.record_change(req, &id, &guard, &patched)?;
+        *guard = patched;
+        // Index keys might come from either the patch or the unchanged portion
+        // of metadata, so the merged metadata is used.
+        self.notify_update(&id, &guard);
+        Ok(Response::new().with_header(header::ETag(etag_of_json(&*guard))))
+    }
+}
+/// Keep the change of metadata of article `id` from `old` to `new` on request
+/// `req` in `history`.
+pub fn record_change(history: &History, req: &Request, id: &str,
+    old: &JsonValue, new: &JsonValue) -> Result<()> {
+    fn to_text(json: &JsonValue) -> String {
+        match json.as_object() {
+            // Newly created metadata.
+            Some(obj) if obj.is_empty() => String::new(),
+            _ => ::serde_json::to_string_pretty(json).unwrap_or_default(),
         }
-        Ok(Response::new())
     }
+    let author = author_of(req);
+    history.record_change(id, &to_text(old), &to_text(new),
+        author.as_ref().map(|x| &x[..]))
 }
 impl Api for MetadataApi {
     fn name(&self) -> &[& str] {
@@ -48,6 +48,44 @@ fn test_get_some() {
     check_type(&res, "application", "json");
     check_content(&res, FOO_META);
 }
+#[test]
+fn test_get_draft() {
+    let api = api();
+    let req = Request::new(Method::Put)
+        .with_path_segs(&["bar"])
+        .with_header(Authorization(Bearer { token: "PASSWORD".to_owned() }))
+        .with_json(&json!({ "draft": true }))
+        .unwrap();
+    let _ = test_ok(&api, req);
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["bar"]);
+    let err = test_err(&api, req);
+    assert_eq!(err.status(), StatusCode::NotFound);
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["bar"])
+        .with_header(Authorization(Bearer { token: "PASSWORD".to_owned() }));
+    let res = test_ok(&api, req);
+    check_content(&res, r#"{"draft":true}"#);
+}
+#[test]
+fn test_get_scheduled() {
+    use api::observer::PostObserver;
+    use api::schedule::Schedule;
+    let mut api = api();
+    let schedule = Schedule::new("published");
+    schedule.update_metadata("foo",
+        &json!({ "published": "2999-01-01T00:00:00+08:00" }));
+    api.set_schedule(schedule);
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["foo"]);
+    let err = test_err(&api, req);
+    assert_eq!(err.status(), StatusCode::NotFound);
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["foo"])
+        .with_header(Authorization(Bearer { token: "PASSWORD".to_owned() }));
+    let res = test_ok(&api, req);
+    check_content(&res, FOO_META_ALL);
+}
 
 #[test]
 fn test_put() {
@@ -184,3 +222,55 @@ fn test_index_removal() {
     let _ = test_ok(&api, req);
     assert_eq!(index.read().unwrap().get_range(0, 2), vec!["bar", "baz"]);
 }
+
+#[test]
+fn test_get_not_modified() {
+    use self::header::{ETag, IfNoneMatch};
+    let api = api();
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["foo"]);
+    let etag = test_ok(&api, req).header::<ETag>().unwrap().0.clone();
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["foo"])
+        .with_header(IfNoneMatch::Items(vec![etag]));
+    let res = test_ok(&api, req);
+    assert_eq!(res.status(), StatusCode::NotModified);
+}
+#[test]
+fn test_put_if_match() {
+    use self::header::{ETag, IfMatch};
+    let api = api();
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["foo"]);
+    let etag = test_ok(&api, req).header::<ETag>().unwrap().0.clone();
+    let req = Request::new(Method::Put)
+        .with_path_segs(&["foo"])
+        .with_header(Authorization(Bearer { token: "PASSWORD".to_owned() }))
+        .with_header(IfMatch::Items(vec![etag.clone()]))
+        .with_json(&gen_null_neko())
+        .unwrap();
+    let res = test_ok(&api, req);
+    // The new tag is the one of the new metadata.
+    let new_etag = res.header::<ETag>().unwrap().0.clone();
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["foo"]);
+    let res = test_ok(&api, req);
+    assert_eq!(res.header::<ETag>().unwrap().0, new_etag);
+    // The metadata has been changed since the old tag was given.
+    let req = Request::new(Method::Put)
+        .with_path_segs(&["foo"])
+        .with_header(Authorization(Bearer { token: "PASSWORD".to_owned() }))
+        .with_header(IfMatch::Items(vec![etag.clone()]))
+        .with_json(&gen_null_neko())
+        .unwrap();
+    let err = test_err(&api, req);
+    assert_eq!(err.status(), StatusCode::PreconditionFailed);
+    let req = Request::new(Method::Patch)
+        .with_path_segs(&["foo"])
+        .with_header(Authorization(Bearer { token: "PASSWORD".to_owned() }))
+        .with_header(IfMatch::Items(vec![etag]))
+        .with_json(&gen_null_neko())
+        .unwrap();
+    let err = test_err(&api, req);
+    assert_eq!(err.status(), StatusCode::PreconditionFailed);
+}
@@ -1,18 +1,38 @@
-
 pub mod index;
+pub mod observer;
 
-pub use self::index::Index;
+pub use self::index::{Index, Indices};
+pub use self::observer::PostObserver;
 
 #[cfg(test)]
-mod test_common;
+pub mod test_common;
 
+pub mod archive;
 pub mod comment;
+pub mod draft;
+pub mod etag;
+pub mod filter;
+pub mod language;
 pub mod metadata;
 pub mod post;
+pub mod related;
 pub mod resource;
+pub mod schedule;
+pub mod search;
+pub mod taxonomy;
+pub mod trash;
 
 // Reexport APIs.
+pub use self::archive::{Archive, ArchiveApi};
 pub use self::comment::CommentApi;
+pub use self::draft::{DraftApi, Drafts};
+pub use self::filter::FilterIndex;
+pub use self::language::Translations;
 pub use self::metadata::MetadataApi;
 pub use self::post::PostApi;
+pub use self::related::Related;
 pub use self::resource::ResourceApi;
+pub use self::schedule::Schedule;
+pub use self::search::{SearchApi, SearchIndex};
+pub use self::taxonomy::{Taxonomy, TaxonomyApi};
+pub use self::trash::TrashApi;
@@ -0,0 +1,20 @@
+use serde_json::Value as JsonValue;
+
+/// Data derived from articles, which should be kept updated when articles are
+/// changed.
+pub trait PostObserver: Send + Sync {
+    /// Metadata of article `id` has been changed.
+    fn update_metadata(&self, _id: &str, _meta: &JsonValue) {}
+    /// Content of article `id` has been changed.
+    fn update_content(&self, _id: &str, _content: &str) {}
+    /// Article `id` has been removed.
+    fn remove(&self, _id: &str) {}
+    /// Data derived from article `id`, kept in the index snapshot so that it
+    /// can be restored without reading the article again. `Null` if nothing is
+    /// derived from the article.
+    fn dump(&self, _id: &str) -> JsonValue {
+        JsonValue::Null
+    }
+    /// Restore data of article `id` dumped by `dump`.
+    fn restore(&self, _id: &str, _state: &JsonValue) {}
+}
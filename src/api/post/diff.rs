@@ -0,0 +1,149 @@
+///! Line-based unified diff between revisions of articles.
+use std::cmp::max;
+
+/// Number of unchanged lines shown around changes.
+const CONTEXT: usize = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// Shortest edit script turning `a` into `b`, found by the longest common
+/// subsequence of lines.
+fn diff_lines<'a>(a: &[&'a str], b: &[&'a str]) -> Vec<(Op, &'a str)> {
+    // Common prefix and suffix are kept out of the quadratic table.
+    let prefix = a.iter().zip(b.iter())
+        .take_while(|&(x, y)| x == y)
+        .count();
+    let suffix = a[prefix..].iter().rev().zip(b[prefix..].iter().rev())
+        .take_while(|&(x, y)| x == y)
+        .count();
+    let (a_mid, b_mid) = (&a[prefix..a.len() - suffix],
+        &b[prefix..b.len() - suffix]);
+    let (n, m) = (a_mid.len(), b_mid.len());
+    // `lcs[i][j]` is the length of LCS of `a_mid[i..]` and `b_mid[j..]`.
+    let mut lcs = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if a_mid[i] == b_mid[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                max(lcs[i + 1][j], lcs[i][j + 1])
+            };
+        }
+    }
+    let mut ops: Vec<(Op, &str)> = a[..prefix].iter()
+        .map(|&x| (Op::Equal, x))
+        .collect();
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && a_mid[i] == b_mid[j] {
+            ops.push((Op::Equal, a_mid[i]));
+            i += 1;
+            j += 1;
+        } else if i < n && (j == m || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            // Deletions are put before insertions.
+            ops.push((Op::Delete, a_mid[i]));
+            i += 1;
+        } else {
+            ops.push((Op::Insert, b_mid[j]));
+            j += 1;
+        }
+    }
+    ops.extend(a[a.len() - suffix..].iter().map(|&x| (Op::Equal, x)));
+    ops
+}
+
+/// Make a unified diff from `old` to `new`, labeled `old_name` and `new_name`.
+/// An empty string is returned if there is no difference.
+pub fn unified_diff(old: &str, new: &str, old_name: &str, new_name: &str)
+    -> String {
+    let a: Vec<&str> = old.lines().collect();
+    let b: Vec<&str> = new.lines().collect();
+    let ops = diff_lines(&a, &b);
+    // Numbers of old and new lines before each operation.
+    let mut pos = Vec::with_capacity(ops.len() + 1);
+    let (mut old_pos, mut new_pos) = (0, 0);
+    for &(op, _) in ops.iter() {
+        pos.push((old_pos, new_pos));
+        if op != Op::Insert { old_pos += 1; }
+        if op != Op::Delete { new_pos += 1; }
+    }
+    pos.push((old_pos, new_pos));
+
+    let mut out = String::new();
+    let is_change = |&(op, _): &(Op, &str)| op != Op::Equal;
+    let mut i = 0;
+    while let Some(offset) = ops[i..].iter().position(&is_change) {
+        let beg = (i + offset).saturating_sub(CONTEXT);
+        // Changes separated by no more than twice the context are put in the
+        // same hunk.
+        let mut end = i + offset;
+        loop {
+            while end < ops.len() && ops[end].0 != Op::Equal {
+                end += 1;
+            }
+            let next = ops[end..].iter()
+                .position(&is_change)
+                .map(|x| end + x);
+            match next {
+                Some(next) if next - end <= 2 * CONTEXT => end = next,
+                _ => break,
+            }
+        }
+        let end = (end + CONTEXT).min(ops.len());
+        if out.is_empty() {
+            out.push_str(&format!("--- {}\n+++ {}\n", old_name, new_name));
+        }
+        let (old_beg, new_beg) = pos[beg];
+        let (old_len, new_len) = (pos[end].0 - old_beg, pos[end].1 - new_beg);
+        // Empty ranges start at the line before them.
+        out.push_str(&format!("@@ -{},{} +{},{} @@\n",
+            if old_len == 0 { old_beg } else { old_beg + 1 }, old_len,
+            if new_len == 0 { new_beg } else { new_beg + 1 }, new_len));
+        for &(op, line) in ops[beg..end].iter() {
+            out.push(match op {
+                Op::Equal => ' ',
+                Op::Delete => '-',
+                Op::Insert => '+',
+            });
+            out.push_str(line);
+            out.push('\n');
+        }
+        i = end;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::unified_diff;
+
+    #[test]
+    fn test_diff_same() {
+        assert_eq!(unified_diff("a\nb", "a\nb", "a", "b"), "");
+    }
+    #[test]
+    fn test_diff_hunk() {
+        let old = "1\n2\n3\n4\n5\n6\n7\n8";
+        let new = "1\n2\n3\n4\nfive\n6\n7\n8\n9";
+        assert_eq!(unified_diff(old, new, "old", "new"), "--- old\n+++ new\n\
+            @@ -2,7 +2,8 @@\n 2\n 3\n 4\n-5\n+five\n 6\n 7\n 8\n+9\n");
+    }
+    #[test]
+    fn test_diff_hunks() {
+        let old = (1..21).map(|x| x.to_string()).collect::<Vec<_>>().join("\n");
+        let new = old.replace("\n2\n", "\n").replace("19", "nineteen");
+        assert_eq!(unified_diff(&old, &new, "old", "new"), "--- old\n+++ new\n\
+            @@ -1,5 +1,4 @@\n 1\n-2\n 3\n 4\n 5\n\
+            @@ -16,5 +15,5 @@\n 16\n 17\n 18\n-19\n+nineteen\n 20\n");
+    }
+    #[test]
+    fn test_diff_empty() {
+        assert_eq!(unified_diff("", "a", "old", "new"),
+            "--- old\n+++ new\n@@ -0,0 +1,1 @@\n+a\n");
+    }
+}
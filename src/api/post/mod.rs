@@ -1,22 +1,185 @@
-use std::sync::Arc;
-use hyper::header::ContentType;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, Mutex, RwLock};
+use chrono::{FixedOffset, Utc};
+use hyper::header::{qitem, ContentLanguage, ContentType, LanguageTag};
+use serde_json::Value as JsonValue;
+use writium::hyper::mime::Mime;
 use writium::prelude::*;
 use writium_auth::{Authority, DumbAuthority};
 use writium_cache::{Cache, DumbCacheSource};
-use super::index::Index;
+use super::etag::{check_match, etag_of, etag_of_json, respond, respond_json};
+use super::filter::{Filter, FilterIndex};
+use super::index::{is_draft, Index, Indices};
+use super::language::{vary_language, Translations};
+use super::metadata::record_change;
+use super::observer::PostObserver;
+use super::related::Related;
+use super::schedule::Schedule;
+use model::{has_history, is_post_file, list_resources, move_history,
+    remove_history, History, Trash};
+use model::comment::Comments;
+use view::listing::{get_digest, md_to_html};
+use self::diff::unified_diff;
+pub use self::permalink::Permalink;
 
-const ERR_MIME: &'static str = "Only data of type 'text/markdown' is accepted.";
+const ERR_CONTENT_TYPE: &'static str = "Content type not given.";
+const ERR_MIME: &'static str = "Only data of type 'text/markdown' or \
+    'multipart/form-data' is accepted.";
+const ERR_NEW_MIME: &'static str = "Only data of type 'text/markdown' or \
+    'application/json' is accepted.";
+const ERR_NEW_POST: &'static str = "New articles in JSON should be given as \
+    `{\"content\": <markdown>, \"metadata\": <object>}`.";
+const ERR_INDEX: &'static str = "No index matches the requested name.";
+const ERR_LIMIT: &'static str = "At least one article should be requested.";
+const ERR_NOT_PUBLISHED: &'static str = "The article is not published yet.";
+const ERR_EXPAND: &'static str = "Only `metadata`, `digest` and `stats` can be \
+    expanded.";
+const ERR_DIFF: &'static str = "Revisions to be compared should be given as \
+    `<revision>[,<revision>]`, where the current content is compared if the \
+    second one is omitted.";
+const ERR_BUNDLE: &'static str = "Bundle is malformed.";
+const ERR_BUNDLE_PART: &'static str = "Each part of a bundle should be named \
+    by a path in the article which is not hidden, and only `content.md`, \
+    `metadata.json`, their translations and resources can be uploaded.";
+const ERR_BUNDLE_CONTENT: &'static str = "Bundle should contain exactly one \
+    `content.md` in UTF-8.";
+const ERR_BUNDLE_METADATA: &'static str = "`metadata.json` in bundle should be \
+    a JSON object.";
+const ERR_BUNDLE_TRANSLATION: &'static str = "Translations in bundle should \
+    come with `content.md`, and be in UTF-8 or JSON objects.";
+const ERR_LANG: &'static str = "Articles are not translated into the requested \
+    language.";
+const ERR_MIME_NOT_FOUND: &'static str = "No corresponding MIME matches the \
+    inquired file type (extension). Maybe the file type is intentionally \
+    prevented from being transferred.";
+const ERR_MIME_EXT_MISMATCH: &'static str = "Path extension doesn't accord \
+    with content type denoted.";
+const ERR_POST_ACTION: &'static str = "Either a revision to be restored or a \
+    path to move to should be given.";
+const ERR_MOVE_TO: &'static str = "Articles can only be moved to a different \
+    path, which is not hidden and not inside the article itself.";
+const ERR_NOT_FOUND: &'static str = "No such article.";
+const ERR_MOVE_CONFLICT: &'static str = "An article already exists at the \
+    destination.";
 
 const DEFAULT_ENTRIES_PER_REQUEST: u64 = 5;
 
+mod diff;
+mod multipart;
+mod permalink;
 #[cfg(test)]
 mod tests;
 
+/// Extra information to be included in each listed article.
+#[derive(Default)]
+struct Expand {
+    metadata: bool,
+    digest: bool,
+    stats: bool,
+}
+impl Expand {
+    /// Parse a comma-separated list like `metadata,digest`.
+    fn parse(text: &str) -> Result<Expand> {
+        let mut expand = Expand::default();
+        for field in text.split(',').map(|x| x.trim()).filter(|x| !x.is_empty()) {
+            match field {
+                "metadata" => expand.metadata = true,
+                "digest" => expand.digest = true,
+                "stats" => expand.stats = true,
+                _ => return Err(Error::bad_request(ERR_EXPAND)),
+            }
+        }
+        Ok(expand)
+    }
+}
+
+#[derive(Serialize)]
+struct Digest {
+    title: String,
+    /// The first paragraph rendered in HTML.
+    content: String,
+}
+#[derive(Serialize)]
+struct Stats {
+    words: usize,
+    characters: usize,
+}
+#[derive(Serialize)]
+struct PostEntry {
+    id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metadata: Option<JsonValue>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    digest: Option<Digest>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stats: Option<Stats>,
+}
+/// Everything removed with an article.
+#[derive(Serialize)]
+struct Removal {
+    id: String,
+    content: bool,
+    metadata: bool,
+    comments: bool,
+    /// IDs of resources.
+    resources: Vec<String>,
+    /// Whether revision history is removed.
+    revisions: bool,
+    /// Languages of which translated content or metadata is removed.
+    translations: Vec<String>,
+}
+/// Files of an article uploaded together.
+#[derive(Default)]
+struct Bundle {
+    content: Option<String>,
+    metadata: Option<JsonValue>,
+    /// Resources and their paths relative to the article.
+    resources: Vec<(String, Vec<u8>)>,
+    /// Translated content and metadata by language.
+    translated_contents: Vec<(String, String)>,
+    translated_metadata: Vec<(String, JsonValue)>,
+}
+#[derive(Serialize)]
+struct PostListing {
+    /// Number of all the listed articles.
+    total: usize,
+    from: usize,
+    /// `from` of the next page, if there is one.
+    next: Option<usize>,
+    items: Vec<PostEntry>,
+}
+
 pub struct PostApi {
     auth: Arc<Authority<Privilege=()>>,
     cache: Arc<Cache<String>>,
-    index: Index,
+    metadata_cache: Arc<Cache<JsonValue>>,
+    comment_cache: Arc<Cache<Comments>>,
+    resource_cache: Arc<Cache<Vec<u8>>>,
+    published_dir: String,
+    indices: Indices,
+    /// Metadata fields listed articles are filtered by.
+    filter_index: FilterIndex,
+    observers: Vec<Arc<PostObserver>>,
+    schedule: Schedule,
+    related: Related,
+    history: Arc<History>,
+    metadata_history: Arc<History>,
+    /// Metadata key set to the time an article is created.
+    created_key: Option<String>,
+    /// Metadata key set to the time an article is last modified.
+    modified_key: Option<String>,
+    timezone: FixedOffset,
+    /// Pattern of paths of articles created without a path.
+    permalink: Permalink,
+    /// Extensions of resources allowed in bundles.
+    allowed_exts: HashMap<String, Mime>,
+    /// Trash revisions of removed articles are moved to.
+    trash: Trash,
+    translations: Translations,
     entries_per_request: u64,
+    /// Paths of new articles are picked and taken one at a time, so that no
+    /// two articles are created at the same path.
+    creating: Mutex<()>,
 }
 
 impl PostApi {
@@ -24,13 +187,47 @@ impl PostApi {
         PostApi {
             auth: Arc::new(DumbAuthority::new()),
             cache: Arc::new(Cache::new(0, DumbCacheSource::new())),
-            index: Index::default(),
+            metadata_cache: Arc::new(Cache::new(0, DumbCacheSource::new())),
+            comment_cache: Arc::new(Cache::new(0, DumbCacheSource::new())),
+            resource_cache: Arc::new(Cache::new(0, DumbCacheSource::new())),
+            published_dir: String::new(),
+            indices: Indices::default(),
+            filter_index: FilterIndex::default(),
+            observers: Vec::new(),
+            schedule: Schedule::default(),
+            related: Related::default(),
+            history: Arc::new(History::default()),
+            metadata_history: Arc::new(History::default()),
+            created_key: None,
+            modified_key: None,
+            timezone: FixedOffset::east(0),
+            permalink: Permalink::default(),
+            allowed_exts: HashMap::new(),
+            trash: Trash::default(),
+            translations: Translations::default(),
             entries_per_request: DEFAULT_ENTRIES_PER_REQUEST,
+            creating: Mutex::new(()),
         }
     }
     pub fn set_cache(&mut self, cache: Arc<Cache<String>>) {
         self.cache = cache;
     }
+    pub fn set_metadata_cache(&mut self, cache: Arc<Cache<JsonValue>>) {
+        self.metadata_cache = cache;
+    }
+    /// Set the comment cache, in which comments are moved with articles.
+    pub fn set_comment_cache(&mut self, cache: Arc<Cache<Comments>>) {
+        self.comment_cache = cache;
+    }
+    /// Set the resource cache, in which resources are moved with articles.
+    pub fn set_resource_cache(&mut self, cache: Arc<Cache<Vec<u8>>>) {
+        self.resource_cache = cache;
+    }
+    /// Set the directory in which resources and revisions of moved articles
+    /// are looked up.
+    pub fn set_published_dir(&mut self, published_dir: &str) {
+        self.published_dir = published_dir.to_owned();
+    }
     pub fn set_auth(&mut self, auth: Arc<Authority<Privilege=()>>) {
         self.auth = auth;
     }
@@ -38,37 +235,311 @@ impl PostApi {
         self.entries_per_request = entries_per_request;
     }
     pub fn set_index(&mut self, index: Index) {
-        self.index = index;
+        self.indices = Indices::from(index);
+    }
+    pub fn set_indices(&mut self, indices: Indices) {
+        self.indices = indices;
+    }
+    /// Set the metadata fields listed articles are filtered by. It should
+    /// also be added as an observer to be kept updated.
+    pub fn set_filter_index(&mut self, filter_index: FilterIndex) {
+        self.filter_index = filter_index;
+    }
+    /// Add an observer to be notified when content is changed.
+    pub fn add_observer(&mut self, observer: Arc<PostObserver>) {
+        self.observers.push(observer);
+    }
+    /// Set the schedule of articles to be published in the future. These
+    /// articles are only available to authorized requests.
+    pub fn set_schedule(&mut self, schedule: Schedule) {
+        self.schedule = schedule;
+    }
+    pub fn set_related(&mut self, related: Related) {
+        self.related = related;
+    }
+    /// Set the history in which revisions of content are kept.
+    pub fn set_history(&mut self, history: Arc<History>) {
+        self.history = history;
+    }
+    /// Set the history in which revisions of metadata are kept, when they're
+    /// changed with content.
+    pub fn set_metadata_history(&mut self, history: Arc<History>) {
+        self.metadata_history = history;
+    }
+
+    /// Set the metadata key set to the time an article is first written, in
+    /// RFC 3339 format. Keys already set are not overwritten.
+    pub fn set_created_key(&mut self, key: &str) {
+        self.created_key = Some(key.to_owned());
+    }
+    /// Set the metadata key set to the time an article is written each time,
+    /// in RFC 3339 format.
+    pub fn set_modified_key(&mut self, key: &str) {
+        self.modified_key = Some(key.to_owned());
+    }
+    /// Set the timezone of timestamps set in metadata.
+    pub fn set_timezone(&mut self, timezone: FixedOffset) {
+        self.timezone = timezone;
+    }
+    /// Set the pattern of paths of articles created by `POST /v1/posts`.
+    pub fn set_permalink(&mut self, permalink: Permalink) {
+        self.permalink = permalink;
+    }
+    /// Set the extensions and MIME of resources allowed in bundles.
+    pub fn set_allowed_exts(&mut self, allowed_exts: HashMap<String, Mime>) {
+        self.allowed_exts = allowed_exts;
+    }
+    /// Set the trash revisions of removed articles are moved to. Other files
+    /// are moved to trash by cache sources.
+    pub fn set_trash(&mut self, trash: Trash) {
+        self.trash = trash;
+    }
+    /// Set the languages articles are translated into, which are moved and
+    /// removed with articles.
+    pub fn set_translations(&mut self, translations: Translations) {
+        self.translations = translations;
+    }
+
+    /// Articles not published yet and hidden from the request.
+    fn hidden(&self, req: &Request) -> HashSet<String> {
+        self.schedule.hidden_from(req, &*self.auth)
     }
 
     fn get_content(&self, req: &mut Request) -> ApiResult {
+        #[derive(Deserialize)]
+        struct Param {
+            /// List related articles instead of the content.
+            related: Option<String>,
+            /// List revisions instead of the content.
+            revisions: Option<String>,
+            /// Get the content of a revision.
+            revision: Option<usize>,
+            /// Compare two revisions, see `ERR_DIFF`.
+            diff: Option<String>,
+            /// Language of the content.
+            lang: Option<String>,
+        }
+        let param = req.to_param::<Param>()?;
         let id = req.path_segs().join("/");
-        let cache = self.cache.get(&id)?;
-        let text = cache.read().unwrap();
-        let text_ref: &[u8] = text.as_ref();
+        if let Some(new_id) = self.redirect_of(&id) {
+            return Ok(Response::new()
+                .with_status(StatusCode::MovedPermanently)
+                .with_header(header::Location::new(
+                    format!("/api/v1/posts/{}", new_id))))
+        }
+        let is_draft = self.metadata_cache.get(&id)
+            .map(|meta| is_draft(&meta.read().unwrap()))
+            .unwrap_or(false);
+        if (is_draft || self.schedule.is_hidden(&id)) &&
+            self.auth.authorize((), &req).is_err() {
+            return Err(Error::not_found(ERR_NOT_PUBLISHED))
+        }
+        if param.related.is_some() {
+            let hidden = self.hidden(req);
+            let related: Vec<String> = self.related.related(&id)
+                .into_iter()
+                .filter(|x| !hidden.contains(x))
+                .collect();
+            let res = Response::new()
+                .with_header(ContentType(
+                    "application/json; charset=UTF-8".parse().unwrap()));
+            return respond_json(req, res, &related)
+        }
+        if param.revisions.is_some() {
+            let res = Response::new()
+                .with_header(ContentType(
+                    "application/json; charset=UTF-8".parse().unwrap()));
+            return respond_json(req, res, &self.history.revisions(&id)?)
+        }
+        if let Some(ref diff) = param.diff {
+            return self.get_diff(req, &id, diff)
+        }
+        // Only the content in the default language is kept in history.
+        let (text, lang) = match param.revision {
+            Some(rev) => (self.history.get(&id, rev)?, None),
+            None => self.get_translated(req, &id,
+                param.lang.as_ref().map(|x| &x[..]))?,
+        };
+        let mut res = Response::new()
+            .with_header(ContentType(
+                "text/markdown; charset=UTF-8".parse().unwrap()));
+        if param.revision.is_none() {
+            res = res.with_header(vary_language());
+        }
+        if let Some(tag) = lang.and_then(|x| x.parse::<LanguageTag>().ok()) {
+            res = res.with_header(ContentLanguage(vec![qitem(tag)]));
+        }
+        Ok(respond(req, res, text.into_bytes()))
+    }
+    /// Content of article `id` in the language selected by `lang` or header
+    /// `Accept-Language`, and the language. Content in the default language is
+    /// given if the article is not translated into the selected one.
+    fn get_translated(&self, req: &Request, id: &str, lang: Option<&str>)
+        -> Result<(String, Option<String>)> {
+        let lang = self.translations.negotiate(req, lang);
+        let text = match self.translations.content(&lang, id) {
+            Some(text) => return Ok((text, Some(lang))),
+            None => self.cache.get(id)?.read().unwrap().clone(),
+        };
+        let lang = self.translations.default_language();
+        if lang.is_empty() {
+            Ok((text, None))
+        } else {
+            Ok((text, Some(lang.to_owned())))
+        }
+    }
+    /// Where article `id` has been moved to, if it has been moved and no other
+    /// article has taken its place.
+    fn redirect_of(&self, id: &str) -> Option<String> {
+        if self.cache.get(id).is_ok() {
+            return None
+        }
+        let meta = self.metadata_cache.get(id).ok()?;
+        let guard = meta.read().unwrap();
+        redirect_of(&guard)
+    }
+    /// Unified diff between revisions of article `id`, specified in `diff`.
+    fn get_diff(&self, req: &Request, id: &str, diff: &str) -> ApiResult {
+        let mut revs = diff.splitn(2, ',')
+            .map(|x| x.trim().parse::<usize>());
+        let old_rev = match revs.next() {
+            Some(Ok(rev)) => rev,
+            _ => return Err(Error::bad_request(ERR_DIFF)),
+        };
+        let (new, new_name) = match revs.next() {
+            Some(Ok(rev)) =>
+                (self.history.get(id, rev)?, format!("{}@{}", id, rev)),
+            Some(Err(_)) => return Err(Error::bad_request(ERR_DIFF)),
+            None => (self.cache.get(id)?.read().unwrap().clone(),
+                id.to_owned()),
+        };
+        let old = self.history.get(id, old_rev)?;
+        let old_name = format!("{}@{}", id, old_rev);
         let res = Response::new()
             .with_header(ContentType(
-                "text/markdown; charset=UTF-8".parse().unwrap()))
-            .with_body(text_ref);
-        Ok(res)
+                "text/x-diff; charset=UTF-8".parse().unwrap()));
+        let diff = unified_diff(&old, &new, &old_name, &new_name);
+        Ok(respond(req, res, diff.into_bytes()))
+    }
+    /// Make a listing entry of article `id`, including extra information
+    /// specified in `expand`.
+    /// Entry of article `id` in listings, with extra information in language
+    /// `lang`. Information not translated is given in the default language.
+    fn make_entry(&self, id: String, expand: &Expand, lang: &str)
+        -> PostEntry {
+        let metadata = if expand.metadata {
+            self.metadata_cache.get(&id)
+                .map(|x| self.translations.metadata(lang, &id,
+                    &x.read().unwrap()))
+                .ok()
+        } else {
+            None
+        };
+        let (digest, stats) = if expand.digest || expand.stats {
+            let text = match self.translations.content(lang, &id) {
+                Some(text) => Ok(text),
+                None => self.cache.get(&id)
+                    .map(|x| x.read().unwrap().clone()),
+            };
+            match text {
+                Ok(text) => {
+                    let digest = if expand.digest {
+                        let (title, content) = get_digest(&text);
+                        Some(Digest {
+                            title: title,
+                            content: md_to_html(&content),
+                        })
+                    } else {
+                        None
+                    };
+                    let stats = if expand.stats {
+                        Some(Stats {
+                            words: text.split_whitespace().count(),
+                            characters: text.chars().count(),
+                        })
+                    } else {
+                        None
+                    };
+                    (digest, stats)
+                },
+                Err(_) => (None, None),
+            }
+        } else {
+            (None, None)
+        };
+        PostEntry {
+            id: id,
+            metadata: metadata,
+            digest: digest,
+            stats: stats,
+        }
     }
     fn get_index(&self, req: &mut Request) -> ApiResult {
         #[derive(Deserialize)]
         struct Param {
             /// The index of the first article to be included.
             from: Option<usize>,
+            /// The maximal number of articles to be included, no more than
+            /// `entries_per_request`.
+            limit: Option<usize>,
+            /// The name of index to be listed.
+            index: Option<String>,
+            /// Extra information to be included in each article.
+            expand: Option<String>,
+            /// Language of extra information.
+            lang: Option<String>,
         }
         let param = req.to_param::<Param>()?;
+        if param.limit == Some(0) {
+            // Otherwise `next` would be `from` and clients would never reach
+            // the end.
+            return Err(Error::bad_request(ERR_LIMIT))
+        }
         let from = param.from.unwrap_or(0);
-        let guard = self.index.read().unwrap();
-        let entries = guard.get_range(from, self.entries_per_request as usize);
-        Response::new()
+        let take = param.limit
+            .map(|x| x.min(self.entries_per_request as usize))
+            .unwrap_or(self.entries_per_request as usize);
+        let expand = match param.expand {
+            Some(ref expand) => Expand::parse(expand)?,
+            None => Expand::default(),
+        };
+        let index = self.indices.get(param.index.as_ref().map(|x| &x[..]))
+            .ok_or(Error::not_found(ERR_INDEX))?;
+        let filter = Filter::from_params(
+            req.to_param::<BTreeMap<String, String>>()?,
+            &["from", "limit", "index", "expand", "lang"], index)?;
+        let hidden = self.hidden(req);
+        let (total, ids) = if filter.is_empty() {
+            (index.visible_len(&hidden),
+                index.get_visible_range(from, take, &hidden))
+        } else {
+            let ids = index.get_visible_range(0, index.visible_len(&hidden),
+                &hidden);
+            let ids = filter.filter(ids, &self.filter_index);
+            let total = ids.len();
+            (total, ids.into_iter().skip(from).take(take).collect())
+        };
+        let end = from.saturating_add(take);
+        let next = if end < total { Some(end) } else { None };
+        let lang = self.translations.negotiate(req,
+            param.lang.as_ref().map(|x| &x[..]));
+        let items = ids.into_iter()
+            .map(|id| self.make_entry(id, &expand, &lang))
+            .collect();
+        let res = Response::new()
             .with_header(ContentType(
                 "application/json; charset=UTF-8".parse().unwrap()))
-            .with_json(&entries)
+            .with_header(vary_language());
+        respond_json(req, res, &PostListing {
+            total: total,
+            from: from,
+            next: next,
+            items: items,
+        })
     }
-    /// `/v1/posts{/path..}?{digest}{raw}`
-    /// `/v1/posts?{from}`
+    /// `/v1/posts{/path..}?{related}{revisions}{revision}{diff}{lang}`
+    /// `/v1/posts?{from}{limit}{index}{expand}{lang}`
+    /// `/v1/posts?{has}{after}{before}{filter.<key>..}`
     fn get(&self, req: &mut Request) -> ApiResult {
         if req.path_segs().len() == 0 {
             self.get_index(req)
@@ -76,34 +547,786 @@ impl PostApi {
             self.get_content(req)
         }
     }
-    /// `/v1/posts{/path..}`
+    /// `/v1/posts{/path..}?{lang}`
+    ///
+    /// Translations are only written when `lang` is given explicitly, so that
+    /// content in the default language is never overwritten by accident.
     fn put(&self, req: &mut Request) -> ApiResult {
+        #[derive(Deserialize)]
+        struct Param {
+            /// Language of the content.
+            lang: Option<String>,
+        }
         self.auth.authorize((), &req)?;
+        let param = req.to_param::<Param>()?;
 
-        // Check content type. A valid request can only contain `text/markdown`.
+        // Check content type. A valid request can only contain `text/markdown`,
+        // or a bundle in `multipart/form-data`.
         let mime = req.header::<ContentType>()
-            .ok_or(Error::bad_request("Content type not given."))?;
+            .ok_or(Error::bad_request(ERR_CONTENT_TYPE))?;
+        let id = req.path_segs().join("/");
+        if mime.0.type_() == "multipart" && mime.0.subtype() == "form-data" {
+            let boundary = mime.0.get_param("boundary")
+                .map(|x| x.as_str().to_owned())
+                .ok_or(Error::bad_request(ERR_BUNDLE))?;
+            return self.put_bundle(req, &id, &boundary)
+        }
         if mime.0.type_() != "text" || mime.0.subtype() != "markdown" {
             return Err(Error::new(StatusCode::UnsupportedMediaType, ERR_MIME))
         }
 
-        let id = req.path_segs().join("/");
-        self.cache.get(&id)
-            .or(self.cache.create(&id))
-            .and_then(|cache| {
-                Ok(*cache.write().unwrap() = req.to_str()?.to_owned())
+        let text = req.to_str()?.to_owned();
+        match param.lang {
+            Some(ref lang) if lang != self.translations.default_language() =>
+                self.save_translation(req, &id, lang, text),
+            _ => self.save(req, &id, text),
+        }
+    }
+    /// Replace the content of article `id` translated into `lang` with `text`
+    /// on request `req`. The article must have been written in the default
+    /// language.
+    fn save_translation(&self, req: &Request, id: &str, lang: &str,
+        text: String) -> ApiResult {
+        let translation = self.translations.get(lang)
+            .ok_or(Error::bad_request(ERR_LANG))?;
+        let content = self.cache.get(id)?.read().unwrap().clone();
+        let (cache, exists) = match translation.post_cache.get(id) {
+            Ok(cache) => (cache, true),
+            Err(_) => (translation.post_cache.create(id)?, false),
+        };
+        let mut guard = cache.write().unwrap();
+        let etag = if exists { Some(etag_of(guard.as_bytes())) } else { None };
+        check_match(req, etag.as_ref())?;
+        // The translation is not replaced if it cannot be kept in history.
+        let author = author_of(req);
+        translation.history.record_change(id, &guard, &text,
+            author.as_ref().map(|x| &x[..]))?;
+        let etag = etag_of(text.as_bytes());
+        *guard = text;
+        drop(guard);
+        info!("Translated article '{}' into '{}'.", id, lang);
+        // Data is derived from content in the default language, which is
+        // given again so that observers know the article has been changed.
+        for observer in self.observers.iter() {
+            observer.update_content(id, &content);
+        }
+        Ok(Response::new().with_header(header::ETag(etag)))
+    }
+    /// Parse and validate a bundle of `content.md`, `metadata.json`, their
+    /// translations and resources, whose paths are given by file names of
+    /// parts, or field names if file names are absent.
+    fn parse_bundle(&self, body: &[u8], boundary: &str) -> Result<Bundle> {
+        let parts = multipart::parse(body, boundary)
+            .ok_or(Error::bad_request(ERR_BUNDLE))?;
+        let mut bundle = Bundle::default();
+        let mut paths = HashSet::new();
+        for part in parts {
+            let path = part.file_name.as_ref().or(part.name.as_ref())
+                .map(|x| x.trim_matches('/').to_owned())
+                .ok_or(Error::bad_request(ERR_BUNDLE_PART))?;
+            let is_valid = !path.is_empty() &&
+                path.split('/').all(|x| !x.is_empty() && !x.starts_with('.'));
+            if !is_valid || !paths.insert(path.clone()) {
+                return Err(Error::bad_request(ERR_BUNDLE_PART))
+            }
+            match &path[..] {
+                "content.md" => {
+                    let text = String::from_utf8(part.data)
+                        .map_err(|_| Error::bad_request(ERR_BUNDLE_CONTENT))?;
+                    bundle.content = Some(text);
+                    continue
+                },
+                "metadata.json" => {
+                    let meta = ::serde_json::from_slice::<JsonValue>(&part.data)
+                        .ok()
+                        .and_then(|x| if x.is_object() {
+                            Some(x)
+                        } else {
+                            None
+                        })
+                        .ok_or(Error::bad_request(ERR_BUNDLE_METADATA))?;
+                    bundle.metadata = Some(meta);
+                    continue
+                },
+                _ => {},
+            }
+            if let Some(lang) = translation_of(&path, "content.", ".md") {
+                self.translations.get(lang)
+                    .ok_or(Error::bad_request(ERR_LANG))?;
+                let text = String::from_utf8(part.data)
+                    .map_err(|_| Error::bad_request(ERR_BUNDLE_TRANSLATION))?;
+                bundle.translated_contents.push((lang.to_owned(), text));
+                continue
+            }
+            if let Some(lang) = translation_of(&path, "metadata.", ".json") {
+                self.translations.get(lang)
+                    .ok_or(Error::bad_request(ERR_LANG))?;
+                let meta = ::serde_json::from_slice::<JsonValue>(&part.data)
+                    .ok()
+                    .and_then(|x| if x.is_object() { Some(x) } else { None })
+                    .ok_or(Error::bad_request(ERR_BUNDLE_TRANSLATION))?;
+                bundle.translated_metadata.push((lang.to_owned(), meta));
+                continue
+            }
+            // Files of articles are never uploaded as resources, even for
+            // nested articles.
+            let file_name = path.rsplit('/').next().unwrap_or_default();
+            if is_post_file(file_name) {
+                return Err(Error::bad_request(ERR_BUNDLE_PART))
+            }
+            let ext = path.rsplitn(2, '.').next().unwrap_or_default();
+            let mm = self.allowed_exts.get(ext)
+                .ok_or(Error::new(StatusCode::UnsupportedMediaType,
+                    ERR_MIME_NOT_FOUND))?;
+            if let Some(ref ty) = part.content_type {
+                let mime = ty.parse::<Mime>()
+                    .map_err(|_| Error::bad_request(ERR_MIME_EXT_MISMATCH))?;
+                if mime.type_() != mm.type_() ||
+                    mime.subtype() != mm.subtype() {
+                    return Err(Error::bad_request(ERR_MIME_EXT_MISMATCH))
+                }
+            }
+            bundle.resources.push((path, part.data));
+        }
+        if bundle.content.is_none() {
+            return Err(Error::bad_request(ERR_BUNDLE_CONTENT))
+        }
+        Ok(bundle)
+    }
+    /// `/v1/posts{/path..}` in `multipart/form-data`
+    ///
+    /// Nothing is written unless every part of the bundle is valid, and
+    /// everything written is restored if any of the files fails to be written.
+    /// Indices are updated after all the files are written.
+    fn put_bundle(&self, req: &Request, id: &str, boundary: &str)
+        -> ApiResult {
+        let bundle = self.parse_bundle(req.body(), boundary)?;
+        let is_stamped = self.created_key.is_some() ||
+            self.modified_key.is_some();
+        let (cache, exists) = match self.cache.get(id) {
+            Ok(cache) => (cache, true),
+            Err(_) => (self.cache.create(id)?, false),
+        };
+        // Metadata is only created if it's to be written.
+        let meta_entry = match self.metadata_cache.get(id) {
+            Ok(cache) => Ok(Some((cache, true))),
+            Err(_) if bundle.metadata.is_some() || is_stamped =>
+                self.metadata_cache.create(id).map(|x| Some((x, false))),
+            Err(_) => Ok(None),
+        };
+        // The article is locked from the precondition being checked until all
+        // the files are written, metadata before content as views do. Entries
+        // created to be locked are removed if nothing is written.
+        let result = meta_entry.and_then(|meta_entry| {
+            let result = {
+                let mut meta_guard = meta_entry.as_ref()
+                    .map(|&(ref cache, _)| cache.write().unwrap());
+                let mut guard = cache.write().unwrap();
+                self.write_bundle(req, id, bundle,
+                    meta_guard.as_mut().map(|x| &mut **x), &mut guard, exists)
+            };
+            if let (&Err(_), Some((_, false))) = (&result, meta_entry) {
+                if let Err(err) = self.metadata_cache.remove(id) {
+                    warn!("Unable to remove metadata of '{}': {}", id, err);
+                }
+            }
+            result
+        });
+        if result.is_err() && !exists {
+            if let Err(err) = self.cache.remove(id) {
+                warn!("Unable to remove content of '{}': {}", id, err);
+            }
+        }
+        Ok(Response::new().with_header(header::ETag(result?)))
+    }
+    /// Write `bundle` of article `id`, whose metadata and content are locked
+    /// as `meta_guard` and `guard` respectively, and the content `exists` or
+    /// is just created. The metadata is stamped as it's written. The new
+    /// entity tag of the content is returned.
+    fn write_bundle<'a>(&'a self, req: &Request, id: &str, bundle: Bundle,
+        meta_guard: Option<&mut JsonValue>, guard: &mut String, exists: bool)
+        -> Result<header::EntityTag> {
+        // Changes made by others since the client fetched the content are not
+        // overwritten.
+        let etag = if exists { Some(etag_of(guard.as_bytes())) } else { None };
+        check_match(req, etag.as_ref())?;
+        let text = bundle.content.unwrap_or_default();
+        let meta = meta_guard.as_ref().and_then(|old| {
+            let meta = bundle.metadata.as_ref().unwrap_or(&**old);
+            let meta = self.stamped(meta, !exists);
+            if meta == **old { None } else { Some(meta) }
+        });
+
+        let mut staged: Vec<Box<Staged + 'a>> = Vec::new();
+        for (path, data) in bundle.resources {
+            let res_id = format!("{}/{}", id, path);
+            staged.push(StagedItem::boxed(&*self.resource_cache, &res_id,
+                data));
+        }
+        for (lang, meta) in bundle.translated_metadata {
+            let cache = &self.translations.get(&lang).unwrap().metadata_cache;
+            staged.push(StagedItem::boxed(&**cache, id, meta));
+        }
+        // Translated content is kept in history as well.
+        let mut translated = Vec::new();
+        for (lang, text) in bundle.translated_contents {
+            let translation = self.translations.get(&lang).unwrap();
+            let old = self.translations.content(&lang, id).unwrap_or_default();
+            staged.push(StagedItem::boxed(&*translation.post_cache, id,
+                text.clone()));
+            translated.push((translation, old, text));
+        }
+        let mut done = 0;
+        let mut result = Ok(());
+        for item in staged.iter_mut() {
+            result = item.commit();
+            if result.is_err() {
+                break
+            }
+            done += 1;
+        }
+        // The bundle is not kept if it cannot be kept in history.
+        let author = author_of(req);
+        let result = result
+            .and_then(|_| self.history.record_change(id, &guard[..], &text,
+                author.as_ref().map(|x| &x[..])))
+            .and_then(|_| {
+                for &(translation, ref old, ref new) in translated.iter() {
+                    translation.history.record_change(id, old, new,
+                        author.as_ref().map(|x| &x[..]))?;
+                }
+                Ok(())
             })
-            .map(|_| Response::new())
+            .and_then(|_| match (meta_guard.as_ref(), meta.as_ref()) {
+                (Some(old), Some(meta)) => record_change(
+                    &self.metadata_history, req, id, old, meta),
+                _ => Ok(()),
+            });
+        if let Err(err) = result {
+            warn!("Unable to upload bundle of article '{}', restoring it.", id);
+            for item in staged[..done].iter().rev() {
+                item.undo();
+            }
+            return Err(err)
+        }
+        // Content and metadata are written last as nothing fails then.
+        let etag = etag_of(text.as_bytes());
+        for observer in self.observers.iter() {
+            observer.update_content(id, &text);
+        }
+        *guard = text;
+        if let (Some(meta_guard), Some(meta)) = (meta_guard, meta) {
+            self.indices.update(id, &meta);
+            for observer in self.observers.iter() {
+                observer.update_metadata(id, &meta);
+            }
+            *meta_guard = meta;
+        }
+        info!("Uploaded bundle of article '{}'.", id);
+        Ok(etag)
+    }
+    /// `/v1/posts`
+    ///
+    /// Create an article of markdown content, or a JSON object of `content` and
+    /// optionally `metadata`, at a path made from the permalink pattern.
+    fn post_new(&self, req: &mut Request) -> ApiResult {
+        #[derive(Deserialize)]
+        struct NewPost {
+            content: String,
+            metadata: Option<JsonValue>,
+        }
+        let mime = req.header::<ContentType>()
+            .ok_or(Error::bad_request(ERR_CONTENT_TYPE))?;
+        let post = if mime.0.type_() == "text" &&
+            mime.0.subtype() == "markdown" {
+            NewPost {
+                content: req.to_str()?.to_owned(),
+                metadata: None,
+            }
+        } else if mime.0.type_() == "application" &&
+            mime.0.subtype() == "json" {
+            ::serde_json::from_slice::<NewPost>(req.body())
+                .map_err(|err| {
+                    Error::bad_request(ERR_NEW_POST).with_cause(err)
+                })?
+        } else {
+            return Err(Error::new(StatusCode::UnsupportedMediaType,
+                ERR_NEW_MIME))
+        };
+        match post.metadata {
+            Some(JsonValue::Object(_)) | None => {},
+            _ => return Err(Error::bad_request(ERR_NEW_POST)),
+        }
+        let (title, _) = get_digest(&post.content);
+        let now = Utc::now().with_timezone(&self.timezone);
+        let (id, cache) = self.create_new(&self.permalink.make(&title, &now))?;
+        // Metadata is only written once the content is, and everything is
+        // removed if any of them fails.
+        let result = self.write_content(req, &id, &cache, false, post.content)
+            .and_then(|etag| {
+                if let Some(meta) = post.metadata {
+                    let cache = self.metadata_cache.create(&id)?;
+                    let mut guard = cache.write().unwrap();
+                    record_change(&self.metadata_history, req, &id, &guard,
+                        &meta)?;
+                    *guard = meta;
+                    self.indices.update(&id, &guard);
+                    for observer in self.observers.iter() {
+                        observer.update_metadata(&id, &guard);
+                    }
+                }
+                self.stamp(req, &id, true)?;
+                Ok(etag)
+            });
+        let etag = match result {
+            Ok(etag) => etag,
+            Err(err) => {
+                self.undo_create(&id);
+                return Err(err)
+            },
+        };
+        info!("Created article '{}'.", id);
+        Ok(Response::new()
+            .with_status(StatusCode::Created)
+            .with_header(header::ETag(etag))
+            .with_header(header::Location::new(
+                format!("/api/v1/posts/{}", id))))
+    }
+    /// Take a path made of `base` for a new article, which is numbered if
+    /// it's taken, even by a redirect or revisions, and create the content of
+    /// the article there.
+    fn create_new(&self, base: &str) -> Result<(String, Arc<RwLock<String>>)> {
+        let _guard = self.creating.lock().unwrap();
+        let is_taken = |id: &str| self.cache.contains(id) ||
+            self.metadata_cache.contains(id) ||
+            has_history(&self.published_dir, id);
+        let mut id = base.to_owned();
+        let mut n = 1;
+        while id.is_empty() || is_taken(&id) {
+            n += 1;
+            id = format!("{}-{}", base, n);
+        }
+        let cache = self.cache.create(&id)?;
+        Ok((id, cache))
+    }
+    /// Remove everything of article `id` which has failed to be created.
+    /// Failures are only logged as the original failure is reported.
+    fn undo_create(&self, id: &str) {
+        warn!("Unable to create article '{}', removing it.", id);
+        if let Err(err) = self.cache.remove(id) {
+            warn!("Unable to remove content of '{}': {}", id, err);
+        }
+        if self.metadata_cache.contains(id) {
+            if let Err(err) = self.metadata_cache.remove(id) {
+                warn!("Unable to remove metadata of '{}': {}", id, err);
+            }
+        }
+        let trash = Trash::default();
+        if let Err(err) = remove_history(&self.published_dir, id, &trash) {
+            warn!("Unable to remove revisions of '{}': {}", id, err);
+        }
+        self.indices.remove(id);
+        for observer in self.observers.iter() {
+            observer.remove(id);
+        }
+    }
+    /// `/v1/posts{/path..}?{restore}`
+    /// `/v1/posts{/path..}?{move_to}{redirect}`
+    fn post(&self, req: &mut Request) -> ApiResult {
+        #[derive(Deserialize)]
+        struct Param {
+            /// The revision to be restored.
+            restore: Option<usize>,
+            /// The path the article is moved to.
+            move_to: Option<String>,
+            /// Leave a redirect to the new path when the article is moved.
+            redirect: Option<String>,
+        }
+        self.auth.authorize((), &req)?;
+        if req.path_segs().len() == 0 {
+            return self.post_new(req)
+        }
+
+        let param = req.to_param::<Param>()?;
+        let id = req.path_segs().join("/");
+        if let Some(ref new_id) = param.move_to {
+            return self.move_post(req, &id, new_id, param.redirect.is_some())
+        }
+        let rev = param.restore.ok_or(Error::bad_request(ERR_POST_ACTION))?;
+        let text = self.history.get(&id, rev)?;
+        self.save(req, &id, text)
+    }
+    /// Move article `id` to `new_id` with its metadata, comments, resources,
+    /// translations and revisions. If `redirect` is set, a metadata file
+    /// redirecting to the new path is left at the original path.
+    fn move_post(&self, req: &Request, id: &str, new_id: &str,
+        redirect: bool) -> ApiResult {
+        let new_id = new_id.trim_matches('/');
+        let is_valid = !new_id.is_empty() &&
+            new_id != id &&
+            !new_id.starts_with(&format!("{}/", id)) &&
+            new_id.split('/').all(|x| !x.is_empty() && !x.starts_with('.'));
+        if !is_valid {
+            return Err(Error::bad_request(ERR_MOVE_TO))
+        }
+        let text = self.cache.get(id)?.read().unwrap().clone();
+        check_match(req, Some(&etag_of(text.as_bytes())))?;
+        let mut moves: Vec<(&ItemCache, String, String)> = vec![
+            (&*self.cache, id.to_owned(), new_id.to_owned()),
+            (&*self.comment_cache, id.to_owned(), new_id.to_owned()),
+        ];
+        for (_, translation) in self.translations.iter() {
+            moves.push((&*translation.post_cache, id.to_owned(),
+                new_id.to_owned()));
+            moves.push((&*translation.metadata_cache, id.to_owned(),
+                new_id.to_owned()));
+        }
+        for res_id in list_resources(&self.published_dir, id) {
+            let new_res_id = format!("{}{}", new_id, &res_id[id.len()..]);
+            moves.push((&*self.resource_cache, res_id, new_res_id));
+        }
+        // Nothing is overwritten, so that everything can be moved back if any
+        // of the moves fails.
+        let is_taken = has_history(&self.published_dir, new_id) ||
+            moves.iter().any(|&(cache, _, ref to)| cache.contains(to));
+        if is_taken {
+            return Err(Error::new(StatusCode::Conflict, ERR_MOVE_CONFLICT))
+        }
+        moves.insert(1, (&*self.metadata_cache, id.to_owned(),
+            new_id.to_owned()));
+        // Metadata without content, like a redirect, is replaced.
+        let replaced = match self.metadata_cache.get(new_id) {
+            Ok(cache) => {
+                let meta = cache.read().unwrap().clone();
+                self.metadata_cache.remove(new_id)?;
+                Some(meta)
+            },
+            Err(_) => None,
+        };
+        // Revisions are moved first so that the original directory can be
+        // removed once all the files are moved.
+        let mut done = 0;
+        let mut result = move_history(&self.published_dir, id, new_id);
+        if result.is_ok() {
+            for &(cache, ref from, ref to) in moves.iter() {
+                result = cache.move_item(from, to);
+                if result.is_err() {
+                    break
+                }
+                done += 1;
+            }
+        }
+        if let Err(err) = result {
+            self.undo_move(id, new_id, &moves[..done], replaced);
+            return Err(err)
+        }
+        let meta = self.metadata_cache.get(new_id).ok()
+            .map(|cache| cache.read().unwrap().clone());
+        info!("Moved article '{}' to '{}'.", id, new_id);
+
+        self.indices.remove(id);
+        for observer in self.observers.iter() {
+            observer.remove(id);
+        }
+        if let Some(ref meta) = meta {
+            self.indices.update(new_id, meta);
+            for observer in self.observers.iter() {
+                observer.update_metadata(new_id, meta);
+            }
+        }
+        for observer in self.observers.iter() {
+            observer.update_content(new_id, &text);
+        }
+        if redirect {
+            *self.metadata_cache.create(id)?.write().unwrap() =
+                json!({ "redirect": new_id });
+        }
+        Ok(Response::new()
+            .with_status(StatusCode::Created)
+            .with_header(header::Location::new(
+                format!("/api/v1/posts/{}", new_id))))
+    }
+    /// Move article `new_id` back to `id` after a failure, where `moves` have
+    /// been made and metadata `replaced` was at `new_id`. Failures are only
+    /// logged as the original failure is reported.
+    fn undo_move(&self, id: &str, new_id: &str,
+        moves: &[(&ItemCache, String, String)], replaced: Option<JsonValue>) {
+        warn!("Unable to move article '{}' to '{}', moving it back.", id,
+            new_id);
+        for &(cache, ref from, ref to) in moves.iter().rev() {
+            if let Err(err) = cache.move_item(to, from) {
+                warn!("Unable to move '{}' back to '{}': {}", to, from, err);
+            }
+        }
+        if let Err(err) = move_history(&self.published_dir, new_id, id) {
+            warn!("Unable to move revisions of '{}' back: {}", new_id, err);
+        }
+        if let Some(meta) = replaced {
+            match self.metadata_cache.create(new_id) {
+                Ok(cache) => *cache.write().unwrap() = meta,
+                Err(err) => warn!("Unable to restore metadata of '{}': {}",
+                    new_id, err),
+            }
+        }
+    }
+    /// Replace the content of article `id` with `text` on request `req`,
+    /// keeping the previous content in history. The new entity tag is
+    /// responded.
+    fn save(&self, req: &Request, id: &str, text: String) -> ApiResult {
+        let (cache, exists) = match self.cache.get(id) {
+            Ok(cache) => (cache, true),
+            Err(_) => (self.cache.create(id)?, false),
+        };
+        let etag = self.write_content(req, id, &cache, exists, text)?;
+        self.stamp(req, id, !exists)?;
+        Ok(Response::new().with_header(header::ETag(etag)))
+    }
+    /// Write `text` to `cache` of the content of article `id`, which `exists`
+    /// or is just created, keeping the previous content in history. The new
+    /// entity tag is returned.
+    fn write_content(&self, req: &Request, id: &str, cache: &RwLock<String>,
+        exists: bool, text: String) -> Result<header::EntityTag> {
+        let mut guard = cache.write().unwrap();
+        // Changes made by others since the client fetched the content are not
+        // overwritten.
+        let etag = if exists { Some(etag_of(guard.as_bytes())) } else { None };
+        check_match(req, etag.as_ref())?;
+        // The content is not replaced if it cannot be kept in history.
+        let author = author_of(req);
+        self.history.record_change(id, &guard, &text,
+            author.as_ref().map(|x| &x[..]))?;
+        for observer in self.observers.iter() {
+            observer.update_content(id, &text);
+        }
+        let etag = etag_of(text.as_bytes());
+        *guard = text;
+        // Views lock metadata before content, so content must not be locked
+        // while metadata is, which it's not once this returns.
+        Ok(etag)
     }
-    /// `/v1/posts{/path..}`
+    /// Set timestamps in the metadata of article `id`, which has just been
+    /// written on request `req`, and update indices with them. The creation
+    /// time is set only if the article `is_new`.
+    fn stamp(&self, req: &Request, id: &str, is_new: bool) -> Result<()> {
+        if self.created_key.is_none() && self.modified_key.is_none() {
+            return Ok(())
+        }
+        let cache = match self.metadata_cache.get(id) {
+            Ok(cache) => cache,
+            Err(_) => self.metadata_cache.create(id)?,
+        };
+        let mut guard = cache.write().unwrap();
+        let stamped = self.stamped(&guard, is_new);
+        // Nothing is recorded if the timestamps are kept, e.g., only the
+        // creation time is set and it's set already.
+        if etag_of_json(&stamped).strong_eq(&etag_of_json(&*guard)) {
+            return Ok(())
+        }
+        record_change(&self.metadata_history, req, id, &guard, &stamped)?;
+        *guard = stamped;
+        self.indices.update(id, &guard);
+        for observer in self.observers.iter() {
+            observer.update_metadata(id, &guard);
+        }
+        Ok(())
+    }
+    /// Metadata `meta` with timestamps set to now. The creation time is set
+    /// only if the article `is_new`.
+    fn stamped(&self, meta: &JsonValue, is_new: bool) -> JsonValue {
+        let now = Utc::now().with_timezone(&self.timezone).to_rfc3339();
+        let mut stamped = if meta.is_object() {
+            meta.clone()
+        } else {
+            json!({})
+        };
+        {
+            let obj = stamped.as_object_mut().unwrap();
+            if let Some(ref key) = self.created_key {
+                if is_new && !obj.contains_key(key) {
+                    obj.insert(key.to_owned(), JsonValue::String(now.clone()));
+                }
+            }
+            if let Some(ref key) = self.modified_key {
+                obj.insert(key.to_owned(), JsonValue::String(now));
+            }
+        }
+        stamped
+    }
+    /// `/v1/posts{/path..}?{dry_run}`
+    ///
+    /// The article is removed with its metadata, comments, resources,
+    /// translations and revisions. Everything removed, or to be removed in a
+    /// dry run, is listed in the response.
     fn delete(&self, req: &mut Request) -> ApiResult {
+        #[derive(Deserialize)]
+        struct Param {
+            /// List what would be removed without removing anything.
+            dry_run: Option<String>,
+        }
         self.auth.authorize((), &req)?;
-        
+
+        let param = req.to_param::<Param>()?;
         let id = req.path_segs().join("/");
-        self.cache.remove(&id)
-            .map(|_| Response::new())
+        let etag = self.cache.get(&id)
+            .map(|cache| etag_of(cache.read().unwrap().as_bytes()))
+            .ok();
+        check_match(req, etag.as_ref())?;
+        let removal = Removal {
+            content: etag.is_some(),
+            metadata: self.metadata_cache.get(&id).is_ok(),
+            comments: self.comment_cache.get(&id).is_ok(),
+            resources: list_resources(&self.published_dir, &id),
+            revisions: has_history(&self.published_dir, &id),
+            translations: self.translations.iter()
+                .filter(|&(_, x)| x.post_cache.get(&id).is_ok() ||
+                    x.metadata_cache.get(&id).is_ok())
+                .map(|(lang, _)| lang.to_owned())
+                .collect(),
+            id: id,
+        };
+        if !removal.content && !removal.metadata && !removal.comments &&
+            removal.resources.is_empty() && removal.translations.is_empty() {
+            return Err(Error::not_found(ERR_NOT_FOUND))
+        }
+        if param.dry_run.is_none() {
+            self.remove(&removal)?;
+        }
+        let res = Response::new()
+            .with_header(ContentType(
+                "application/json; charset=UTF-8".parse().unwrap()));
+        respond_json(req, res, &removal)
+    }
+    /// Remove everything listed in `removal`, and the article from indices.
+    fn remove(&self, removal: &Removal) -> Result<()> {
+        let id = &removal.id;
+        // Revisions are removed first so that the article directory can be
+        // removed once all the files are removed.
+        remove_history(&self.published_dir, id, &self.trash)?;
+        for res_id in removal.resources.iter() {
+            self.resource_cache.remove(res_id)?;
+        }
+        for lang in removal.translations.iter() {
+            let translation = self.translations.get(lang).unwrap();
+            if translation.post_cache.get(id).is_ok() {
+                translation.post_cache.remove(id)?;
+            }
+            if translation.metadata_cache.get(id).is_ok() {
+                translation.metadata_cache.remove(id)?;
+            }
+        }
+        if removal.comments {
+            self.comment_cache.remove(id)?;
+        }
+        if removal.metadata {
+            self.metadata_cache.remove(id)?;
+        }
+        if removal.content {
+            self.cache.remove(id)?;
+        }
+        info!("Removed article '{}'.", id);
+        self.indices.remove(id);
+        for observer in self.observers.iter() {
+            observer.remove(id);
+        }
+        Ok(())
+    }
+}
+/// Cache of a kind of files of articles, in which items can be moved.
+trait ItemCache {
+    fn contains(&self, id: &str) -> bool;
+    /// Move item `id` to `new_id`. Nothing is done if there is no such item.
+    fn move_item(&self, id: &str, new_id: &str) -> Result<()>;
+}
+impl<T: 'static + Clone> ItemCache for Cache<T> {
+    fn contains(&self, id: &str) -> bool {
+        self.get(id).is_ok()
+    }
+    fn move_item(&self, id: &str, new_id: &str) -> Result<()> {
+        let val = match self.get(id) {
+            Ok(item) => item.read().unwrap().clone(),
+            Err(_) => return Ok(()),
+        };
+        let item = match self.get(new_id) {
+            Ok(item) => item,
+            Err(_) => self.create(new_id)?,
+        };
+        *item.write().unwrap() = val;
+        self.remove(id)
+    }
+}
+/// Write of an item of an article staged in a bundle, which can be undone if
+/// other writes of the bundle fail.
+trait Staged {
+    /// Write the item, keeping the previous one.
+    fn commit(&mut self) -> Result<()>;
+    /// Restore the previous item, or remove the item if there was none.
+    /// Failures are only logged.
+    fn undo(&self);
+}
+struct StagedItem<'a, T: 'static> {
+    cache: &'a Cache<T>,
+    id: String,
+    val: Option<T>,
+    /// The previous item, or `None` if there was none.
+    prev: Option<T>,
+}
+impl<'a, T: 'static + Clone> StagedItem<'a, T> {
+    fn boxed(cache: &'a Cache<T>, id: &str, val: T) -> Box<Staged + 'a> {
+        Box::new(StagedItem {
+            cache: cache,
+            id: id.to_owned(),
+            val: Some(val),
+            prev: None,
+        })
     }
 }
+impl<'a, T: 'static + Clone> Staged for StagedItem<'a, T> {
+    fn commit(&mut self) -> Result<()> {
+        let item = match self.cache.get(&self.id) {
+            Ok(item) => {
+                self.prev = Some(item.read().unwrap().clone());
+                item
+            },
+            Err(_) => self.cache.create(&self.id)?,
+        };
+        if let Some(val) = self.val.take() {
+            *item.write().unwrap() = val;
+        }
+        Ok(())
+    }
+    fn undo(&self) {
+        let result = match self.prev {
+            Some(ref prev) => self.cache.get(&self.id)
+                .map(|item| *item.write().unwrap() = prev.clone()),
+            None => self.cache.remove(&self.id),
+        };
+        if let Err(err) = result {
+            warn!("Unable to restore '{}': {}", self.id, err);
+        }
+    }
+}
+/// Language of translation `path` named like `<prefix><lang><suffix>`, e.g.
+/// `content.zh.md`.
+pub fn translation_of<'a>(path: &'a str, prefix: &str, suffix: &str)
+    -> Option<&'a str> {
+    if path.len() <= prefix.len() + suffix.len() ||
+        !path.starts_with(prefix) || !path.ends_with(suffix) {
+        return None
+    }
+    Some(&path[prefix.len()..path.len() - suffix.len()])
+}
+/// Path an article has been moved to, according to its metadata.
+pub fn redirect_of(meta: &JsonValue) -> Option<String> {
+    meta.get("redirect")
+        .and_then(|x| x.as_str())
+        .map(|x| x.to_owned())
+}
+/// Author of a change, given in header `From`.
+pub fn author_of(req: &Request) -> Option<String> {
+    req.header::<::hyper::header::From>()
+        .map(|x| x.0.clone())
+}
+
 impl Api for PostApi {
     fn name(&self) -> &[&str] {
         &["posts"]
@@ -114,8 +1337,9 @@ impl Api for PostApi {
         use self::Method::*;
         match req.method() {
             Options => Ok(Response::new()
-                .with_header(Allow(vec![Options, Get, Put, Delete]))),
+                .with_header(Allow(vec![Options, Get, Post, Put, Delete]))),
             Get => self.get(req),
+            Post => self.post(req),
             Put => self.put(req),
             Delete => self.delete(req),
             _ => Err(Error::method_not_allowed()),
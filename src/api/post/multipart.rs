@@ -0,0 +1,165 @@
+///! Parser of `multipart/form-data` bodies, in which articles are uploaded in
+///! bundles.
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Part {
+    /// Field name in `Content-Disposition`.
+    pub name: Option<String>,
+    /// File name in `Content-Disposition`.
+    pub file_name: Option<String>,
+    pub content_type: Option<String>,
+    pub data: Vec<u8>,
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    if needle.len() > haystack.len() {
+        return None
+    }
+    (0..haystack.len() - needle.len() + 1)
+        .find(|&i| &haystack[i..i + needle.len()] == needle)
+}
+
+/// Parse a parameter list like `form-data; name="foo"; filename="bar.png"`.
+/// In quoted values, `;` doesn't end the parameter and `\` escapes the next
+/// character.
+fn parse_params(text: &str) -> Vec<(String, String)> {
+    let mut params = Vec::new();
+    let mut key = String::new();
+    let mut val = String::new();
+    // The disposition type before the first `;` is not a parameter.
+    let mut is_type = true;
+    let mut in_val = false;
+    let mut quoted = false;
+    let mut escaped = false;
+    for ch in text.chars().chain(Some(';')) {
+        if quoted {
+            match ch {
+                _ if escaped => {
+                    val.push(ch);
+                    escaped = false;
+                },
+                '\\' => escaped = true,
+                '"' => quoted = false,
+                _ => val.push(ch),
+            }
+            continue
+        }
+        match ch {
+            ';' => {
+                if !is_type && in_val {
+                    params.push((key.trim().to_lowercase(), val.clone()));
+                }
+                is_type = false;
+                in_val = false;
+                key.clear();
+                val.clear();
+            },
+            '=' if !in_val => in_val = true,
+            '"' if in_val => quoted = true,
+            // Spaces in quoted values are kept.
+            ' ' | '\t' if in_val => {},
+            _ if in_val => val.push(ch),
+            _ => key.push(ch),
+        }
+    }
+    params
+}
+
+/// Parse the headers of a part.
+fn parse_headers(text: &str, part: &mut Part) {
+    for line in text.split("\r\n") {
+        let mut kv = line.splitn(2, ':');
+        let (key, val) = match (kv.next(), kv.next()) {
+            (Some(key), Some(val)) => (key.trim().to_lowercase(), val.trim()),
+            _ => continue,
+        };
+        match &key[..] {
+            "content-disposition" => for (k, v) in parse_params(val) {
+                match &k[..] {
+                    "name" => part.name = Some(v),
+                    "filename" => part.file_name = Some(v),
+                    _ => {},
+                }
+            },
+            "content-type" => part.content_type = Some(val.to_owned()),
+            _ => {},
+        }
+    }
+}
+
+/// Parse `body` of parts delimited by `boundary`. `None` is returned if the
+/// body is malformed.
+pub fn parse(body: &[u8], boundary: &str) -> Option<Vec<Part>> {
+    let delimiter = format!("--{}", boundary).into_bytes();
+    let close = format!("\r\n--{}", boundary).into_bytes();
+    let mut parts = Vec::new();
+    // Preamble before the first delimiter is ignored.
+    let mut rest = &body[find(body, &delimiter)? + delimiter.len()..];
+    loop {
+        if rest.starts_with(b"--") {
+            return Some(parts)
+        }
+        if !rest.starts_with(b"\r\n") {
+            return None
+        }
+        rest = &rest[2..];
+        let mut part = Part::default();
+        let header_end = find(rest, b"\r\n\r\n")?;
+        let headers = ::std::str::from_utf8(&rest[..header_end]).ok()?;
+        parse_headers(headers, &mut part);
+        rest = &rest[header_end + 4..];
+        let data_end = find(rest, &close)?;
+        part.data = rest[..data_end].to_owned();
+        parts.push(part);
+        rest = &rest[data_end + close.len()..];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, parse_params, Part};
+
+    #[test]
+    fn test_parse() {
+        let body = b"Preamble.\r\n--XyZ\r\n\
+            Content-Disposition: form-data; name=\"content.md\"\r\n\r\n\
+            # Title\r\n\r\nHello!\r\n\
+            --XyZ\r\n\
+            Content-Disposition: form-data; name=\"img\"; \
+            filename=\"img/a.png\"\r\n\
+            Content-Type: image/png\r\n\r\n\
+            \x89PNG\r\n\
+            --XyZ--\r\n";
+        let parts = parse(body, "XyZ").unwrap();
+        assert_eq!(parts, vec![
+            Part {
+                name: Some("content.md".to_owned()),
+                data: b"# Title\r\n\r\nHello!".to_vec(),
+                ..Part::default()
+            },
+            Part {
+                name: Some("img".to_owned()),
+                file_name: Some("img/a.png".to_owned()),
+                content_type: Some("image/png".to_owned()),
+                data: b"\x89PNG".to_vec(),
+            },
+        ]);
+    }
+    #[test]
+    fn test_parse_params() {
+        let params = parse_params(
+            "form-data; name=\"a;b\"; filename=\" c \\\"d\\\".png\"; x = y");
+        assert_eq!(params, vec![
+            ("name".to_owned(), "a;b".to_owned()),
+            ("filename".to_owned(), " c \"d\".png".to_owned()),
+            ("x".to_owned(), "y".to_owned()),
+        ]);
+    }
+    #[test]
+    fn test_parse_malformed() {
+        assert!(parse(b"--XyZ\r\nContent-Type: text/plain\r\n\r\nfoo", "XyZ")
+            .is_none());
+        assert!(parse(b"no delimiter", "XyZ").is_none());
+        assert_eq!(parse(b"--XyZ--", "XyZ"), Some(Vec::new()));
+    }
+}
@@ -0,0 +1,128 @@
+///! Paths of articles created without a path given by clients.
+use chrono::{DateTime, Datelike, FixedOffset};
+
+/// Slugs are kept in 255 bytes, which most file systems limit file names to,
+/// leaving some for numbered suffixes of taken paths like `-2`.
+const MAX_SLUG_LEN: usize = 240;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Part {
+    Text(String),
+    Year,
+    Month,
+    Day,
+    Slug,
+}
+
+/// Pattern of article paths like `{year}/{month}/{slug}`, where `{year}`,
+/// `{month}` and `{day}` are replaced by the date an article is created, and
+/// `{slug}` by the slug of its title.
+#[derive(Clone, Debug)]
+pub struct Permalink {
+    parts: Vec<Part>,
+}
+impl Permalink {
+    /// Parse `pattern`. `None` is returned if any placeholder is unknown or
+    /// not closed.
+    pub fn parse(pattern: &str) -> Option<Permalink> {
+        let mut parts = Vec::new();
+        let mut rest = pattern;
+        while let Some(beg) = rest.find('{') {
+            if beg > 0 {
+                parts.push(Part::Text(rest[..beg].to_owned()));
+            }
+            let end = beg + rest[beg..].find('}')?;
+            let part = match &rest[beg + 1..end] {
+                "year" => Part::Year,
+                "month" => Part::Month,
+                "day" => Part::Day,
+                "slug" => Part::Slug,
+                _ => return None,
+            };
+            parts.push(part);
+            rest = &rest[end + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_owned()));
+        }
+        Some(Permalink { parts: parts })
+    }
+    /// Make the path of an article titled `title` created at `time`.
+    pub fn make(&self, title: &str, time: &DateTime<FixedOffset>) -> String {
+        let mut path = String::new();
+        for part in self.parts.iter() {
+            match *part {
+                Part::Text(ref text) => path.push_str(text),
+                Part::Year => path.push_str(&time.year().to_string()),
+                Part::Month => path.push_str(&format!("{:02}", time.month())),
+                Part::Day => path.push_str(&format!("{:02}", time.day())),
+                Part::Slug => path.push_str(&slugify(title)),
+            }
+        }
+        path.trim_matches('/').to_owned()
+    }
+}
+impl Default for Permalink {
+    /// Make a `Permalink` of pattern `{slug}`.
+    fn default() -> Permalink {
+        Permalink { parts: vec![Part::Slug] }
+    }
+}
+
+/// Make a slug of `title`: lowercase alphanumerics separated by `-`, cut at
+/// `MAX_SLUG_LEN` bytes. `post` is returned if there is no alphanumeric.
+pub fn slugify(title: &str) -> String {
+    let words: Vec<String> = title
+        .split(|ch: char| !ch.is_alphanumeric())
+        .filter(|x| !x.is_empty())
+        .map(|x| x.to_lowercase())
+        .collect();
+    if words.is_empty() {
+        return "post".to_owned()
+    }
+    let mut slug = words.join("-");
+    if slug.len() > MAX_SLUG_LEN {
+        let mut end = MAX_SLUG_LEN;
+        while !slug.is_char_boundary(end) {
+            end -= 1;
+        }
+        slug.truncate(end);
+        let len = slug.trim_right_matches('-').len();
+        slug.truncate(len);
+    }
+    slug
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::DateTime;
+    use super::{slugify, Permalink};
+
+    #[test]
+    fn test_slugify() {
+        assert_eq!(slugify("Hello, Writus!"), "hello-writus");
+        assert_eq!(slugify("  Rust 2018 -- Edition "), "rust-2018-edition");
+        assert_eq!(slugify("你好 世界"), "你好-世界");
+        assert_eq!(slugify("?!"), "post");
+        let slug = slugify(&"Writus ".repeat(100));
+        assert_eq!(slug.len(), 240);
+        assert!(slug.starts_with("writus-writus-"));
+        assert_eq!(slugify(&"你好".repeat(100)).len(), 240);
+        let title = "a".to_owned() + &"你好".repeat(100);
+        assert_eq!(slugify(&title).len(), 238);
+        assert_eq!(slugify(&(&"a".repeat(239) + " b")), "a".repeat(239));
+    }
+    #[test]
+    fn test_permalink() {
+        let time = DateTime::parse_from_rfc3339("2018-03-04T05:06:07+08:00")
+            .unwrap();
+        let permalink = Permalink::parse("{year}/{month}/{day}/{slug}")
+            .unwrap();
+        assert_eq!(permalink.make("Hello, Writus!", &time),
+            "2018/03/04/hello-writus");
+        let permalink = Permalink::parse("/posts-{year}/{slug}/").unwrap();
+        assert_eq!(permalink.make("Foo", &time), "posts-2018/foo");
+        assert!(Permalink::parse("{year}/{title}").is_none());
+        assert!(Permalink::parse("{year").is_none());
+    }
+}
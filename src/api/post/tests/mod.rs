@@ -9,7 +9,9 @@ static CONTENT_MARKDOWN: &'static str = "# Title\n\nHello, Writus!\n\nBeep!";
 
 static CONTENT_DIF: &'static str = "Wow!";
 
+mod site;
 mod source;
+use self::site::Site;
 
 fn api() -> PostApi {
     use writium_cache::Cache;
@@ -20,16 +22,87 @@ fn api() -> PostApi {
 }
 fn indexed_api() -> PostApi {
     use api::Index;
-    use serde_json::Value as JsonValue;
     let index = Index::new("key", "integer", None);
     let mut api = api();
     api.set_entries_per_request(2);
-    index.write().unwrap().insert("/foo".into(), &JsonValue::Number(0.into()));
-    index.write().unwrap().insert("/bar".into(), &JsonValue::Number(1.into()));
-    index.write().unwrap().insert("/baz".into(), &JsonValue::Number(2.into()));
+    index.update("/foo", &json!({ "key": 0 }));
+    index.update("/bar", &json!({ "key": 1 }));
+    index.update("/baz", &json!({ "key": 2 }));
     api.set_index(index);
     api
 }
+fn multi_indexed_api() -> PostApi {
+    use api::{Index, Indices};
+    let mut api = api();
+    api.set_entries_per_request(3);
+    let by_num = Index::new("num", "+integer", None);
+    let by_title = Index::new("title", "-string", None);
+    let mut indices = Indices::new("by_num", by_num);
+    indices.insert("by_title", by_title);
+    indices.update("/foo", &json!({ "num": 0, "title": "a" }));
+    indices.update("/bar", &json!({ "num": 1, "title": "c" }));
+    indices.update("/baz", &json!({ "num": 2, "title": "b" }));
+    api.set_indices(indices);
+    api
+}
+fn filtered_api() -> PostApi {
+    use api::{FilterIndex, Index};
+    use api::observer::PostObserver;
+    let mut api = api();
+    api.set_entries_per_request(1);
+    let index = Index::new("key", "+integer", None);
+    let filter_index = FilterIndex::new();
+    for &(id, ref meta) in [
+        ("foo", json!({ "key": 0, "author": "alice", "tags": ["rust"] })),
+        ("bar", json!({ "key": 1, "author": "bob", "tags": ["rust"] })),
+        ("baz", json!({ "key": 2, "author": "alice", "series": "a" })),
+    ].iter() {
+        index.update(id, meta);
+        filter_index.update_metadata(id, meta);
+    }
+    api.set_index(index);
+    api.set_filter_index(filter_index);
+    api
+}
+fn scheduled_api() -> PostApi {
+    use api::{Index, Schedule};
+    use api::observer::PostObserver;
+    let mut api = api();
+    api.set_entries_per_request(2);
+    let index = Index::new("published", "-datetime", None);
+    let schedule = Schedule::new("published");
+    for &(id, published) in [
+        ("foo", "2999-01-01T00:00:00+08:00"),
+        ("bar", "2018-01-02T00:00:00+08:00"),
+        ("baz", "2018-01-01T00:00:00+08:00"),
+    ].iter() {
+        let meta = json!({ "published": published });
+        index.update(id, &meta);
+        schedule.update_metadata(id, &meta);
+    }
+    api.set_index(index);
+    api.set_schedule(schedule);
+    api
+}
+fn related_api() -> PostApi {
+    use api::{Related, Schedule};
+    use api::observer::PostObserver;
+    let mut api = api();
+    let related = Related::new("tags", 5);
+    let schedule = Schedule::new("published");
+    for &(id, published) in [
+        ("foo", "2018-01-01T00:00:00+08:00"),
+        ("bar", "2018-01-02T00:00:00+08:00"),
+        ("baz", "2999-01-01T00:00:00+08:00"),
+    ].iter() {
+        let meta = json!({ "published": published, "tags": ["rust"] });
+        related.update_metadata(id, &meta);
+        schedule.update_metadata(id, &meta);
+    }
+    api.set_related(related);
+    api.set_schedule(schedule);
+    api
+}
 
 #[test]
 fn fail_get_one() {
@@ -54,7 +127,7 @@ fn test_get_index() {
     let req = Request::new(Method::Get);
     let res = test_ok(&api, req);
     check_type(&res, "application", "json");
-    check_content(&res, r#"["/foo","/bar"]"#);
+    check_content(&res, r#"{"total":3,"from":0,"next":2,"items":[{"id":"/foo"},{"id":"/bar"}]}"#);
 }
 #[test]
 fn test_get_index_from() {
@@ -63,7 +136,170 @@ fn test_get_index_from() {
         .with_query("from=1");
     let res = test_ok(&api, req);
     check_type(&res, "application", "json");
-    check_content(&res, r#"["/bar","/baz"]"#);
+    check_content(&res, r#"{"total":3,"from":1,"next":null,"items":[{"id":"/bar"},{"id":"/baz"}]}"#);
+}
+#[test]
+fn test_get_index_limit() {
+    let api = indexed_api();
+    let req = Request::new(Method::Get)
+        .with_query("limit=1");
+    let res = test_ok(&api, req);
+    check_content(&res, r#"{"total":3,"from":0,"next":1,"items":[{"id":"/foo"}]}"#);
+    // Limited by `entries_per_request`.
+    let req = Request::new(Method::Get)
+        .with_query("limit=10");
+    let res = test_ok(&api, req);
+    check_content(&res, r#"{"total":3,"from":0,"next":2,"items":[{"id":"/foo"},{"id":"/bar"}]}"#);
+}
+#[test]
+fn fail_get_index_limit() {
+    let api = indexed_api();
+    let req = Request::new(Method::Get)
+        .with_query("limit=0");
+    let err = test_err(&api, req);
+    assert_eq!(err.status(), StatusCode::BadRequest);
+}
+#[test]
+fn test_get_index_from_max() {
+    let api = indexed_api();
+    let req = Request::new(Method::Get)
+        .with_query("from=18446744073709551615");
+    let res = test_ok(&api, req);
+    check_content(&res, r#"{"total":3,"from":18446744073709551615,"next":null,"items":[]}"#);
+}
+#[test]
+fn test_get_index_expand() {
+    use api::Index;
+    let mut api = api();
+    let index = Index::new("key", "integer", None);
+    index.update("foo", &json!({ "key": 0 }));
+    api.set_index(index);
+    let req = Request::new(Method::Get)
+        .with_query("expand=digest,stats");
+    let res = test_ok(&api, req);
+    check_content(&res, r#"{"total":1,"from":0,"next":null,"items":[{"id":"foo","digest":{"title":"Title","content":"<p>Hello, Writus!</p>\n"},"stats":{"words":5,"characters":30}}]}"#);
+}
+#[test]
+fn test_get_index_lang() {
+    use serde_json::Value as JsonValue;
+    use api::{Index, Translations};
+    let mut api = api();
+    let index = Index::new("key", "integer", None);
+    index.update("foo", &json!({ "key": 0 }));
+    api.set_index(index);
+    api.set_metadata_cache(cache_of(vec![
+        ("foo", json!({ "key": 0, "title": "Title" })),
+    ]));
+    let mut translations = Translations::new("en");
+    translations.insert("zh",
+        cache_of(vec![("foo", "# 标题\n\n你好".to_owned())]),
+        cache_of::<JsonValue>(vec![("foo", json!({ "title": "标题" }))]));
+    api.set_translations(translations);
+    let req = Request::new(Method::Get)
+        .with_query("expand=metadata,digest&lang=zh");
+    let res = test_ok(&api, req);
+    check_content(&res, r#"{"total":1,"from":0,"next":null,"items":[{"id":"foo","metadata":{"key":0,"title":"标题"},"digest":{"title":"标题","content":"<p>你好</p>\n"}}]}"#);
+    // Languages without translations fall back to the default one.
+    let req = Request::new(Method::Get)
+        .with_query("expand=metadata&lang=fr");
+    let res = test_ok(&api, req);
+    check_content(&res, r#"{"total":1,"from":0,"next":null,"items":[{"id":"foo","metadata":{"key":0,"title":"Title"}}]}"#);
+}
+#[test]
+fn fail_get_index_expand() {
+    let api = indexed_api();
+    let req = Request::new(Method::Get)
+        .with_query("expand=comments");
+    let err = test_err(&api, req);
+    assert_eq!(err.status(), StatusCode::BadRequest);
+}
+#[test]
+fn test_get_index_named() {
+    let api = multi_indexed_api();
+    let req = Request::new(Method::Get);
+    let res = test_ok(&api, req);
+    check_content(&res, r#"{"total":3,"from":0,"next":null,"items":[{"id":"/foo"},{"id":"/bar"},{"id":"/baz"}]}"#);
+    let req = Request::new(Method::Get)
+        .with_query("index=by_title");
+    let res = test_ok(&api, req);
+    check_content(&res, r#"{"total":3,"from":0,"next":null,"items":[{"id":"/bar"},{"id":"/baz"},{"id":"/foo"}]}"#);
+}
+#[test]
+fn fail_get_index_named() {
+    let api = multi_indexed_api();
+    let req = Request::new(Method::Get)
+        .with_query("index=by_nothing");
+    let err = test_err(&api, req);
+    assert_eq!(err.status(), StatusCode::NotFound);
+}
+#[test]
+fn test_get_index_filter() {
+    let api = filtered_api();
+    let get = |query: &str| {
+        let req = Request::new(Method::Get)
+            .with_query(query);
+        test_ok(&api, req)
+    };
+    check_content(&get("filter.author=alice"), r#"{"total":2,"from":0,"next":1,"items":[{"id":"foo"}]}"#);
+    check_content(&get("filter.author=alice&from=1"), r#"{"total":2,"from":1,"next":null,"items":[{"id":"baz"}]}"#);
+    check_content(&get("filter.tags=rust&after=1"), r#"{"total":1,"from":0,"next":null,"items":[{"id":"bar"}]}"#);
+    check_content(&get("has=series&before=2"), r#"{"total":0,"from":0,"next":null,"items":[]}"#);
+}
+#[test]
+fn fail_get_index_filter() {
+    let api = filtered_api();
+    let req = Request::new(Method::Get)
+        .with_query("before=never");
+    let err = test_err(&api, req);
+    assert_eq!(err.status(), StatusCode::BadRequest);
+    // Unknown parameters are not taken as filters.
+    let req = Request::new(Method::Get)
+        .with_query("author=alice");
+    let err = test_err(&api, req);
+    assert_eq!(err.status(), StatusCode::BadRequest);
+}
+#[test]
+fn test_get_index_scheduled() {
+    let api = scheduled_api();
+    let req = Request::new(Method::Get);
+    let res = test_ok(&api, req);
+    check_content(&res, r#"{"total":2,"from":0,"next":null,"items":[{"id":"bar"},{"id":"baz"}]}"#);
+    let req = Request::new(Method::Get)
+        .with_query("from=1");
+    let res = test_ok(&api, req);
+    check_content(&res, r#"{"total":2,"from":1,"next":null,"items":[{"id":"baz"}]}"#);
+    let req = Request::new(Method::Get)
+        .with_header(Authorization(Bearer { token: "PASSWORD".to_owned() }));
+    let res = test_ok(&api, req);
+    check_content(&res, r#"{"total":3,"from":0,"next":2,"items":[{"id":"foo"},{"id":"bar"}]}"#);
+}
+#[test]
+fn test_get_one_scheduled() {
+    let api = scheduled_api();
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["foo"]);
+    let err = test_err(&api, req);
+    assert_eq!(err.status(), StatusCode::NotFound);
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["foo"])
+        .with_header(Authorization(Bearer { token: "PASSWORD".to_owned() }));
+    let res = test_ok(&api, req);
+    check_content(&res, CONTENT_MARKDOWN);
+}
+#[test]
+fn test_get_one_related() {
+    let api = related_api();
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["foo"])
+        .with_query("related");
+    let res = test_ok(&api, req);
+    check_content(&res, r#"["bar"]"#);
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["foo"])
+        .with_query("related")
+        .with_header(Authorization(Bearer { token: "PASSWORD".to_owned() }));
+    let res = test_ok(&api, req);
+    check_content(&res, r#"["bar","baz"]"#);
 }
 
 #[test]
@@ -101,7 +337,616 @@ fn test_put() {
     check_type(&res, "text", "markdown");
     check_content(&res, CONTENT_DIF);
 }
+#[test]
+fn test_get_not_modified() {
+    use self::header::{ETag, IfNoneMatch};
+    let api = api();
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["foo"]);
+    let etag = test_ok(&api, req).header::<ETag>().unwrap().0.clone();
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["foo"])
+        .with_header(IfNoneMatch::Items(vec![etag]));
+    let res = test_ok(&api, req);
+    assert_eq!(res.status(), StatusCode::NotModified);
+}
+#[test]
+fn test_put_if_match() {
+    use self::header::{ETag, IfMatch};
+    let api = api();
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["foo"]);
+    let etag = test_ok(&api, req).header::<ETag>().unwrap().0.clone();
+    let req = Request::new(Method::Put)
+        .with_path_segs(&["foo"])
+        .with_header(Authorization(Bearer { token: "PASSWORD".to_owned() }))
+        .with_header(ContentType("text/markdown".parse().unwrap()))
+        .with_header(IfMatch::Items(vec![etag.clone()]))
+        .with_body(CONTENT_DIF);
+    let _ = test_ok(&api, req);
+    // The content has been changed since the old tag was given.
+    let req = Request::new(Method::Put)
+        .with_path_segs(&["foo"])
+        .with_header(Authorization(Bearer { token: "PASSWORD".to_owned() }))
+        .with_header(ContentType("text/markdown".parse().unwrap()))
+        .with_header(IfMatch::Items(vec![etag]))
+        .with_body(CONTENT_MARKDOWN);
+    let err = test_err(&api, req);
+    assert_eq!(err.status(), StatusCode::PreconditionFailed);
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["foo"]);
+    let res = test_ok(&api, req);
+    check_content(&res, CONTENT_DIF);
+}
+#[test]
+fn test_put_bundle() {
+    let mut api = api();
+    let mut exts = ::std::collections::HashMap::new();
+    exts.insert("png".to_owned(), "image/png".parse().unwrap());
+    api.set_allowed_exts(exts);
+    let put = |body: &str| Request::new(Method::Put)
+        .with_path_segs(&["bar"])
+        .with_header(Authorization(Bearer { token: "PASSWORD".to_owned() }))
+        .with_header(ContentType("multipart/form-data; boundary=XyZ"
+            .parse().unwrap()))
+        .with_body(body.to_owned());
+    // Nothing is written if any part is invalid.
+    let err = test_err(&api, put("--XyZ\r\n\
+        Content-Disposition: form-data; name=\"content.md\"\r\n\r\n\
+        Wow!\r\n\
+        --XyZ\r\n\
+        Content-Disposition: form-data; name=\"a.exe\"\r\n\r\n\
+        MZ\r\n\
+        --XyZ--"));
+    assert_eq!(err.status(), StatusCode::UnsupportedMediaType);
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["bar"]);
+    let err = test_err(&api, req);
+    assert_eq!(err.status(), StatusCode::NotFound);
+    let _ = test_ok(&api, put("--XyZ\r\n\
+        Content-Disposition: form-data; name=\"content.md\"\r\n\r\n\
+        Wow!\r\n\
+        --XyZ--"));
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["bar"]);
+    let res = test_ok(&api, req);
+    check_content(&res, CONTENT_DIF);
+}
+#[test]
+fn test_put_bundle_files() {
+    use std::fs::remove_dir_all;
+    use api::Index;
+    use model::History;
+    use serde_json::Value as JsonValue;
+    let dir = ::std::env::temp_dir()
+        .join(format!("writus-post-bundle-{}", ::std::process::id()));
+    let history = Arc::new(History::new(&dir.to_string_lossy(),
+        "metadata.json"));
+    let metadata_cache = cache_of::<JsonValue>(vec![]);
+    let resource_cache = cache_of::<Vec<u8>>(vec![]);
+    let index = Index::new("key", "+integer", None);
+    let mut api = api();
+    let mut exts = ::std::collections::HashMap::new();
+    exts.insert("png".to_owned(), "image/png".parse().unwrap());
+    api.set_allowed_exts(exts);
+    api.set_metadata_cache(metadata_cache.clone());
+    api.set_resource_cache(resource_cache.clone());
+    api.set_metadata_history(history.clone());
+    api.set_index(index.clone());
+    let req = Request::new(Method::Put)
+        .with_path_segs(&["bar"])
+        .with_header(Authorization(Bearer { token: "PASSWORD".to_owned() }))
+        .with_header(ContentType("multipart/form-data; boundary=XyZ"
+            .parse().unwrap()))
+        .with_body("--XyZ\r\n\
+            Content-Disposition: form-data; name=\"content.md\"\r\n\r\n\
+            Wow!\r\n\
+            --XyZ\r\n\
+            Content-Disposition: form-data; name=\"metadata.json\"\r\n\r\n\
+            {\"key\":1}\r\n\
+            --XyZ\r\n\
+            Content-Disposition: form-data; name=\"img\"; \
+            filename=\"img/a;b.png\"\r\n\
+            Content-Type: image/png\r\n\r\n\
+            PNG\r\n\
+            --XyZ--");
+    let _ = test_ok(&api, req);
+    let data = resource_cache.get("bar/img/a;b.png").unwrap().read().unwrap()
+        .clone();
+    assert_eq!(data, b"PNG".to_vec());
+    let meta = metadata_cache.get("bar").unwrap().read().unwrap().clone();
+    assert_eq!(meta, json!({ "key": 1 }));
+    assert_eq!(history.revisions("bar").unwrap().len(), 1);
+    assert_eq!(index.read().unwrap().rank("bar"), Some(0));
+    remove_dir_all(&dir).unwrap();
+}
+#[test]
+fn fail_put_bundle_rollback() {
+    use writium_cache::Cache;
+    use serde_json::Value as JsonValue;
+    use api::Translations;
+    let metadata_cache = cache_of(vec![("bar", json!({ "key": 0 }))]);
+    let mut api = api();
+    api.set_cache(Arc::new(Cache::new(3,
+        source::ReadOnlySource::<String>::new(vec![]))));
+    api.set_metadata_cache(metadata_cache.clone());
+    let translated_cache = cache_of::<JsonValue>(vec![]);
+    let mut translations = Translations::new("en");
+    translations.insert("zh", cache_of::<String>(vec![]),
+        translated_cache.clone());
+    api.set_translations(translations);
+    let req = Request::new(Method::Put)
+        .with_path_segs(&["bar"])
+        .with_header(Authorization(Bearer { token: "PASSWORD".to_owned() }))
+        .with_header(ContentType("multipart/form-data; boundary=XyZ"
+            .parse().unwrap()))
+        .with_body("--XyZ\r\n\
+            Content-Disposition: form-data; name=\"content.md\"\r\n\r\n\
+            Wow!\r\n\
+            --XyZ\r\n\
+            Content-Disposition: form-data; name=\"metadata.json\"\r\n\r\n\
+            {\"key\":1}\r\n\
+            --XyZ\r\n\
+            Content-Disposition: form-data; name=\"metadata.zh.json\"\r\n\
+            \r\n\
+            {\"key\":2}\r\n\
+            --XyZ--");
+    let err = test_err(&api, req);
+    assert_eq!(err.status(), StatusCode::InternalServerError);
+    // Files written before the content fails are restored.
+    let meta = metadata_cache.get("bar").unwrap().read().unwrap().clone();
+    assert_eq!(meta, json!({ "key": 0 }));
+    assert!(translated_cache.get("bar").is_err());
+}
+#[test]
+fn fail_put_bundle_translation_rollback() {
+    use writium_cache::Cache;
+    use serde_json::Value as JsonValue;
+    use api::Translations;
+    let mut api = api();
+    let translated_cache = cache_of::<JsonValue>(vec![]);
+    let mut translations = Translations::new("en");
+    translations.insert("zh", Arc::new(Cache::new(3,
+        source::ReadOnlySource::<String>::new(vec![]))),
+        translated_cache.clone());
+    api.set_translations(translations);
+    let req = Request::new(Method::Put)
+        .with_path_segs(&["foo"])
+        .with_header(Authorization(Bearer { token: "PASSWORD".to_owned() }))
+        .with_header(ContentType("multipart/form-data; boundary=XyZ"
+            .parse().unwrap()))
+        .with_body("--XyZ\r\n\
+            Content-Disposition: form-data; name=\"content.md\"\r\n\r\n\
+            Wow!\r\n\
+            --XyZ\r\n\
+            Content-Disposition: form-data; name=\"metadata.zh.json\"\r\n\
+            \r\n\
+            {\"key\":2}\r\n\
+            --XyZ\r\n\
+            Content-Disposition: form-data; name=\"content.zh.md\"\r\n\
+            \r\n\
+            Wow!\r\n\
+            --XyZ--");
+    let err = test_err(&api, req);
+    assert_eq!(err.status(), StatusCode::InternalServerError);
+    // Files written before the translation fails are restored, while the
+    // content is left untouched.
+    assert!(translated_cache.get("foo").is_err());
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["foo"]);
+    check_content(&test_ok(&api, req), CONTENT_MARKDOWN);
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["foo"])
+        .with_query("lang=zh");
+    check_content(&test_ok(&api, req), CONTENT_MARKDOWN);
+}
+#[test]
+fn test_post_new() {
+    use self::header::Location;
+    let api = api();
+    let post = |ty: &str, body: &str| Request::new(Method::Post)
+        .with_header(Authorization(Bearer { token: "PASSWORD".to_owned() }))
+        .with_header(ContentType(ty.parse().unwrap()))
+        .with_body(body.to_owned());
+    let res = test_ok(&api, post("text/markdown", CONTENT_MARKDOWN));
+    assert_eq!(res.status(), StatusCode::Created);
+    assert_eq!(res.header::<Location>().unwrap().to_string(),
+        "/api/v1/posts/title");
+    // Paths already taken are numbered.
+    let res = test_ok(&api, post("application/json",
+        r##"{"content":"# Title\n\nAgain!"}"##));
+    assert_eq!(res.header::<Location>().unwrap().to_string(),
+        "/api/v1/posts/title-2");
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["title-2"]);
+    let res = test_ok(&api, req);
+    check_content(&res, "# Title\n\nAgain!");
+    let err = test_err(&api, post("application/json", r#"{"metadata":{}}"#));
+    assert_eq!(err.status(), StatusCode::BadRequest);
+    let err = test_err(&api, Request::new(Method::Post)
+        .with_header(Authorization(Bearer { token: "PASSWORD".to_owned() }))
+        .with_body(CONTENT_MARKDOWN));
+    assert_eq!(err.status(), StatusCode::BadRequest);
+}
+#[test]
+fn test_post_new_concurrent() {
+    use std::collections::HashSet;
+    use std::thread;
+    use self::header::Location;
+    let mut api = api();
+    api.set_cache(cache_of::<String>(vec![]));
+    let api = Arc::new(api);
+    let threads: Vec<_> = (0..8).map(|_| {
+        let api = api.clone();
+        thread::spawn(move || {
+            let req = Request::new(Method::Post)
+                .with_header(Authorization(Bearer {
+                    token: "PASSWORD".to_owned()
+                }))
+                .with_header(ContentType("text/markdown".parse().unwrap()))
+                .with_body(CONTENT_MARKDOWN);
+            let res = test_ok(&*api, req);
+            res.header::<Location>().unwrap().to_string()
+        })
+    }).collect();
+    let paths: HashSet<String> = threads.into_iter()
+        .map(|x| x.join().unwrap())
+        .collect();
+    assert_eq!(paths.len(), 8);
+}
+#[test]
+fn test_post_new_rollback() {
+    use writium_cache::Cache;
+    use serde_json::Value as JsonValue;
+    let mut api = api();
+    api.set_metadata_cache(Arc::new(Cache::new(3,
+        source::ReadOnlySource::<JsonValue>::new(vec![]))));
+    let req = Request::new(Method::Post)
+        .with_header(Authorization(Bearer { token: "PASSWORD".to_owned() }))
+        .with_header(ContentType("application/json".parse().unwrap()))
+        .with_body(r##"{"content":"# Title","metadata":{"key":0}}"##);
+    let err = test_err(&api, req);
+    assert_eq!(err.status(), StatusCode::InternalServerError);
+    // The content written before the failure is removed.
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["title"]);
+    let err = test_err(&api, req);
+    assert_eq!(err.status(), StatusCode::NotFound);
+}
+#[test]
+fn test_put_timestamps() {
+    use serde_json::Value as JsonValue;
+    use api::Index;
+    let mut api = api();
+    let metadata_cache = cache_of::<JsonValue>(vec![]);
+    let index = Index::new("published", "-datetime", None);
+    api.set_metadata_cache(metadata_cache.clone());
+    api.set_index(index.clone());
+    api.set_created_key("published");
+    api.set_modified_key("modified");
+    let put = || Request::new(Method::Put)
+        .with_path_segs(&["bar"])
+        .with_header(Authorization(Bearer { token: "PASSWORD".to_owned() }))
+        .with_header(ContentType("text/markdown".parse().unwrap()))
+        .with_body(CONTENT_DIF);
+    let _ = test_ok(&api, put());
+    let meta = metadata_cache.get("bar").unwrap().read().unwrap().clone();
+    assert!(meta["published"].is_string());
+    assert_eq!(meta["published"], meta["modified"]);
+    assert_eq!(index.read().unwrap().rank("bar"), Some(0));
+    // The creation time is kept.
+    let _ = test_ok(&api, put());
+    let new_meta = metadata_cache.get("bar").unwrap().read().unwrap().clone();
+    assert_eq!(new_meta["published"], meta["published"]);
+    assert!(new_meta["modified"].is_string());
+}
+#[test]
+fn test_put_timestamps_history() {
+    use std::fs::remove_dir_all;
+    use serde_json::Value as JsonValue;
+    use model::History;
+    let dir = ::std::env::temp_dir()
+        .join(format!("writus-post-stamps-{}", ::std::process::id()));
+    let history = Arc::new(History::new(&dir.to_string_lossy(),
+        "metadata.json"));
+    let mut api = api();
+    api.set_metadata_cache(cache_of::<JsonValue>(vec![]));
+    api.set_metadata_history(history.clone());
+    api.set_created_key("published");
+    let put = || Request::new(Method::Put)
+        .with_path_segs(&["bar"])
+        .with_header(Authorization(Bearer { token: "PASSWORD".to_owned() }))
+        .with_header(ContentType("text/markdown".parse().unwrap()))
+        .with_body(CONTENT_DIF);
+    let _ = test_ok(&api, put());
+    assert_eq!(history.revisions("bar").unwrap().len(), 1);
+    // Nothing is recorded if the metadata is not changed.
+    let _ = test_ok(&api, put());
+    assert_eq!(history.revisions("bar").unwrap().len(), 1);
+    remove_dir_all(&dir).unwrap();
+}
+#[test]
+fn test_revisions() {
+    use std::fs::remove_dir_all;
+    use model::History;
+    use self::header::From;
+    let dir = ::std::env::temp_dir()
+        .join(format!("writus-post-revisions-{}", ::std::process::id()));
+    let mut api = api();
+    api.set_history(Arc::new(History::new(&dir.to_string_lossy(),
+        "content.md")));
+    let req = Request::new(Method::Put)
+        .with_path_segs(&["foo"])
+        .with_header(Authorization(Bearer { token: "PASSWORD".to_owned() }))
+        .with_header(ContentType("text/markdown".parse().unwrap()))
+        .with_header(From("alice@example.com".to_owned()))
+        .with_body(CONTENT_DIF);
+    let _ = test_ok(&api, req);
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["foo"])
+        .with_query("revisions");
+    let res = test_ok(&api, req);
+    check_type(&res, "application", "json");
+    let revs: ::serde_json::Value = ::serde_json::from_str(res.to_str().unwrap())
+        .unwrap();
+    assert_eq!(revs[0]["revision"], 1);
+    assert_eq!(revs[0]["author"], ::serde_json::Value::Null);
+    assert_eq!(revs[1]["author"], "alice@example.com");
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["foo"])
+        .with_query("revision=1");
+    let res = test_ok(&api, req);
+    check_content(&res, CONTENT_MARKDOWN);
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["foo"])
+        .with_query("diff=1,2");
+    let res = test_ok(&api, req);
+    check_type(&res, "text", "x-diff");
+    check_content(&res, "--- foo@1\n+++ foo@2\n@@ -1,5 +1,1 @@\n\
+        -# Title\n-\n-Hello, Writus!\n-\n-Beep!\n+Wow!\n");
+    // Restore the original content.
+    let req = Request::new(Method::Post)
+        .with_path_segs(&["foo"])
+        .with_query("restore=1");
+    let err = test_err(&api, req);
+    assert_eq!(err.status(), StatusCode::Unauthorized);
+    let req = Request::new(Method::Post)
+        .with_path_segs(&["foo"])
+        .with_query("restore=1")
+        .with_header(Authorization(Bearer { token: "PASSWORD".to_owned() }));
+    let _ = test_ok(&api, req);
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["foo"]);
+    let res = test_ok(&api, req);
+    check_content(&res, CONTENT_MARKDOWN);
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["foo"])
+        .with_query("diff=1");
+    let res = test_ok(&api, req);
+    check_content(&res, "");
+    remove_dir_all(&dir).unwrap();
+}
+#[test]
+fn test_move() {
+    use self::header::Location;
+    use view::PostView;
+    let site = Site::new("move");
+    let api = site.api();
+    let req = Request::new(Method::Post)
+        .with_path_segs(&["foo"])
+        .with_query("move_to=/bar/&redirect")
+        .with_header(Authorization(Bearer { token: "PASSWORD".to_owned() }));
+    let res = test_ok(&api, req);
+    assert_eq!(res.status(), StatusCode::Created);
+    assert_eq!(res.header::<Location>().unwrap().to_string(),
+        "/api/v1/posts/bar");
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["bar"]);
+    let res = test_ok(&api, req);
+    check_content(&res, CONTENT_MARKDOWN);
+    // Everything of the article is moved.
+    assert_eq!(*site.metadata_cache.get("bar").unwrap().read().unwrap(),
+        json!({ "key": 0 }));
+    assert_eq!(site.comment_cache.get("bar").unwrap().read().unwrap().len(), 1);
+    assert!(site.comment_cache.get("foo").is_err());
+    assert_eq!(*site.resource_cache.get("bar/img/cover.png").unwrap()
+        .read().unwrap(), b"PNG".to_vec());
+    assert!(site.resource_cache.get("foo/img/cover.png").is_err());
+    assert_eq!(site.index.read().unwrap().get_range(0, 5), vec!["bar"]);
+    // The original path redirects to the new one.
+    assert_eq!(*site.metadata_cache.get("foo").unwrap().read().unwrap(),
+        json!({ "redirect": "bar" }));
+    let mut view = PostView::new();
+    view.set_post_cache(site.post_cache.clone());
+    view.set_metadata_cache(site.metadata_cache.clone());
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["foo"]);
+    let res = test_ok(&view, req);
+    assert_eq!(res.status(), StatusCode::MovedPermanently);
+    assert_eq!(res.header::<Location>().unwrap().to_string(), "/posts/bar");
+    // The redirect is replaced when the article is moved back.
+    let req = Request::new(Method::Post)
+        .with_path_segs(&["bar"])
+        .with_query("move_to=foo")
+        .with_header(Authorization(Bearer { token: "PASSWORD".to_owned() }));
+    let _ = test_ok(&api, req);
+    assert_eq!(*site.metadata_cache.get("foo").unwrap().read().unwrap(),
+        json!({ "key": 0 }));
+    assert_eq!(site.index.read().unwrap().get_range(0, 5), vec!["foo"]);
+}
+#[test]
+fn fail_move() {
+    let site = Site::new("fail-move");
+    let api = site.api();
+    let req = Request::new(Method::Put)
+        .with_path_segs(&["bar"])
+        .with_header(Authorization(Bearer { token: "PASSWORD".to_owned() }))
+        .with_header(ContentType("text/markdown".parse().unwrap()))
+        .with_body(CONTENT_DIF);
+    let _ = test_ok(&api, req);
+    *site.comment_cache.create("baz").unwrap().write().unwrap() =
+        site.comment_cache.get("foo").unwrap().read().unwrap().clone();
+    for &(query, status) in [
+        ("move_to=bar", StatusCode::Conflict),
+        // Comments left at the destination are not overwritten.
+        ("move_to=baz", StatusCode::Conflict),
+        ("move_to=foo/baz", StatusCode::BadRequest),
+        ("move_to=.baz", StatusCode::BadRequest),
+    ].iter() {
+        let req = Request::new(Method::Post)
+            .with_path_segs(&["foo"])
+            .with_query(query)
+            .with_header(Authorization(Bearer { token: "PASSWORD".to_owned() }));
+        let err = test_err(&api, req);
+        assert_eq!(err.status(), status);
+    }
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["foo"]);
+    let res = test_ok(&api, req);
+    check_content(&res, CONTENT_MARKDOWN);
+}
+#[test]
+fn fail_move_rollback() {
+    use writium_cache::Cache;
+    use model::comment::Comments;
+    let site = Site::new("move-rollback");
+    let mut api = site.api();
+    // Comments cannot be moved.
+    let comments = site.comment_cache.get("foo").unwrap().read().unwrap()
+        .clone();
+    api.set_comment_cache(Arc::new(Cache::new(10,
+        source::ReadOnlySource::<Comments>::new(vec![("foo", comments)]))));
+    let req = Request::new(Method::Post)
+        .with_path_segs(&["foo"])
+        .with_query("move_to=bar")
+        .with_header(Authorization(Bearer { token: "PASSWORD".to_owned() }));
+    let err = test_err(&api, req);
+    assert_eq!(err.status(), StatusCode::InternalServerError);
+    // Files moved before the failure are moved back.
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["foo"]);
+    let res = test_ok(&api, req);
+    check_content(&res, CONTENT_MARKDOWN);
+    assert!(site.post_cache.get("bar").is_err());
+    assert_eq!(*site.metadata_cache.get("foo").unwrap().read().unwrap(),
+        json!({ "key": 0 }));
+    assert!(site.metadata_cache.get("bar").is_err());
+    assert_eq!(*site.resource_cache.get("foo/img/cover.png").unwrap()
+        .read().unwrap(), b"PNG".to_vec());
+    assert_eq!(site.index.read().unwrap().get_range(0, 5), vec!["foo"]);
+}
 
+#[test]
+fn test_translations() {
+    use writium_cache::Cache;
+    use serde_json::Value as JsonValue;
+    use self::header::{qitem, AcceptLanguage, Vary};
+    use api::Translations;
+    let mut api = api();
+    let mut translations = Translations::new("en");
+    translations.insert("zh",
+        Arc::new(Cache::new(3, source::MockSource::empty())),
+        cache_of::<JsonValue>(vec![]));
+    api.set_translations(translations);
+    let put = |lang: &str| Request::new(Method::Put)
+        .with_path_segs(&["foo"])
+        .with_query(&format!("lang={}", lang))
+        .with_header(Authorization(Bearer { token: "PASSWORD".to_owned() }))
+        .with_header(ContentType("text/markdown".parse().unwrap()))
+        .with_body(CONTENT_DIF);
+    let _ = test_ok(&api, put("zh"));
+    let err = test_err(&api, put("fr"));
+    assert_eq!(err.status(), StatusCode::BadRequest);
+    let get = || Request::new(Method::Get)
+        .with_path_segs(&["foo"]);
+    check_content(&test_ok(&api, get().with_query("lang=zh")), CONTENT_DIF);
+    check_content(&test_ok(&api, get()
+        .with_header(AcceptLanguage(vec![qitem("zh-CN".parse().unwrap())]))),
+        CONTENT_DIF);
+    // Content in the default language is neither overwritten nor hidden.
+    let res = test_ok(&api, get());
+    check_content(&res, CONTENT_MARKDOWN);
+    assert_eq!(res.header::<Vary>().map(|x| x.to_string()),
+        Some("Accept-Language".to_owned()));
+    check_content(&test_ok(&api, get().with_query("lang=fr")),
+        CONTENT_MARKDOWN);
+    let req = Request::new(Method::Delete)
+        .with_path_segs(&["foo"])
+        .with_query("dry_run")
+        .with_header(Authorization(Bearer { token: "PASSWORD".to_owned() }));
+    let res = test_ok(&api, req);
+    check_content(&res, concat!(
+        r#"{"id":"foo","content":true,"metadata":false,"comments":false,"#,
+        r#""resources":[],"revisions":false,"translations":["zh"]}"#));
+}
+#[test]
+fn test_translation_history() {
+    use std::fs::remove_dir_all;
+    use serde_json::Value as JsonValue;
+    use api::Translations;
+    use model::History;
+    let dir = ::std::env::temp_dir()
+        .join(format!("writus-post-translation-{}", ::std::process::id()));
+    let history = Arc::new(History::new(&dir.to_string_lossy(),
+        "content.zh.md"));
+    let mut api = api();
+    let mut translations = Translations::new("en");
+    translations.insert("zh", cache_of::<String>(vec![]),
+        cache_of::<JsonValue>(vec![]));
+    translations.set_history("zh", history.clone());
+    api.set_translations(translations);
+    let put = |text: &str| Request::new(Method::Put)
+        .with_path_segs(&["foo"])
+        .with_query("lang=zh")
+        .with_header(Authorization(Bearer { token: "PASSWORD".to_owned() }))
+        .with_header(ContentType("text/markdown".parse().unwrap()))
+        .with_body(text.to_owned());
+    let _ = test_ok(&api, put("# 标题"));
+    let _ = test_ok(&api, put(CONTENT_DIF));
+    let revisions = history.revisions("foo").unwrap();
+    assert_eq!(revisions.len(), 2);
+    assert_eq!(history.get("foo", 1).unwrap(), "# 标题");
+    assert_eq!(history.get("foo", 2).unwrap(), CONTENT_DIF);
+    remove_dir_all(&dir).unwrap();
+}
+#[test]
+fn test_delete_dry_run() {
+    let api = api();
+    let req = Request::new(Method::Delete)
+        .with_path_segs(&["foo"])
+        .with_query("dry_run")
+        .with_header(Authorization(Bearer { token: "PASSWORD".to_owned() }));
+    let res = test_ok(&api, req);
+    check_type(&res, "application", "json");
+    check_content(&res, concat!(
+        r#"{"id":"foo","content":true,"metadata":false,"comments":false,"#,
+        r#""resources":[],"revisions":false,"translations":[]}"#));
+    // Nothing is removed in a dry run.
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["foo"]);
+    let res = test_ok(&api, req);
+    check_content(&res, CONTENT_MARKDOWN);
+}
+#[test]
+fn test_delete_cascade() {
+    let site = Site::new("delete");
+    let api = site.api();
+    let req = Request::new(Method::Delete)
+        .with_path_segs(&["foo"])
+        .with_header(Authorization(Bearer { token: "PASSWORD".to_owned() }));
+    let res = test_ok(&api, req);
+    check_content(&res, concat!(
+        r#"{"id":"foo","content":true,"metadata":true,"comments":true,"#,
+        r#""resources":["foo/img/cover.png"],"revisions":false,"#,
+        r#""translations":[]}"#));
+    assert!(site.post_cache.get("foo").is_err());
+    assert!(site.metadata_cache.get("foo").is_err());
+    assert!(site.comment_cache.get("foo").is_err());
+    assert!(site.resource_cache.get("foo/img/cover.png").is_err());
+    assert_eq!(site.index.read().unwrap().rank("foo"), None);
+    // The article directory is removed once it's empty.
+    assert!(!site.dir.join("foo").exists());
+}
 #[test]
 fn fail_delete_auth() {
     let api = api();
@@ -0,0 +1,68 @@
+use std::fs::{create_dir_all, remove_dir_all, File};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+use serde_json::Value as JsonValue;
+use writium_cache::Cache;
+use api::{Index, PostApi};
+use auth::SimpleAuthority;
+use model::comment::Comments;
+use model::{CommentSource, MetadataSource, PostSource, ResourceSource};
+
+/// Articles in a temporary directory, accessed through sources of every kind
+/// of files. Article `foo` has metadata, a comment and a resource. The
+/// directory is removed once the site is dropped.
+pub struct Site {
+    pub dir: PathBuf,
+    pub post_cache: Arc<Cache<String>>,
+    pub metadata_cache: Arc<Cache<JsonValue>>,
+    pub comment_cache: Arc<Cache<Comments>>,
+    pub resource_cache: Arc<Cache<Vec<u8>>>,
+    pub index: Index,
+}
+impl Site {
+    pub fn new(name: &str) -> Site {
+        let dir = ::std::env::temp_dir()
+            .join(format!("writus-post-{}-{}", name, ::std::process::id()));
+        create_dir_all(dir.join("foo/img")).unwrap();
+        for &(path, data) in [
+            ("foo/content.md", super::CONTENT_MARKDOWN),
+            ("foo/metadata.json", r#"{"key":0}"#),
+            ("foo/comments.json",
+                r#"{"0":{"metadata":{},"content":"Wow!"}}"#),
+            ("foo/img/cover.png", "PNG"),
+        ].iter() {
+            File::create(dir.join(path)).unwrap()
+                .write_all(data.as_bytes()).unwrap();
+        }
+        let path = dir.to_string_lossy().into_owned();
+        let index = Index::new("key", "integer", None);
+        index.update("foo", &json!({ "key": 0 }));
+        Site {
+            post_cache: Arc::new(Cache::new(10, PostSource::new(&path))),
+            metadata_cache: Arc::new(Cache::new(10,
+                MetadataSource::new(&path))),
+            comment_cache: Arc::new(Cache::new(10, CommentSource::new(&path))),
+            resource_cache: Arc::new(Cache::new(10,
+                ResourceSource::new(&path))),
+            index: index,
+            dir: dir,
+        }
+    }
+    pub fn api(&self) -> PostApi {
+        let mut api = PostApi::new();
+        api.set_auth(Arc::new(SimpleAuthority::new("PASSWORD")));
+        api.set_published_dir(&self.dir.to_string_lossy());
+        api.set_cache(self.post_cache.clone());
+        api.set_metadata_cache(self.metadata_cache.clone());
+        api.set_comment_cache(self.comment_cache.clone());
+        api.set_resource_cache(self.resource_cache.clone());
+        api.set_index(self.index.clone());
+        api
+    }
+}
+impl Drop for Site {
+    fn drop(&mut self) {
+        let _ = remove_dir_all(&self.dir);
+    }
+}
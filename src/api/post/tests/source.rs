@@ -10,6 +10,9 @@ impl MockSource {
         map.insert("foo".to_owned(), super::CONTENT_MARKDOWN.to_owned());
         MockSource(Mutex::new(map))
     }
+    pub fn empty() -> MockSource {
+        MockSource(Mutex::new(HashMap::new()))
+    }
 }
 impl CacheSource for MockSource {
     type Value = String;
@@ -32,3 +35,27 @@ impl CacheSource for MockSource {
         Ok(())
     }
 }
+
+/// Source of items which cannot be created.
+pub struct ReadOnlySource<T>(HashMap<String, T>);
+impl<T> ReadOnlySource<T> {
+    pub fn new(items: Vec<(&str, T)>) -> ReadOnlySource<T> {
+        let map = items.into_iter()
+            .map(|(id, item)| (id.to_owned(), item))
+            .collect();
+        ReadOnlySource(map)
+    }
+}
+impl<T: 'static + Clone + Send + Sync> CacheSource for ReadOnlySource<T> {
+    type Value = T;
+    fn load(&self, id: &str, create: bool) -> Result<T> {
+        match self.0.get(id) {
+            Some(item) => Ok(item.clone()),
+            None if create => Err(Error::internal("Read-only.")),
+            None => Err(Error::not_found("x")),
+        }
+    }
+    fn remove(&self, _id: &str) -> Result<()> {
+        Ok(())
+    }
+}
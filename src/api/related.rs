@@ -0,0 +1,286 @@
+///! Related articles.
+///! Articles sharing more tags are more related. Articles sharing the same
+///! number of tags are compared by TF-IDF similarity of their contents.
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::thread;
+use std::time::Duration;
+use serde_json::Value as JsonValue;
+use super::index::is_indexable;
+use super::observer::PostObserver;
+use super::search::text;
+
+/// Changes are collected for this period before related articles are computed
+/// again.
+const REFRESH_DELAY_MS: u64 = 500;
+
+#[derive(Clone, Default, Deserialize, Serialize)]
+struct Document {
+    tags: BTreeSet<String>,
+    /// Number of occurrences of each term in content.
+    terms: HashMap<String, usize>,
+    /// Indexable articles can be related to others.
+    indexable: bool,
+}
+
+/// TF-IDF vectors of indexable documents, normalized to unit length.
+fn vectors(docs: &HashMap<String, Document>)
+    -> HashMap<&str, HashMap<&str, f64>> {
+    let mut dfs: HashMap<&str, usize> = HashMap::new();
+    let docs: Vec<(&str, &Document)> = docs.iter()
+        .filter(|&(_, doc)| doc.indexable)
+        .map(|(id, doc)| (&id[..], doc))
+        .collect();
+    for &(_, doc) in docs.iter() {
+        for term in doc.terms.keys() {
+            *dfs.entry(term).or_insert(0) += 1;
+        }
+    }
+    let n = docs.len() as f64;
+    docs.into_iter()
+        .map(|(id, doc)| {
+            let mut vec: HashMap<&str, f64> = doc.terms.iter()
+                .map(|(term, &tf)| {
+                    let idf = (n / dfs[&term[..]] as f64).ln();
+                    (&term[..], tf as f64 * idf)
+                })
+                .filter(|&(_, weight)| weight > 0.0)
+                .collect();
+            let norm = vec.values().map(|x| x * x).sum::<f64>().sqrt();
+            for weight in vec.values_mut() {
+                *weight /= norm;
+            }
+            (id, vec)
+        })
+        .collect()
+}
+/// At most `count` related articles of each indexable document, most related
+/// first. Only indexable documents are related to each other.
+fn compute(docs: &HashMap<String, Document>, count: usize)
+    -> HashMap<String, Vec<String>> {
+    fn similarity(a: &HashMap<&str, f64>, b: &HashMap<&str, f64>) -> f64 {
+        let (a, b) = if a.len() < b.len() { (a, b) } else { (b, a) };
+        a.iter()
+            .filter_map(|(term, x)| b.get(term).map(|y| x * y))
+            .sum()
+    }
+    let vectors = vectors(docs);
+    vectors.iter()
+        .map(|(id, vec)| {
+            let tags = &docs[*id].tags;
+            let mut scores: Vec<(usize, f64, &str)> = vectors.iter()
+                .filter(|&(other, _)| other != id)
+                .map(|(other, other_vec)| {
+                    let overlap = docs[*other].tags
+                        .intersection(tags)
+                        .count();
+                    (overlap, similarity(vec, other_vec), *other)
+                })
+                .filter(|&(overlap, sim, _)| overlap > 0 || sim > 0.0)
+                .collect();
+            scores.sort_by(|a, b| b.0.cmp(&a.0)
+                .then_with(|| b.1.partial_cmp(&a.1).unwrap())
+                .then_with(|| a.2.cmp(b.2)));
+            let related = scores.into_iter()
+                .take(count)
+                .map(|(_, _, other)| other.to_owned())
+                .collect();
+            (id.to_string(), related)
+        })
+        .collect()
+}
+
+/// Related articles of all the articles. Results are computed in a background
+/// thread, from a snapshot of the documents, after they're changed, so that
+/// neither readers nor writers wait for the computation.
+#[derive(Clone, Default)]
+pub struct Related {
+    /// Metadata key of tags.
+    key: String,
+    /// Maximal number of related articles of each article.
+    count: usize,
+    docs: Arc<RwLock<HashMap<String, Document>>>,
+    /// Related articles of each article, most related first.
+    results: Arc<RwLock<HashMap<String, Vec<String>>>>,
+    /// Whether results are outdated, notified when documents are changed.
+    dirty: Arc<(Mutex<bool>, Condvar)>,
+    /// Held while computing, so that newer results are not overwritten by
+    /// older ones.
+    computing: Arc<Mutex<()>>,
+}
+impl Related {
+    /// Make a new collection of related articles by tags in metadata key
+    /// `key`. At most `count` related articles are kept for each article.
+    pub fn new(key: &str, count: usize) -> Related {
+        Related {
+            key: key.to_owned(),
+            count: count,
+            ..Related::default()
+        }
+    }
+    fn mark_dirty(&self) {
+        let (ref dirty, ref cvar) = *self.dirty;
+        *dirty.lock().unwrap() = true;
+        cvar.notify_all();
+    }
+    /// Compute related articles of all articles, if any article has been
+    /// changed since the last computation.
+    pub fn refresh(&self) {
+        let _computing = self.computing.lock().unwrap();
+        {
+            let mut dirty = self.dirty.0.lock().unwrap();
+            if !*dirty {
+                return
+            }
+            *dirty = false;
+        }
+        // Changes made from now on mark the results dirty again.
+        let docs = self.docs.read().unwrap().clone();
+        info!("Computing related articles.");
+        let results = compute(&docs, self.count);
+        *self.results.write().unwrap() = results;
+    }
+    /// Refresh results in a new thread whenever articles are changed.
+    pub fn spawn(&self) {
+        let related = self.clone();
+        thread::spawn(move || loop {
+            {
+                let (ref dirty, ref cvar) = *related.dirty;
+                let mut guard = dirty.lock().unwrap();
+                while !*guard {
+                    guard = cvar.wait(guard).unwrap();
+                }
+            }
+            thread::sleep(Duration::from_millis(REFRESH_DELAY_MS));
+            related.refresh();
+        });
+    }
+    /// Related articles of article `id`, most related first. Results might be
+    /// computed before the latest changes, but articles no longer indexable
+    /// are never included.
+    pub fn related(&self, id: &str) -> Vec<String> {
+        let docs = self.docs.read().unwrap();
+        let indexable = |id: &str| docs.get(id)
+            .map(|doc| doc.indexable)
+            .unwrap_or(false);
+        if !indexable(id) {
+            return Vec::new()
+        }
+        self.results.read().unwrap().get(id)
+            .map(|related| related.iter()
+                .filter(|x| indexable(&x[..]))
+                .cloned()
+                .collect())
+            .unwrap_or_default()
+    }
+}
+impl PostObserver for Related {
+    fn update_metadata(&self, id: &str, meta: &JsonValue) {
+        let tags = match meta.get(&self.key) {
+            Some(&JsonValue::Array(ref tags)) => tags.iter()
+                .filter_map(|x| x.as_str())
+                .map(|x| x.to_owned())
+                .collect(),
+            Some(&JsonValue::String(ref tag)) => Some(tag.to_owned())
+                .into_iter()
+                .collect(),
+            _ => BTreeSet::new(),
+        };
+        {
+            let mut docs = self.docs.write().unwrap();
+            let doc = docs.entry(id.to_owned())
+                .or_insert_with(Document::default);
+            doc.tags = tags;
+            doc.indexable = is_indexable(meta);
+        }
+        self.mark_dirty();
+    }
+    fn update_content(&self, id: &str, content: &str) {
+        let mut terms = HashMap::new();
+        for term in text::tokenize(content) {
+            *terms.entry(term).or_insert(0) += 1;
+        }
+        self.docs.write().unwrap()
+            .entry(id.to_owned())
+            .or_insert_with(Document::default)
+            .terms = terms;
+        self.mark_dirty();
+    }
+    fn remove(&self, id: &str) {
+        self.docs.write().unwrap().remove(id);
+        self.results.write().unwrap().remove(id);
+        self.mark_dirty();
+    }
+    fn dump(&self, id: &str) -> JsonValue {
+        match self.docs.read().unwrap().get(id) {
+            Some(doc) => json!(doc),
+            None => JsonValue::Null,
+        }
+    }
+    fn restore(&self, id: &str, state: &JsonValue) {
+        if let Ok(doc) = ::serde_json::from_value::<Document>(state.clone()) {
+            self.docs.write().unwrap().insert(id.to_owned(), doc);
+            self.mark_dirty();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use api::observer::PostObserver;
+    use super::Related;
+
+    fn related() -> Related {
+        let related = Related::new("tags", 2);
+        for &(id, ref tags, content) in [
+            ("foo", vec!["rust", "web"], "Writus serves blogs."),
+            ("bar", vec!["rust", "web"], "Hyper serves requests."),
+            ("baz", vec!["rust"], "Borrow checker."),
+            ("qux", vec![], "Blogs served by Writus are fast."),
+            ("quux", vec!["cooking"], "Pasta recipes."),
+        ].iter() {
+            related.update_metadata(id, &json!({ "tags": tags }));
+            related.update_content(id, content);
+        }
+        related.refresh();
+        related
+    }
+
+    #[test]
+    fn test_tags() {
+        let related = related();
+        assert_eq!(related.related("bar"), vec!["foo", "baz"]);
+        assert!(related.related("quux").is_empty());
+    }
+    #[test]
+    fn test_content() {
+        let related = related();
+        // Shares three terms with `foo` but only one with `bar`.
+        assert_eq!(related.related("qux"), vec!["foo", "bar"]);
+    }
+    #[test]
+    fn test_update() {
+        let related = related();
+        related.update_metadata("baz", &json!({ "tags": ["rust", "web"] }));
+        related.update_metadata("bar", &json!({ "noIndex": true }));
+        // Articles no longer indexable are excluded before results are
+        // computed again.
+        assert_eq!(related.related("foo"), vec!["baz"]);
+        assert!(related.related("bar").is_empty());
+        related.refresh();
+        assert_eq!(related.related("foo"), vec!["baz", "qux"]);
+        related.remove("baz");
+        related.refresh();
+        assert_eq!(related.related("foo"), vec!["qux"]);
+    }
+    #[test]
+    fn test_not_indexable() {
+        let related = related();
+        related.update_metadata("baz", &json!({ "tags": ["rust"], "draft": true }));
+        related.refresh();
+        assert!(related.related("baz").is_empty());
+        let related = related.related("bar");
+        assert_eq!(related[0], "foo");
+        assert!(!related.contains(&"baz".to_owned()));
+    }
+}
@@ -5,6 +5,9 @@ use self::header::ContentType;
 use writium::hyper::mime::Mime;
 use writium_auth::{Authority, DumbAuthority};
 use writium_cache::{Cache, DumbCacheSource};
+use super::etag::{check_match, etag_of, respond};
+use super::draft::Drafts;
+use super::schedule::Schedule;
 
 const ERR_MISSING_CONTENT_TYPE: &'static str = "Content type should be denoted \
     for verification use.";
@@ -13,12 +16,15 @@ const ERR_MIME_NOT_FOUND: &'static str = "No corresponding MIME matches the \
     prevented from being transferred.";
 const ERR_MIME_EXT_MISMATCH: &'static str = "Path extension doesn't accord \
     with content type denoted.";
+const ERR_NOT_PUBLISHED: &'static str = "The article is not published yet.";
 
 pub struct ResourceApi {
     auth: Arc<Authority<Privilege=()>>,
     cache: Arc<Cache<Vec<u8>>>,
     published_dir: String,
     allowed_exts: HashMap<String, Mime>,
+    schedule: Schedule,
+    drafts: Drafts,
 }
 
 impl ResourceApi {
@@ -28,6 +34,8 @@ impl ResourceApi {
             cache: Arc::new(Cache::new(0, DumbCacheSource::new())),
             published_dir: String::new(),
             allowed_exts: HashMap::new(),
+            schedule: Schedule::default(),
+            drafts: Drafts::default(),
         }
     }
     pub fn set_auth(&mut self, auth: Arc<Authority<Privilege=()>>) {
@@ -42,6 +50,32 @@ impl ResourceApi {
     pub fn set_allowed_exts(&mut self, allowed_exts: HashMap<String, Mime>) {
         self.allowed_exts = allowed_exts;
     }
+    /// Set the schedule of articles to be published in the future. Resources
+    /// of these articles are only available to authorized requests.
+    pub fn set_schedule(&mut self, schedule: Schedule) {
+        self.schedule = schedule;
+    }
+    /// Set the drafts, whose resources are only available to authorized
+    /// requests.
+    pub fn set_drafts(&mut self, drafts: Drafts) {
+        self.drafts = drafts;
+    }
+
+    /// Resources of drafts and of articles not published yet are hidden from
+    /// unauthorized requests. A resource belongs to the article at any of its
+    /// ancestor paths.
+    fn check_published(&self, req: &Request) -> Result<()> {
+        let segs = req.path_segs();
+        let is_hidden = (1..segs.len()).any(|i| {
+            let id = segs[..i].join("/");
+            self.drafts.contains(&id) || self.schedule.is_hidden(&id)
+        });
+        if is_hidden && self.auth.authorize((), req).is_err() {
+            Err(Error::not_found(ERR_NOT_PUBLISHED))
+        } else {
+            Ok(())
+        }
+    }
 
     fn get(&self, req: &mut Request) -> ApiResult {
         let id = req.path_segs().join("/");
@@ -49,13 +83,14 @@ impl ResourceApi {
         let mime = self.allowed_exts.get(ext)
             .ok_or(Error::new(StatusCode::UnsupportedMediaType,
                 ERR_MIME_NOT_FOUND))?;
+        self.check_published(req)?;
 
         let cache = self.cache.get(&id)?;
         let guard = cache.read().unwrap();
         let data = (*guard).clone();
-        Ok(Response::new()
-            .with_header(ContentType(mime.clone()))
-            .with_body(data))
+        let res = Response::new()
+            .with_header(ContentType(mime.clone()));
+        Ok(respond(req, res, data))
     }
 
     fn put(&self, req: &mut Request) -> ApiResult {
@@ -73,18 +108,25 @@ impl ResourceApi {
             return Err(Error::bad_request(ERR_MIME_EXT_MISMATCH))
         }
 
-        self.cache.get(&id)
-            .or(self.cache.create(&id))
-            .and_then(|cache| {
-                Ok(*cache.write().unwrap() = req.body().to_owned())
-            })
-            .map(|_| Response::new())
+        let (cache, exists) = match self.cache.get(&id) {
+            Ok(cache) => (cache, true),
+            Err(_) => (self.cache.create(&id)?, false),
+        };
+        let mut guard = cache.write().unwrap();
+        let etag = if exists { Some(etag_of(&guard)) } else { None };
+        check_match(req, etag.as_ref())?;
+        *guard = req.body().to_owned();
+        Ok(Response::new().with_header(header::ETag(etag_of(&guard))))
     }
 
     fn delete(&self, req: &mut Request) -> ApiResult {
         self.auth.authorize((), &req)?;
 
         let id = req.path_segs().join("/");
+        let etag = self.cache.get(&id)
+            .map(|cache| etag_of(&cache.read().unwrap()))
+            .ok();
+        check_match(req, etag.as_ref())?;
         self.cache.remove(&id)
             .map(|_| Response::new())
     }
@@ -108,3 +150,7 @@ impl Api for ResourceApi {
         }
     }
 }
+
+#[cfg(test)]
+mod tests;
+
@@ -0,0 +1,69 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use writium::prelude::*;
+use self::header::{Authorization, Bearer};
+use api::draft::Drafts;
+use api::observer::PostObserver;
+use api::schedule::Schedule;
+use api::test_common::*;
+use auth::SimpleAuthority;
+use super::ResourceApi;
+
+fn api() -> ResourceApi {
+    let mut exts = HashMap::new();
+    exts.insert("png".to_owned(), "image/png".parse().unwrap());
+    let mut api = ResourceApi::new();
+    api.set_auth(Arc::new(SimpleAuthority::new("PASSWORD")));
+    api.set_cache(cache_of(vec![
+        ("foo/img/cover.png", b"PNG".to_vec()),
+        ("bar/cover.png", b"PNG".to_vec()),
+    ]));
+    api.set_allowed_exts(exts);
+    api
+}
+
+#[test]
+fn test_get_scheduled() {
+    let schedule = Schedule::new("published");
+    schedule.update_metadata("foo",
+        &json!({ "published": "2999-01-01T00:00:00+08:00" }));
+    let mut api = api();
+    api.set_schedule(schedule);
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["foo", "img", "cover.png"]);
+    let err = test_err(&api, req);
+    assert_eq!(err.status(), StatusCode::NotFound);
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["foo", "img", "cover.png"])
+        .with_header(Authorization(Bearer {
+            token: "PASSWORD".to_owned(),
+        }));
+    let res = test_ok(&api, req);
+    check_content(&res, "PNG");
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["bar", "cover.png"]);
+    let res = test_ok(&api, req);
+    check_content(&res, "PNG");
+}
+#[test]
+fn test_get_draft() {
+    let drafts = Drafts::new();
+    drafts.update_metadata("foo", &json!({ "draft": true }));
+    let mut api = api();
+    api.set_drafts(drafts);
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["foo", "img", "cover.png"]);
+    let err = test_err(&api, req);
+    assert_eq!(err.status(), StatusCode::NotFound);
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["foo", "img", "cover.png"])
+        .with_header(Authorization(Bearer {
+            token: "PASSWORD".to_owned(),
+        }));
+    let res = test_ok(&api, req);
+    check_content(&res, "PNG");
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["bar", "cover.png"]);
+    let res = test_ok(&api, req);
+    check_content(&res, "PNG");
+}
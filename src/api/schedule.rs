@@ -0,0 +1,143 @@
+///! Scheduled publishing.
+///! Articles whose datetime index key lies in the future are hidden from
+///! anonymous readers until that moment.
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+use chrono::{FixedOffset, Utc};
+use serde_json::Value as JsonValue;
+use writium::prelude::*;
+use writium_auth::Authority;
+use super::index::{DateTime, KeyKind, KeyValue};
+use super::observer::PostObserver;
+
+fn now() -> DateTime {
+    Utc::now().with_timezone(&FixedOffset::east(0))
+}
+
+/// Publishing time of articles scheduled in the future.
+#[derive(Clone, Default)]
+pub struct Schedule {
+    /// Datetime metadata key of publishing time. Nothing is scheduled if it's
+    /// `None`.
+    key: Option<String>,
+    /// Type of the key, which should be one of the datetime types.
+    kind: Option<KeyKind>,
+    posts: Arc<RwLock<HashMap<String, DateTime>>>,
+}
+impl Schedule {
+    /// Make a new schedule by RFC 3339 datetime metadata key `key`.
+    pub fn new(key: &str) -> Schedule {
+        Schedule::with_kind(key, KeyKind::DateTime)
+    }
+    /// Make a new schedule by metadata key `key` of datetime type `kind`, e.g.,
+    /// a date-only key.
+    pub fn with_kind(key: &str, kind: KeyKind) -> Schedule {
+        Schedule {
+            key: Some(key.to_owned()),
+            kind: Some(kind),
+            posts: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+    /// Articles not published yet.
+    pub fn hidden(&self) -> HashSet<String> {
+        let now = now();
+        self.posts.read().unwrap().iter()
+            .filter(|&(_, dt)| dt > &now)
+            .map(|(id, _)| id.to_owned())
+            .collect()
+    }
+    /// Articles hidden from request `req`, i.e., those not published yet unless
+    /// the request is authorized by `auth`.
+    pub fn hidden_from(&self, req: &Request, auth: &Authority<Privilege=()>)
+        -> HashSet<String> {
+        if auth.authorize((), req).is_ok() {
+            HashSet::new()
+        } else {
+            self.hidden()
+        }
+    }
+    /// Check if article `id` is not published yet.
+    pub fn is_hidden(&self, id: &str) -> bool {
+        self.posts.read().unwrap().get(id)
+            .map(|dt| dt > &now())
+            .unwrap_or(false)
+    }
+}
+impl PostObserver for Schedule {
+    fn update_metadata(&self, id: &str, meta: &JsonValue) {
+        let (key, kind) = match (&self.key, self.kind) {
+            (&Some(ref key), Some(kind)) => (key, kind),
+            _ => return,
+        };
+        let mut posts = self.posts.write().unwrap();
+        let dt = match meta.get(key).and_then(|x| kind.value_of(x)) {
+            Some(KeyValue::DateTime(dt)) => Some(dt),
+            _ => None,
+        };
+        match dt {
+            // Articles already published are never hidden again, so they are
+            // not tracked.
+            Some(dt) if dt > now() => {
+                info!("Article '{}' is scheduled to be published at {}.", id,
+                    dt.to_rfc3339());
+                posts.insert(id.to_owned(), dt);
+            },
+            _ => {
+                posts.remove(id);
+            },
+        }
+    }
+    fn remove(&self, id: &str) {
+        self.posts.write().unwrap().remove(id);
+    }
+    fn dump(&self, id: &str) -> JsonValue {
+        match self.posts.read().unwrap().get(id) {
+            Some(dt) => JsonValue::String(dt.to_rfc3339()),
+            None => JsonValue::Null,
+        }
+    }
+    fn restore(&self, id: &str, state: &JsonValue) {
+        let dt = state.as_str()
+            .and_then(|x| DateTime::parse_from_rfc3339(x).ok());
+        match dt {
+            // Articles might have been published since the snapshot was taken.
+            Some(dt) if dt > now() => {
+                self.posts.write().unwrap().insert(id.to_owned(), dt);
+            },
+            _ => {},
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::FixedOffset;
+    use api::index::KeyKind;
+    use api::observer::PostObserver;
+    use super::Schedule;
+
+    #[test]
+    fn test_schedule() {
+        let schedule = Schedule::new("published");
+        schedule.update_metadata("past",
+            &json!({ "published": "2018-01-01T00:00:00+08:00" }));
+        schedule.update_metadata("future",
+            &json!({ "published": "2999-01-01T00:00:00+08:00" }));
+        assert!(!schedule.is_hidden("past"));
+        assert!(schedule.is_hidden("future"));
+        assert_eq!(schedule.hidden().len(), 1);
+        schedule.update_metadata("future",
+            &json!({ "published": "2018-01-02T00:00:00+08:00" }));
+        assert!(!schedule.is_hidden("future"));
+    }
+    #[test]
+    fn test_schedule_date() {
+        let kind = KeyKind::Date(FixedOffset::east(8 * 3600));
+        let schedule = Schedule::with_kind("published", kind);
+        schedule.update_metadata("past", &json!({ "published": "2018-01-01" }));
+        schedule.update_metadata("future",
+            &json!({ "published": "2999-01-01" }));
+        assert!(!schedule.is_hidden("past"));
+        assert!(schedule.is_hidden("future"));
+    }
+}
@@ -0,0 +1,435 @@
+///! Full-text search API.
+///! An inverted index is built over the content and selected metadata fields of
+///! articles. Queries are made of words, `prefix*`es and `"quoted phrases"`,
+///! all of which should be matched in a result.
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+use serde_json::Value as JsonValue;
+use writium::prelude::*;
+use writium_auth::{Authority, DumbAuthority};
+use self::header::ContentType;
+use super::index::is_indexable;
+use super::observer::PostObserver;
+use super::schedule::Schedule;
+
+pub mod text;
+
+#[cfg(test)]
+mod tests;
+
+const DEFAULT_ENTRIES_PER_REQUEST: usize = 5;
+/// Gap of positions between metadata fields and content, so that phrases never
+/// span across them.
+const FIELD_GAP: usize = 16;
+/// Hits in metadata fields are weighted more than those in content.
+const FIELD_BOOST: f64 = 2.0;
+/// Number of bytes kept around the first hit in a snippet.
+const SNIPPET_RADIUS: usize = 80;
+
+const ERR_QUERY: &'static str = "Search query should contain at least one \
+    word.";
+
+/// A parsed search query.
+pub struct Query {
+    clauses: Vec<Clause>,
+}
+enum Clause {
+    Term(String),
+    Prefix(String),
+    Phrase(Vec<String>),
+}
+impl Query {
+    pub fn parse(query: &str) -> Query {
+        let mut clauses = Vec::new();
+        // Segments at odd positions are quoted.
+        for (i, seg) in query.split('"').enumerate() {
+            if i % 2 == 1 {
+                let mut terms = text::tokenize(seg);
+                if terms.len() > 1 {
+                    clauses.push(Clause::Phrase(terms));
+                } else if let Some(term) = terms.pop() {
+                    clauses.push(Clause::Term(term));
+                }
+                continue
+            }
+            for word in seg.split_whitespace() {
+                let mut terms = text::tokenize(word);
+                // Prefixes are not stemmed, `publish*` should match `published`.
+                let prefix = if word.ends_with('*') {
+                    let spans = text::word_spans(word);
+                    terms.pop();
+                    spans.last().map(|&(beg, end)| word[beg..end].to_lowercase())
+                } else {
+                    None
+                };
+                clauses.extend(terms.into_iter().map(Clause::Term));
+                if let Some(prefix) = prefix {
+                    clauses.push(Clause::Prefix(prefix));
+                }
+            }
+        }
+        Query { clauses: clauses }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.clauses.is_empty()
+    }
+    /// Check if a word in the original text is hit by the query.
+    fn is_hit(&self, word: &str) -> bool {
+        let lower = word.to_lowercase();
+        let term = text::stem(&lower);
+        self.clauses.iter().any(|clause| match *clause {
+            Clause::Term(ref t) => t == &term,
+            Clause::Prefix(ref p) => lower.starts_with(p),
+            Clause::Phrase(ref ts) => ts.contains(&term),
+        })
+    }
+}
+
+#[derive(Serialize)]
+pub struct SearchResult {
+    pub id: String,
+    pub title: String,
+    pub score: f64,
+    /// HTML snippet of content with hits highlighted.
+    pub snippet: String,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+struct Document {
+    content: String,
+    fields: String,
+    /// Number of terms in metadata fields.
+    field_len: usize,
+    hidden: bool,
+}
+impl Document {
+    fn terms(&self) -> Vec<(usize, String)> {
+        let fields = text::tokenize(&self.fields);
+        let offset = fields.len() + FIELD_GAP;
+        let mut rv: Vec<(usize, String)> = fields.into_iter()
+            .enumerate()
+            .collect();
+        rv.extend(text::tokenize(&self.content).into_iter()
+            .enumerate()
+            .map(|(pos, term)| (pos + offset, term)));
+        rv
+    }
+    fn title(&self) -> String {
+        self.content.lines()
+            .next()
+            .unwrap_or_default()
+            .trim_left_matches('#')
+            .trim()
+            .to_owned()
+    }
+}
+
+#[derive(Default)]
+struct Inverted {
+    docs: HashMap<String, Document>,
+    /// Term to the positions of it in each document.
+    postings: BTreeMap<String, HashMap<String, Vec<usize>>>,
+}
+impl Inverted {
+    fn unindex(&mut self, id: &str) {
+        let terms: HashSet<String> = match self.docs.get(id) {
+            Some(doc) => doc.terms().into_iter().map(|(_, t)| t).collect(),
+            None => return,
+        };
+        for term in terms {
+            let is_empty = match self.postings.get_mut(&term) {
+                Some(docs) => {
+                    docs.remove(id);
+                    docs.is_empty()
+                },
+                None => false,
+            };
+            if is_empty {
+                self.postings.remove(&term);
+            }
+        }
+    }
+    fn index(&mut self, id: &str) {
+        let terms = match self.docs.get(id) {
+            Some(doc) => doc.terms(),
+            None => return,
+        };
+        for (pos, term) in terms {
+            self.postings.entry(term)
+                .or_insert_with(HashMap::new)
+                .entry(id.to_owned())
+                .or_insert_with(Vec::new)
+                .push(pos);
+        }
+    }
+    fn idf(&self, df: usize) -> f64 {
+        ((self.docs.len() as f64 + 1.0) / (df as f64 + 1.0)).ln() + 1.0
+    }
+    /// Term frequency with hits in metadata fields boosted.
+    fn tf(&self, id: &str, positions: &[usize]) -> f64 {
+        let field_len = self.docs.get(id).map(|doc| doc.field_len).unwrap_or(0);
+        positions.iter()
+            .map(|&pos| if pos < field_len { FIELD_BOOST } else { 1.0 })
+            .sum()
+    }
+    fn score_term(&self, term: &str, scores: &mut HashMap<String, f64>) {
+        if let Some(docs) = self.postings.get(term) {
+            let idf = self.idf(docs.len());
+            for (id, positions) in docs.iter() {
+                *scores.entry(id.to_owned()).or_insert(0.0) +=
+                    self.tf(id, positions) * idf;
+            }
+        }
+    }
+    fn score(&self, clause: &Clause) -> HashMap<String, f64> {
+        let mut scores = HashMap::new();
+        match *clause {
+            Clause::Term(ref term) => self.score_term(term, &mut scores),
+            Clause::Prefix(ref prefix) => {
+                let terms = self.postings.range(prefix.to_owned()..)
+                    .map(|(term, _)| term)
+                    .take_while(|term| term.starts_with(&prefix[..]));
+                for term in terms {
+                    self.score_term(term, &mut scores);
+                }
+            },
+            Clause::Phrase(ref terms) => {
+                let postings: Vec<&HashMap<String, Vec<usize>>> =
+                    match terms.iter().map(|t| self.postings.get(t)).collect() {
+                        Some(postings) => postings,
+                        None => return scores,
+                    };
+                let idf: f64 = postings.iter()
+                    .map(|docs| self.idf(docs.len()))
+                    .sum();
+                for (id, positions) in postings[0].iter() {
+                    let hits: Vec<usize> = positions.iter()
+                        .cloned()
+                        .filter(|&pos| postings.iter()
+                            .enumerate()
+                            .skip(1)
+                            .all(|(i, docs)| docs.get(id)
+                                .map(|x| x.contains(&(pos + i)))
+                                .unwrap_or(false)))
+                        .collect();
+                    if !hits.is_empty() {
+                        scores.insert(id.to_owned(), self.tf(id, &hits) * idf);
+                    }
+                }
+            },
+        }
+        scores
+    }
+}
+
+/// Full-text index of articles.
+#[derive(Clone)]
+pub struct SearchIndex {
+    fields: Vec<String>,
+    inverted: Arc<RwLock<Inverted>>,
+}
+impl SearchIndex {
+    /// Make a new search index. `fields` are the metadata keys to be indexed
+    /// in addition to the content.
+    pub fn new(fields: &[String]) -> SearchIndex {
+        SearchIndex {
+            fields: fields.to_owned(),
+            inverted: Arc::new(RwLock::new(Inverted::default())),
+        }
+    }
+    /// Search for articles matching `query`, ordered by relevance.
+    pub fn search(&self, query: &Query) -> Vec<(String, f64)> {
+        let inverted = self.inverted.read().unwrap();
+        let mut clauses = query.clauses.iter();
+        let mut scores = match clauses.next() {
+            Some(clause) => inverted.score(clause),
+            None => return Vec::new(),
+        };
+        for clause in clauses {
+            let other = inverted.score(clause);
+            scores = scores.into_iter()
+                .filter_map(|(id, score)| other.get(&id)
+                    .map(|x| (id, score + x)))
+                .collect();
+        }
+        let mut rv: Vec<(String, f64)> = scores.into_iter()
+            .filter(|&(ref id, _)| inverted.docs.get(id)
+                .map(|doc| !doc.hidden)
+                .unwrap_or(false))
+            .collect();
+        rv.sort_by(|a, b| b.1.partial_cmp(&a.1)
+            .unwrap()
+            .then_with(|| a.0.cmp(&b.0)));
+        rv
+    }
+    /// Describe a search hit with title and snippet.
+    pub fn result(&self, query: &Query, id: &str, score: f64)
+        -> Option<SearchResult> {
+        let inverted = self.inverted.read().unwrap();
+        let doc = inverted.docs.get(id)?;
+        Some(SearchResult {
+            id: id.to_owned(),
+            title: doc.title(),
+            score: score,
+            snippet: text::snippet(&doc.content, |word| query.is_hit(word),
+                SNIPPET_RADIUS),
+        })
+    }
+}
+impl PostObserver for SearchIndex {
+    fn update_metadata(&self, id: &str, meta: &JsonValue) {
+        let mut fields = String::new();
+        for key in self.fields.iter() {
+            match meta.get(key) {
+                Some(&JsonValue::String(ref val)) => {
+                    fields.push_str(val);
+                    fields.push('\n');
+                },
+                Some(&JsonValue::Array(ref vals)) => {
+                    for val in vals.iter().filter_map(|x| x.as_str()) {
+                        fields.push_str(val);
+                        fields.push('\n');
+                    }
+                },
+                _ => {},
+            }
+        }
+        let mut inverted = self.inverted.write().unwrap();
+        inverted.unindex(id);
+        {
+            let doc = inverted.docs.entry(id.to_owned())
+                .or_insert_with(Document::default);
+            doc.field_len = text::tokenize(&fields).len();
+            doc.fields = fields;
+            doc.hidden = !is_indexable(meta);
+        }
+        inverted.index(id);
+    }
+    fn update_content(&self, id: &str, content: &str) {
+        let mut inverted = self.inverted.write().unwrap();
+        inverted.unindex(id);
+        inverted.docs.entry(id.to_owned())
+            .or_insert_with(Document::default)
+            .content = content.to_owned();
+        inverted.index(id);
+    }
+    fn remove(&self, id: &str) {
+        let mut inverted = self.inverted.write().unwrap();
+        inverted.unindex(id);
+        inverted.docs.remove(id);
+    }
+    /// The document and the positions of each term in it, so that it's not
+    /// tokenized again when restored.
+    fn dump(&self, id: &str) -> JsonValue {
+        let inverted = self.inverted.read().unwrap();
+        let doc = match inverted.docs.get(id) {
+            Some(doc) => doc,
+            None => return JsonValue::Null,
+        };
+        let mut postings: HashMap<String, Vec<usize>> = HashMap::new();
+        for (pos, term) in doc.terms() {
+            postings.entry(term).or_insert_with(Vec::new).push(pos);
+        }
+        json!({ "doc": doc, "postings": postings })
+    }
+    fn restore(&self, id: &str, state: &JsonValue) {
+        #[derive(Deserialize)]
+        struct State {
+            doc: Document,
+            postings: HashMap<String, Vec<usize>>,
+        }
+        let state = match ::serde_json::from_value::<State>(state.clone()) {
+            Ok(state) => state,
+            Err(_) => return,
+        };
+        let mut inverted = self.inverted.write().unwrap();
+        inverted.unindex(id);
+        inverted.docs.insert(id.to_owned(), state.doc);
+        for (term, positions) in state.postings {
+            inverted.postings.entry(term)
+                .or_insert_with(HashMap::new)
+                .insert(id.to_owned(), positions);
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct SearchResults {
+    total: usize,
+    results: Vec<SearchResult>,
+}
+
+pub struct SearchApi {
+    search: SearchIndex,
+    auth: Arc<Authority<Privilege=()>>,
+    schedule: Schedule,
+    entries_per_request: usize,
+}
+impl SearchApi {
+    pub fn new(search: SearchIndex) -> SearchApi {
+        SearchApi {
+            search: search,
+            auth: Arc::new(DumbAuthority::new()),
+            schedule: Schedule::default(),
+            entries_per_request: DEFAULT_ENTRIES_PER_REQUEST,
+        }
+    }
+    /// Authority of requests searching articles not published yet.
+    pub fn set_auth(&mut self, auth: Arc<Authority<Privilege=()>>) {
+        self.auth = auth;
+    }
+    pub fn set_schedule(&mut self, schedule: Schedule) {
+        self.schedule = schedule;
+    }
+    pub fn set_entries_per_request(&mut self, entries_per_request: usize) {
+        self.entries_per_request = entries_per_request;
+    }
+
+    /// GET `/search?{q}[from]`
+    fn get(&self, req: &mut Request) -> ApiResult {
+        #[derive(Deserialize)]
+        struct Param {
+            /// The search query.
+            q: Option<String>,
+            /// The index of the first result to be included.
+            from: Option<usize>,
+        }
+        let param = req.to_param::<Param>()?;
+        let query = Query::parse(&param.q.unwrap_or_default());
+        if query.is_empty() {
+            return Err(Error::bad_request(ERR_QUERY))
+        }
+        let hidden = self.schedule.hidden_from(req, &*self.auth);
+        let hits: Vec<(String, f64)> = self.search.search(&query).into_iter()
+            .filter(|&(ref id, _)| !hidden.contains(id))
+            .collect();
+        let results = hits.iter()
+            .skip(param.from.unwrap_or(0))
+            .take(self.entries_per_request)
+            .filter_map(|&(ref id, score)| self.search.result(&query, id, score))
+            .collect();
+        Response::new()
+            .with_header(ContentType(
+                "application/json; charset=UTF-8".parse().unwrap()))
+            .with_json(&SearchResults {
+                total: hits.len(),
+                results: results,
+            })
+    }
+}
+impl Api for SearchApi {
+    fn name(&self) -> &[&str] {
+        &["search"]
+    }
+
+    fn route(&self, req: &mut Request) -> ApiResult {
+        use self::header::Allow;
+        use self::Method::*;
+        match req.method() {
+            Options => Ok(Response::new()
+                .with_header(Allow(vec![Options, Get]))),
+            Get => self.get(req),
+            _ => Err(Error::method_not_allowed()),
+        }
+    }
+}
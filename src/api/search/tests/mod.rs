@@ -0,0 +1,126 @@
+use std::sync::Arc;
+use writium::prelude::*;
+use self::header::{Authorization, Bearer};
+use api::observer::PostObserver;
+use api::schedule::Schedule;
+use api::test_common::*;
+use auth::SimpleAuthority;
+use super::{Query, SearchApi, SearchIndex};
+use super::text::{snippet, stem};
+
+fn search() -> SearchIndex {
+    let search = SearchIndex::new(&["tags".to_owned()]);
+    search.update_content("foo", "# Async Rust\n\nFutures are published in \
+        crates.");
+    search.update_metadata("foo", &json!({ "tags": ["rust"] }));
+    search.update_content("bar", "# Publishing\n\nRust is a language. I \
+        publish posts about rust.");
+    search.update_metadata("bar", &json!({}));
+    search.update_content("baz", "# Secret\n\nRust drafts.");
+    search.update_metadata("baz", &json!({ "noIndex": true }));
+    search
+}
+fn ids(search: &SearchIndex, query: &str) -> Vec<String> {
+    search.search(&Query::parse(query)).into_iter()
+        .map(|(id, _)| id)
+        .collect()
+}
+
+#[test]
+fn test_stem() {
+    assert_eq!(stem("published"), stem("publishing"));
+    assert_eq!(stem("posts"), stem("post"));
+    assert_eq!(stem("running"), "run");
+    assert_eq!(stem("stories"), "story");
+    assert_eq!(stem("class"), "class");
+}
+#[test]
+fn test_search_term() {
+    let search = search();
+    assert_eq!(ids(&search, "publish"), vec!["bar", "foo"]);
+    assert_eq!(ids(&search, "futures"), vec!["foo"]);
+    // `baz` is not indexed.
+    assert!(ids(&search, "drafts").is_empty());
+}
+#[test]
+fn test_search_all_terms() {
+    let search = search();
+    assert_eq!(ids(&search, "rust crates"), vec!["foo"]);
+    assert!(ids(&search, "rust golang").is_empty());
+}
+#[test]
+fn test_search_field_boost() {
+    let search = search();
+    // `foo` is tagged `rust`, which is weighted more.
+    assert_eq!(ids(&search, "rust")[0], "foo");
+}
+#[test]
+fn test_search_prefix() {
+    let search = search();
+    assert_eq!(ids(&search, "lang*"), vec!["bar"]);
+    assert_eq!(ids(&search, "pub*").len(), 2);
+}
+#[test]
+fn test_search_phrase() {
+    let search = search();
+    assert_eq!(ids(&search, "\"publish posts\""), vec!["bar"]);
+    assert!(ids(&search, "\"posts publish\"").is_empty());
+}
+#[test]
+fn test_search_remove() {
+    let search = search();
+    search.remove("foo");
+    assert!(ids(&search, "futures").is_empty());
+}
+#[test]
+fn test_snippet() {
+    let query = Query::parse("brown");
+    let text = "The quick <brown> fox.";
+    assert_eq!(snippet(text, |x| query.is_hit(x), 4),
+        "...ck &lt;<mark>brown</mark>&gt; fo...");
+}
+
+#[test]
+fn test_get() {
+    let mut api = SearchApi::new(search());
+    api.set_entries_per_request(1);
+    let req = Request::new(Method::Get)
+        .with_query("q=publish&from=1");
+    let res = test_ok(&api, req);
+    check_type(&res, "application", "json");
+    let json: ::serde_json::Value = ::serde_json::from_str(&res.to_str().unwrap())
+        .unwrap();
+    assert_eq!(json["total"], json!(2));
+    assert_eq!(json["results"][0]["id"], json!("foo"));
+    assert_eq!(json["results"][0]["title"], json!("Async Rust"));
+}
+#[test]
+fn fail_get() {
+    let api = SearchApi::new(search());
+    let req = Request::new(Method::Get)
+        .with_query("q=%20");
+    let err = test_err(&api, req);
+    assert_eq!(err.status(), StatusCode::BadRequest);
+}
+#[test]
+fn test_get_scheduled() {
+    let schedule = Schedule::new("published");
+    schedule.update_metadata("foo",
+        &json!({ "published": "2999-01-01T00:00:00+08:00" }));
+    let mut api = SearchApi::new(search());
+    api.set_auth(Arc::new(SimpleAuthority::new("PASSWORD")));
+    api.set_schedule(schedule);
+    let total = |req| {
+        let res = test_ok(&api, req);
+        let json: ::serde_json::Value =
+            ::serde_json::from_str(&res.to_str().unwrap()).unwrap();
+        json["total"].clone()
+    };
+    let req = Request::new(Method::Get)
+        .with_query("q=futures");
+    assert_eq!(total(req), json!(0));
+    let req = Request::new(Method::Get)
+        .with_query("q=futures")
+        .with_header(Authorization(Bearer { token: "PASSWORD".to_owned() }));
+    assert_eq!(total(req), json!(1));
+}
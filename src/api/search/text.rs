@@ -0,0 +1,135 @@
+///! Text processing for full-text search.
+
+/// Byte spans of words in `text`. A word is a run of alphanumeric characters.
+pub fn word_spans(text: &str) -> Vec<(usize, usize)> {
+    let mut rv = Vec::new();
+    let mut beg = None;
+    for (pos, ch) in text.char_indices() {
+        if ch.is_alphanumeric() {
+            if beg.is_none() {
+                beg = Some(pos);
+            }
+        } else if let Some(b) = beg.take() {
+            rv.push((b, pos));
+        }
+    }
+    if let Some(b) = beg {
+        rv.push((b, text.len()));
+    }
+    rv
+}
+
+/// Normalize a word into a search term.
+pub fn normalize(word: &str) -> String {
+    stem(&word.to_lowercase())
+}
+
+/// Split `text` into search terms.
+pub fn tokenize(text: &str) -> Vec<String> {
+    word_spans(text).into_iter()
+        .map(|(beg, end)| normalize(&text[beg..end]))
+        .collect()
+}
+
+fn strip<'a>(word: &'a str, suffix: &str, min_len: usize) -> Option<&'a str> {
+    if word.ends_with(suffix) && word.len() - suffix.len() >= min_len {
+        Some(&word[..word.len() - suffix.len()])
+    } else {
+        None
+    }
+}
+/// A simple suffix-stripping stemmer for English words. Plural forms, `-ed`,
+/// `-ing` and trailing `e`s are removed so that inflections of a word share
+/// the same stem. Non-ASCII words are kept as is.
+pub fn stem(word: &str) -> String {
+    if !word.is_ascii() {
+        return word.to_owned()
+    }
+    let mut word = if let Some(w) = strip(word, "sses", 1) {
+        format!("{}ss", w)
+    } else if let Some(w) = strip(word, "ies", 2) {
+        format!("{}y", w)
+    } else if word.ends_with("ss") {
+        word.to_owned()
+    } else if let Some(w) = strip(word, "s", 3) {
+        w.to_owned()
+    } else {
+        word.to_owned()
+    };
+    let stripped = strip(&word, "ing", 3)
+        .or_else(|| strip(&word, "ed", 3))
+        .map(|w| {
+            // `running` -> `runn` -> `run`.
+            let bytes = w.as_bytes();
+            let last = bytes[bytes.len() - 1];
+            if bytes[bytes.len() - 2] == last &&
+                !b"aeioulsz".contains(&last) {
+                w[..w.len() - 1].to_owned()
+            } else {
+                w.to_owned()
+            }
+        });
+    if let Some(w) = stripped {
+        word = w;
+    }
+    if let Some(w) = strip(&word, "e", 3).map(|w| w.to_owned()) {
+        word = w;
+    }
+    word
+}
+
+pub fn escape_html(text: &str) -> String {
+    let mut rv = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => rv.push_str("&amp;"),
+            '<' => rv.push_str("&lt;"),
+            '>' => rv.push_str("&gt;"),
+            '"' => rv.push_str("&quot;"),
+            '\'' => rv.push_str("&#39;"),
+            _ => rv.push(ch),
+        }
+    }
+    rv
+}
+
+/// Make an HTML snippet of `text` around the first word satisfying `is_match`.
+/// About `radius` bytes are kept at each side of the word. All the matching
+/// words in the snippet are wrapped in `<mark>`.
+pub fn snippet<F>(text: &str, is_match: F, radius: usize) -> String
+    where F: Fn(&str) -> bool {
+    let spans = word_spans(text);
+    let (first_beg, first_end) = spans.iter()
+        .find(|&&(beg, end)| is_match(&text[beg..end]))
+        .cloned()
+        .unwrap_or((0, 0));
+    let mut beg = first_beg.saturating_sub(radius);
+    while !text.is_char_boundary(beg) {
+        beg -= 1;
+    }
+    let mut end = (first_end + radius).min(text.len());
+    while !text.is_char_boundary(end) {
+        end += 1;
+    }
+    let mut rv = String::new();
+    if beg > 0 {
+        rv.push_str("...");
+    }
+    let mut cursor = beg;
+    for &(b, e) in spans.iter()
+        .filter(|&&(b, e)| b >= beg && e <= end) {
+        let word = &text[b..e];
+        if is_match(word) {
+            rv.push_str(&escape_html(&text[cursor..b]));
+            rv.push_str("<mark>");
+            rv.push_str(&escape_html(word));
+            rv.push_str("</mark>");
+            cursor = e;
+        }
+    }
+    rv.push_str(&escape_html(&text[cursor..end]));
+    if end < text.len() {
+        rv.push_str("...");
+    }
+    rv.replace('\n', " ")
+}
@@ -0,0 +1,233 @@
+///! Taxonomy API.
+///! Metadata arrays like `tags` and `categories` are taxonomies. Each term in
+///! such arrays is mapped to the articles having it.
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+use serde_json::Value as JsonValue;
+use writium::prelude::*;
+use writium_auth::{Authority, DumbAuthority};
+use self::header::ContentType;
+use super::index::{is_indexable, Index};
+use super::observer::PostObserver;
+use super::schedule::Schedule;
+
+#[cfg(test)]
+mod tests;
+
+const DEFAULT_ENTRIES_PER_REQUEST: usize = 5;
+
+const ERR_NOT_FOUND: &'static str = "No article is classified by the \
+    requested term.";
+
+#[derive(Default)]
+struct TaxonomyMap {
+    /// Term to articles.
+    terms: BTreeMap<String, BTreeSet<String>>,
+    /// Article to terms, used to clean up terms when an article is updated.
+    posts: HashMap<String, Vec<String>>,
+}
+
+#[derive(Clone)]
+pub struct Taxonomy {
+    key: String,
+    map: Arc<RwLock<TaxonomyMap>>,
+}
+impl Taxonomy {
+    /// Make a new taxonomy of metadata key `key`.
+    pub fn new(key: &str) -> Taxonomy {
+        Taxonomy {
+            key: key.to_owned(),
+            map: Arc::new(RwLock::new(TaxonomyMap::default())),
+        }
+    }
+    /// Get the metadata key of the current taxonomy.
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+    /// All the terms and the number of articles classified by each of them,
+    /// as if articles in `hidden` were not classified.
+    pub fn terms(&self, hidden: &HashSet<String>) -> BTreeMap<String, usize> {
+        self.map.read().unwrap().terms.iter()
+            .map(|(term, ids)| {
+                let count = ids.iter()
+                    .filter(|id| !hidden.contains(*id))
+                    .count();
+                (term.to_owned(), count)
+            })
+            .filter(|&(_, count)| count > 0)
+            .collect()
+    }
+    /// Get the articles classified by `term`, ordered by `index`, excluding
+    /// those in `hidden`. Articles not present in the index are placed at the
+    /// back.
+    pub fn posts(&self, term: &str, index: &Index, hidden: &HashSet<String>)
+        -> Option<Vec<String>> {
+        let map = self.map.read().unwrap();
+        let guard = index.read().unwrap();
+        // Articles not indexed are ranked after all the indexed ones, in
+        // order of their IDs.
+        let mut ranked: Vec<(bool, Option<usize>, &String)> =
+            map.terms.get(term)?.iter()
+                .filter(|id| !hidden.contains(*id))
+                .map(|id| {
+                    let rank = guard.rank(&id[..]);
+                    (rank.is_none(), rank, id)
+                })
+                .collect();
+        if ranked.is_empty() {
+            return None
+        }
+        ranked.sort();
+        Some(ranked.into_iter()
+            .map(|(_, _, id)| id.to_owned())
+            .collect())
+    }
+    fn insert_post(map: &mut TaxonomyMap, id: &str, terms: Vec<String>) {
+        for term in terms.iter() {
+            map.terms.entry(term.to_owned())
+                .or_insert_with(BTreeSet::new)
+                .insert(id.to_owned());
+        }
+        map.posts.insert(id.to_owned(), terms);
+    }
+    fn remove_post(map: &mut TaxonomyMap, id: &str) {
+        if let Some(terms) = map.posts.remove(id) {
+            for term in terms {
+                let is_empty = match map.terms.get_mut(&term) {
+                    Some(ids) => {
+                        ids.remove(id);
+                        ids.is_empty()
+                    },
+                    None => false,
+                };
+                if is_empty {
+                    map.terms.remove(&term);
+                }
+            }
+        }
+    }
+}
+impl PostObserver for Taxonomy {
+    fn update_metadata(&self, id: &str, meta: &JsonValue) {
+        let mut map = self.map.write().unwrap();
+        Taxonomy::remove_post(&mut map, id);
+        if !is_indexable(meta) {
+            return
+        }
+        // Both a single term and an array of terms are accepted.
+        let terms: Vec<String> = match meta.get(&self.key) {
+            Some(&JsonValue::String(ref term)) => vec![term.to_owned()],
+            Some(&JsonValue::Array(ref terms)) => terms.iter()
+                .filter_map(|term| term.as_str())
+                .map(|term| term.to_owned())
+                .collect(),
+            _ => return,
+        };
+        Taxonomy::insert_post(&mut map, id, terms);
+    }
+    fn remove(&self, id: &str) {
+        let mut map = self.map.write().unwrap();
+        Taxonomy::remove_post(&mut map, id);
+    }
+    fn dump(&self, id: &str) -> JsonValue {
+        match self.map.read().unwrap().posts.get(id) {
+            Some(terms) => json!(terms),
+            None => JsonValue::Null,
+        }
+    }
+    fn restore(&self, id: &str, state: &JsonValue) {
+        if let Some(terms) = state.as_array() {
+            let terms = terms.iter()
+                .filter_map(|term| term.as_str())
+                .map(|term| term.to_owned())
+                .collect();
+            let mut map = self.map.write().unwrap();
+            Taxonomy::insert_post(&mut map, id, terms);
+        }
+    }
+}
+
+pub struct TaxonomyApi {
+    taxonomy: Taxonomy,
+    index: Index,
+    auth: Arc<Authority<Privilege=()>>,
+    schedule: Schedule,
+    entries_per_request: usize,
+}
+impl TaxonomyApi {
+    pub fn new(taxonomy: Taxonomy) -> TaxonomyApi {
+        TaxonomyApi {
+            taxonomy: taxonomy,
+            index: Index::default(),
+            auth: Arc::new(DumbAuthority::new()),
+            schedule: Schedule::default(),
+            entries_per_request: DEFAULT_ENTRIES_PER_REQUEST,
+        }
+    }
+    /// Set the index by which articles are ordered.
+    pub fn set_index(&mut self, index: Index) {
+        self.index = index;
+    }
+    /// Authority of requests listing articles not published yet.
+    pub fn set_auth(&mut self, auth: Arc<Authority<Privilege=()>>) {
+        self.auth = auth;
+    }
+    pub fn set_schedule(&mut self, schedule: Schedule) {
+        self.schedule = schedule;
+    }
+    pub fn set_entries_per_request(&mut self, entries_per_request: usize) {
+        self.entries_per_request = entries_per_request;
+    }
+
+    /// GET `/<taxonomy>`
+    fn get_terms(&self, req: &mut Request) -> ApiResult {
+        let hidden = self.schedule.hidden_from(req, &*self.auth);
+        Response::new()
+            .with_header(ContentType(
+                "application/json; charset=UTF-8".parse().unwrap()))
+            .with_json(&self.taxonomy.terms(&hidden))
+    }
+    /// GET `/<taxonomy>/<term..>?[from]`
+    fn get_posts(&self, req: &mut Request) -> ApiResult {
+        #[derive(Deserialize)]
+        struct Param {
+            /// The index of the first article to be included.
+            from: Option<usize>,
+        }
+        let param = req.to_param::<Param>()?;
+        let term = req.path_segs().join("/");
+        let hidden = self.schedule.hidden_from(req, &*self.auth);
+        let entries: Vec<String> = self.taxonomy.posts(&term, &self.index,
+            &hidden)
+            .ok_or(Error::not_found(ERR_NOT_FOUND))?
+            .into_iter()
+            .skip(param.from.unwrap_or(0))
+            .take(self.entries_per_request)
+            .collect();
+        Response::new()
+            .with_header(ContentType(
+                "application/json; charset=UTF-8".parse().unwrap()))
+            .with_json(&entries)
+    }
+}
+impl Api for TaxonomyApi {
+    fn name(&self) -> &[&str] {
+        // Bound in a namespace named after the taxonomy key.
+        &[]
+    }
+
+    fn route(&self, req: &mut Request) -> ApiResult {
+        use self::header::Allow;
+        use self::Method::*;
+        match req.method() {
+            Options => Ok(Response::new()
+                .with_header(Allow(vec![Options, Get]))),
+            Get => if req.path_segs().len() == 0 {
+                self.get_terms(req)
+            } else {
+                self.get_posts(req)
+            },
+            _ => Err(Error::method_not_allowed()),
+        }
+    }
+}
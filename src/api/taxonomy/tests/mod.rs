@@ -0,0 +1,107 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use writium::prelude::*;
+use self::header::{Authorization, Bearer};
+use api::index::Index;
+use api::observer::PostObserver;
+use api::schedule::Schedule;
+use api::test_common::*;
+use auth::SimpleAuthority;
+use super::{Taxonomy, TaxonomyApi};
+
+fn taxonomy() -> (Taxonomy, Index) {
+    let taxonomy = Taxonomy::new("tags");
+    let index = Index::new("key", "+integer", None);
+    for &(id, ref meta) in [
+        ("foo", json!({ "key": 2, "tags": ["rust", "web"] })),
+        ("bar", json!({ "key": 1, "tags": ["rust"] })),
+        ("baz", json!({ "key": 0, "tags": "web", "noIndex": true })),
+    ].iter() {
+        index.update(id, meta);
+        taxonomy.update_metadata(id, meta);
+    }
+    (taxonomy, index)
+}
+fn api() -> TaxonomyApi {
+    let (taxonomy, index) = taxonomy();
+    let mut api = TaxonomyApi::new(taxonomy);
+    api.set_index(index);
+    api
+}
+/// `foo` is scheduled in the future.
+fn scheduled_api() -> TaxonomyApi {
+    let (taxonomy, index) = taxonomy();
+    let schedule = Schedule::new("published");
+    schedule.update_metadata("foo",
+        &json!({ "published": "2999-01-01T00:00:00+08:00" }));
+    let mut api = TaxonomyApi::new(taxonomy);
+    api.set_index(index);
+    api.set_auth(Arc::new(SimpleAuthority::new("PASSWORD")));
+    api.set_schedule(schedule);
+    api
+}
+
+#[test]
+fn test_terms() {
+    let (taxonomy, _) = taxonomy();
+    let terms = taxonomy.terms(&HashSet::new());
+    assert_eq!(terms.get("rust"), Some(&2));
+    assert_eq!(terms.get("web"), Some(&1));
+}
+#[test]
+fn test_update() {
+    let (taxonomy, index) = taxonomy();
+    taxonomy.update_metadata("foo", &json!({ "key": 2, "tags": ["web"] }));
+    let hidden = HashSet::new();
+    assert_eq!(taxonomy.posts("rust", &index, &hidden).unwrap(), vec!["bar"]);
+    taxonomy.remove("foo");
+    assert!(taxonomy.posts("web", &index, &hidden).is_none());
+}
+#[test]
+fn test_get_terms() {
+    let req = Request::new(Method::Get);
+    let res = test_ok(&api(), req);
+    check_type(&res, "application", "json");
+    check_content(&res, r#"{"rust":2,"web":1}"#);
+}
+#[test]
+fn test_get_posts() {
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["rust"]);
+    let res = test_ok(&api(), req);
+    check_type(&res, "application", "json");
+    check_content(&res, r#"["bar","foo"]"#);
+}
+#[test]
+fn fail_get_posts() {
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["go"]);
+    let err = test_err(&api(), req);
+    assert_eq!(err.status(), StatusCode::NotFound);
+}
+#[test]
+fn test_get_terms_scheduled() {
+    let req = Request::new(Method::Get);
+    let res = test_ok(&scheduled_api(), req);
+    check_content(&res, r#"{"rust":1}"#);
+    let req = Request::new(Method::Get)
+        .with_header(Authorization(Bearer { token: "PASSWORD".to_owned() }));
+    let res = test_ok(&scheduled_api(), req);
+    check_content(&res, r#"{"rust":2,"web":1}"#);
+}
+#[test]
+fn test_get_posts_scheduled() {
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["rust"]);
+    let res = test_ok(&scheduled_api(), req);
+    check_content(&res, r#"["bar"]"#);
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["web"]);
+    let err = test_err(&scheduled_api(), req);
+    assert_eq!(err.status(), StatusCode::NotFound);
+    let req = Request::new(Method::Get)
+        .with_path_segs(&["web"])
+        .with_header(Authorization(Bearer { token: "PASSWORD".to_owned() }));
+    let res = test_ok(&scheduled_api(), req);
+    check_content(&res, r#"["foo"]"#);
+}
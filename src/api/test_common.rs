@@ -1,4 +1,7 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 use writium::prelude::*;
+use writium_cache::{Cache, CacheSource};
 use self::header::ContentType;
 
 pub fn test_ok(api: &Api, mut req: Request) -> Response {
@@ -19,3 +22,33 @@ pub fn check_content(res: &Response, content: &str) {
     let res_content = res.to_str();
     assert_eq!(res_content.unwrap(), content);
 }
+
+/// Cache source keeping items in memory.
+pub struct MapSource<T>(Mutex<HashMap<String, T>>);
+impl<T> CacheSource for MapSource<T>
+    where T: 'static + Clone + Default + Send + Sync {
+    type Value = T;
+    fn load(&self, id: &str, create: bool) -> Result<T> {
+        match self.0.lock().unwrap().get(id) {
+            Some(item) => Ok(item.clone()),
+            None if create => Ok(T::default()),
+            None => Err(Error::not_found("Not found.")),
+        }
+    }
+    fn unload(&self, id: &str, val: &T) -> Result<()> {
+        self.0.lock().unwrap().insert(id.to_owned(), val.clone());
+        Ok(())
+    }
+    fn remove(&self, id: &str) -> Result<()> {
+        self.0.lock().unwrap().remove(id);
+        Ok(())
+    }
+}
+/// Make a cache of `items`.
+pub fn cache_of<T>(items: Vec<(&str, T)>) -> Arc<Cache<T>>
+    where T: 'static + Clone + Default + Send + Sync {
+    let map = items.into_iter()
+        .map(|(id, item)| (id.to_owned(), item))
+        .collect();
+    Arc::new(Cache::new(10, MapSource(Mutex::new(map))))
+}
@@ -0,0 +1,268 @@
+///! Trash API.
+///! Removed articles, metadata, comments, resources and revisions are kept in
+///! trash for a retention period. They can be listed, restored or purged by
+///! authorized requests.
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use writium::prelude::*;
+use writium_auth::{Authority, DumbAuthority};
+use self::header::ContentType;
+use model::{Trash, TrashItem};
+use super::index::{read_post, Indices};
+use super::observer::PostObserver;
+
+const ERR_RESTORE: &'static str = "Either an item or an article to be \
+    restored should be given.";
+const ERR_PURGE: &'static str = "Either an item to be purged or `all` should \
+    be given.";
+const ERR_NOTHING: &'static str = "Nothing of the article is in trash.";
+
+pub struct TrashApi {
+    trash: Trash,
+    auth: Arc<Authority<Privilege=()>>,
+    published_dir: String,
+    indices: Indices,
+    observers: Vec<Arc<PostObserver>>,
+}
+impl TrashApi {
+    pub fn new(trash: Trash) -> TrashApi {
+        TrashApi {
+            trash: trash,
+            auth: Arc::new(DumbAuthority::new()),
+            published_dir: String::new(),
+            indices: Indices::default(),
+            observers: Vec::new(),
+        }
+    }
+    pub fn set_auth(&mut self, auth: Arc<Authority<Privilege=()>>) {
+        self.auth = auth;
+    }
+    /// Set the directory in which restored articles are read to be indexed
+    /// again.
+    pub fn set_published_dir(&mut self, published_dir: &str) {
+        self.published_dir = published_dir.to_owned();
+    }
+    pub fn set_indices(&mut self, indices: Indices) {
+        self.indices = indices;
+    }
+    pub fn add_observer(&mut self, observer: Arc<PostObserver>) {
+        self.observers.push(observer);
+    }
+
+    /// Index articles of `items` again after they are restored.
+    fn reindex(&self, items: &[TrashItem]) {
+        let ids: BTreeSet<&str> = items.iter()
+            .filter_map(|item| {
+                let pos = item.path.rfind('/')?;
+                match &item.path[pos + 1..] {
+                    "content.md" | "metadata.json" => Some(&item.path[..pos]),
+                    _ => None,
+                }
+            })
+            .collect();
+        for id in ids {
+            if let Some((meta, content)) = read_post(&self.published_dir, id) {
+                self.indices.update(id, &meta);
+                for observer in self.observers.iter() {
+                    observer.update_metadata(id, &meta);
+                    observer.update_content(id, &content);
+                }
+                info!("Reindexed restored article '{}'.", id);
+            }
+        }
+    }
+
+    /// GET `/trash`
+    fn get(&self, req: &mut Request) -> ApiResult {
+        self.auth.authorize((), &req)?;
+        self.trash.purge_expired()?;
+        Response::new()
+            .with_header(ContentType(
+                "application/json; charset=UTF-8".parse().unwrap()))
+            .with_json(&self.trash.items()?)
+    }
+    /// POST `/trash/<item>`
+    /// POST `/trash?article=<id>`
+    ///
+    /// Restore an item, or everything of an article in trash. Restored items
+    /// are responded.
+    fn post(&self, req: &mut Request) -> ApiResult {
+        #[derive(Deserialize)]
+        struct Param {
+            /// The article of which everything is restored.
+            article: Option<String>,
+        }
+        self.auth.authorize((), &req)?;
+        let param = req.to_param::<Param>()?;
+        let (restored, err) = match (req.path_segs().len(), param.article) {
+            (1, None) => (vec![self.trash.restore(&req.path_segs()[0])?], None),
+            (0, Some(article)) => {
+                let prefix = format!("{}/", article.trim_matches('/'));
+                // The latest removed is restored if there are several items
+                // of the same path.
+                let mut paths = BTreeSet::new();
+                let items: Vec<TrashItem> = self.trash.items()?
+                    .into_iter()
+                    .rev()
+                    .filter(|item| item.path.starts_with(&prefix))
+                    .filter(|item| paths.insert(item.path.clone()))
+                    .collect();
+                if items.is_empty() {
+                    return Err(Error::not_found(ERR_NOTHING))
+                }
+                // Items are restored as many as possible, and the first error
+                // is reported after those restored are reindexed.
+                let mut restored = Vec::new();
+                let mut first_err = None;
+                for item in items {
+                    match self.trash.restore(&item.id) {
+                        Ok(item) => restored.push(item),
+                        Err(err) => if first_err.is_none() {
+                            first_err = Some(err);
+                        },
+                    }
+                }
+                (restored, first_err)
+            },
+            _ => return Err(Error::bad_request(ERR_RESTORE)),
+        };
+        self.reindex(&restored);
+        if let Some(err) = err {
+            return Err(err)
+        }
+        Response::new()
+            .with_header(ContentType(
+                "application/json; charset=UTF-8".parse().unwrap()))
+            .with_json(&restored)
+    }
+    /// DELETE `/trash/<item>`
+    /// DELETE `/trash?all`
+    ///
+    /// Purge an item, or everything in trash.
+    fn delete(&self, req: &mut Request) -> ApiResult {
+        #[derive(Deserialize)]
+        struct Param {
+            /// Purge everything in trash.
+            all: Option<String>,
+        }
+        self.auth.authorize((), &req)?;
+        let param = req.to_param::<Param>()?;
+        match (req.path_segs().len(), param.all) {
+            (1, None) => self.trash.purge(&req.path_segs()[0])?,
+            (0, Some(_)) => for item in self.trash.items()? {
+                self.trash.purge(&item.id)?;
+            },
+            _ => return Err(Error::bad_request(ERR_PURGE)),
+        }
+        Ok(Response::new())
+    }
+}
+impl Api for TrashApi {
+    fn name(&self) -> &[&str] {
+        &["trash"]
+    }
+    fn route(&self, req: &mut Request) -> ApiResult {
+        use self::header::Allow;
+        use self::Method::*;
+        match req.method() {
+            Options => Ok(Response::new()
+                .with_header(Allow(vec![Options, Get, Post, Delete]))),
+            Get => self.get(req),
+            Post => self.post(req),
+            Delete => self.delete(req),
+            _ => Err(Error::method_not_allowed()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{create_dir_all, remove_dir_all, File};
+    use std::io::Write;
+    use std::sync::Arc;
+    use writium::prelude::*;
+    use self::header::{Authorization, Bearer};
+    use auth::SimpleAuthority;
+    use api::Index;
+    use api::test_common::*;
+    use model::Trash;
+    use super::TrashApi;
+
+    #[test]
+    fn test_restore() {
+        let dir = ::std::env::temp_dir()
+            .join(format!("writus-trash-api-{}", ::std::process::id()));
+        create_dir_all(dir.join("foo")).unwrap();
+        File::create(dir.join("foo/content.md")).unwrap()
+            .write_all(b"# Foo").unwrap();
+        File::create(dir.join("foo/metadata.json")).unwrap()
+            .write_all(br#"{"key":1}"#).unwrap();
+        let dir_str = dir.to_string_lossy().into_owned();
+        let trash = Trash::new(&dir_str, None);
+        trash.put("foo/content.md").unwrap();
+        trash.put("foo/metadata.json").unwrap();
+        let index = Index::new("key", "integer", None);
+        let mut api = TrashApi::new(trash.clone());
+        api.set_auth(Arc::new(SimpleAuthority::new("PASSWORD")));
+        api.set_published_dir(&dir_str);
+        api.set_indices(index.clone().into());
+        let auth = Authorization(Bearer { token: "PASSWORD".to_owned() });
+
+        let req = Request::new(Method::Get);
+        let err = test_err(&api, req);
+        assert_eq!(err.status(), StatusCode::Unauthorized);
+        let req = Request::new(Method::Get)
+            .with_header(auth.clone());
+        let res = test_ok(&api, req);
+        check_type(&res, "application", "json");
+        assert_eq!(trash.items().unwrap().len(), 2);
+        let req = Request::new(Method::Post)
+            .with_query("article=foo")
+            .with_header(auth.clone());
+        let _ = test_ok(&api, req);
+        assert!(dir.join("foo/content.md").exists());
+        assert!(trash.items().unwrap().is_empty());
+        assert_eq!(index.read().unwrap().rank("foo"), Some(0));
+        // Everything is purged only if it's explicitly requested.
+        let req = Request::new(Method::Delete)
+            .with_header(auth.clone());
+        let err = test_err(&api, req);
+        assert_eq!(err.status(), StatusCode::BadRequest);
+        remove_dir_all(&dir).unwrap();
+    }
+    #[test]
+    fn fail_restore_conflict() {
+        let dir = ::std::env::temp_dir()
+            .join(format!("writus-trash-api-conflict-{}",
+                ::std::process::id()));
+        create_dir_all(dir.join("foo")).unwrap();
+        File::create(dir.join("foo/content.md")).unwrap()
+            .write_all(b"# Foo").unwrap();
+        File::create(dir.join("foo/metadata.json")).unwrap()
+            .write_all(br#"{"key":1}"#).unwrap();
+        File::create(dir.join("foo/img.png")).unwrap();
+        let dir_str = dir.to_string_lossy().into_owned();
+        let trash = Trash::new(&dir_str, None);
+        trash.put("foo/metadata.json").unwrap();
+        trash.put("foo/img.png").unwrap();
+        // Taken place of the one in trash.
+        File::create(dir.join("foo/img.png")).unwrap();
+        let index = Index::new("key", "integer", None);
+        let mut api = TrashApi::new(trash.clone());
+        api.set_auth(Arc::new(SimpleAuthority::new("PASSWORD")));
+        api.set_published_dir(&dir_str);
+        api.set_indices(index.clone().into());
+        let req = Request::new(Method::Post)
+            .with_query("article=foo")
+            .with_header(Authorization(Bearer {
+                token: "PASSWORD".to_owned(),
+            }));
+        let err = test_err(&api, req);
+        assert_eq!(err.status(), StatusCode::Conflict);
+        // Items restored before or after the conflict are reindexed.
+        assert!(dir.join("foo/metadata.json").exists());
+        assert_eq!(trash.items().unwrap().len(), 1);
+        assert_eq!(index.read().unwrap().rank("foo"), Some(0));
+        remove_dir_all(&dir).unwrap();
+    }
+}
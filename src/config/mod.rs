@@ -33,15 +33,23 @@ pub struct WritusConfig {
     /// * `published_dir` = `./published`
     /// * `template_dir` = `./published/template`
     /// * `digests_per_page` = `5`
+    /// * `watch` = `false`
     pub extra: Option<TomlValue>,
 
     /// Static pages.
     pub static_pages: Option<HashMap<String, String>>,
+
+    /// Reindex all the articles ignoring the index snapshot. It can only be
+    /// set by command line option `--reindex`.
+    #[serde(skip)]
+    pub reindex: bool,
 }
 impl WritusConfig{
     pub fn load() -> WritusConfig {
         let mut options = Options::new();
         options.optflag("h", "help", "Help information");
+        options.optflag("", "reindex",
+            "Reindex all the articles ignoring the index snapshot");
         let args: Vec<String> = args().collect();
         
         let matches = match options.parse(&args[1..]) {
@@ -75,7 +83,10 @@ impl WritusConfig{
         set_current_dir(path.parent().unwrap())
             .expect("Unable to set current directory to config file's parent.");
         match ::toml::from_str::<WritusConfig>(&config) {
-            Ok(toml) => toml.insert_default(),
+            Ok(mut toml) => {
+                toml.reindex = matches.opt_present("reindex");
+                toml.insert_default()
+            },
             Err(err) => panic!("Unable to parse Writus config file: {:?}", err),
         }
     }
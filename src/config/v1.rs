@@ -1,29 +1,98 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
 use auth::SimpleAuthority;
+use chrono::{Duration, FixedOffset};
 use toml::Value as TomlValue;
 use writium::hyper::mime::Mime;
 use writium::prelude::*;
 use writium_cache::Cache;
 use api::*;
+use api::index::{parse_timezone, KeyKind, Snapshot, DEFAULT_INDEX_NAME};
+use api::post::Permalink;
 use model::*;
 use view::*;
+use watcher::Watcher;
+
+const SNAPSHOT_FILE_NAME: &str = "index_snapshot.json";
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct IndexConfig {
+    pub key: String,
+    pub key_type: String,
+}
 
 #[derive(Deserialize)]
 struct RawExtra {
     pub published_dir: Option<String>,
+    pub cache_dir: Option<String>,
+    pub watch: Option<bool>,
     pub auth_token: Option<String>,
     pub index_key: Option<String>,
     pub index_key_type: Option<String>,
+    pub indices: Option<BTreeMap<String, IndexConfig>>,
+    pub default_index: Option<String>,
+    pub timezone: Option<String>,
+    pub taxonomies: Option<Vec<String>>,
+    pub search_fields: Option<Vec<String>>,
+    pub archive_key: Option<String>,
+    pub related_key: Option<String>,
+    pub related_count: Option<usize>,
+    pub created_key: Option<String>,
+    pub modified_key: Option<String>,
+    pub permalink: Option<String>,
+    pub trash_retention_days: Option<i64>,
+    pub default_language: Option<String>,
+    pub languages: Option<Vec<String>>,
     pub entries_per_request: Option<u64>,
     pub allowed_exts: Option<HashMap<String, String>>,
     pub template_dir: Option<String>,
 }
 pub struct Extra {
     pub published_dir: String,
+    pub cache_dir: String,
+    /// Ignore the index snapshot in `cache_dir` and reindex all the articles.
+    pub reindex: bool,
+    /// Watch `published_dir` for changes made directly to local storage.
+    pub watch: bool,
     pub auth: Arc<SimpleAuthority>,
-    pub index_key: String,
-    pub index_key_type: String,
+    /// All the indices, including the one made from `index_key` and
+    /// `index_key_type`, which is named `default`.
+    pub indices: BTreeMap<String, IndexConfig>,
+    pub default_index: String,
+    /// Timezone of index keys of type `date` and `naive_datetime`.
+    /// [default: `+00:00`]
+    pub timezone: FixedOffset,
+    /// Metadata keys of taxonomies. [default: `["tags", "categories"]`]
+    pub taxonomies: Vec<String>,
+    /// Metadata keys indexed for full-text search in addition to content.
+    /// [default: `["title", "tags", "categories"]`]
+    pub search_fields: Vec<String>,
+    /// Datetime metadata key by which articles are archived.
+    /// [default: `published`]
+    pub archive_key: String,
+    /// Metadata key of tags by which related articles are found.
+    /// [default: `tags`]
+    pub related_key: String,
+    /// Maximal number of related articles of each article. [default: 5]
+    pub related_count: usize,
+    /// Metadata key set to the time an article is first written, or `None` if
+    /// it's not set. [default: `None`]
+    pub created_key: Option<String>,
+    /// Metadata key set to the time an article is last written, or `None` if
+    /// it's not set. [default: `None`]
+    pub modified_key: Option<String>,
+    /// Pattern of paths of articles created without a path.
+    /// [default: `{year}/{month}/{slug}`]
+    pub permalink: Permalink,
+    /// Period removed files are kept in trash, or forever if it's `None`.
+    /// Given in days, where non-positive numbers mean forever. [default: 30]
+    pub trash_retention: Option<Duration>,
+    /// Language of `content.md` and `metadata.json`, or empty if unspecified.
+    /// [default: ``]
+    pub default_language: String,
+    /// Languages articles are translated into, in `content.<lang>.md` and
+    /// `metadata.<lang>.json`. [default: `[]`]
+    pub languages: Vec<String>,
     pub entries_per_request: u64,
     pub allowed_exts: HashMap<String, Mime>,
     pub template_dir: String,
@@ -31,44 +100,286 @@ pub struct Extra {
 impl From<Extra> for Namespace {
     /// Construct a Namespace containing all the v1 api and views.
     fn from(extra: Extra) -> Namespace {
-        let index = Index::new(&extra.index_key, &extra.index_key_type, Some(&extra.published_dir));
-        let post_cache = Arc::new(Cache::new(10, PostSource::new(&extra.published_dir)));
-        let metadata_cache = Arc::new(Cache::new(10, MetadataSource::new(&extra.published_dir)));
-        let comment_cache = Arc::new(Cache::new(10, CommentSource::new(&extra.published_dir)));
-        let resource_cache = Arc::new(Cache::new(10, ResourceSource::new(&extra.published_dir)));
+        let indices = make_indices(&extra);
+        let taxonomies: Vec<Taxonomy> = extra.taxonomies.iter()
+            .map(|key| Taxonomy::new(key))
+            .collect();
+        let mut observers: Vec<Arc<PostObserver>> = Vec::new();
+        for taxonomy in taxonomies.iter() {
+            observers.push(Arc::new(taxonomy.clone()));
+        }
+        let search = SearchIndex::new(&extra.search_fields);
+        observers.push(Arc::new(search.clone()));
+        // Archive keys are of the type given in an index, if any, e.g., a
+        // date-only key.
+        let archive_kind = indices.iter()
+            .filter_map(|(_, index)| index.primary_key_part())
+            .find(|part| part.field == extra.archive_key &&
+                part.kind.is_datetime())
+            .map(|part| part.kind)
+            .unwrap_or(KeyKind::DateTime);
+        let archive = Archive::with_kind(&extra.archive_key, archive_kind);
+        observers.push(Arc::new(archive.clone()));
+        // Articles are scheduled by the default index key if it's a datetime.
+        let schedule = match indices.default_index().primary_key_part() {
+            Some(part) if part.kind.is_datetime() =>
+                Schedule::with_kind(&part.field, part.kind),
+            _ => Schedule::default(),
+        };
+        observers.push(Arc::new(schedule.clone()));
+        let drafts = Drafts::new();
+        observers.push(Arc::new(drafts.clone()));
+        let related = Related::new(&extra.related_key, extra.related_count);
+        observers.push(Arc::new(related.clone()));
+        let filter_index = FilterIndex::new();
+        observers.push(Arc::new(filter_index.clone()));
+        index_posts(&extra, &indices, &observers);
+        // Related articles are computed in background so startup is not
+        // delayed, until then none is listed.
+        related.spawn();
+        let trash = Trash::new(&extra.published_dir, extra.trash_retention);
+        match trash.purge_expired() {
+            Ok(n) => info!("Purged {} expired items in trash.", n),
+            Err(err) => warn!("Unable to purge expired items in trash: {}", err),
+        }
+        trash.spawn();
+        let post_tracker = Tracker::new();
+        let post_cache = Arc::new(Cache::new(10, post_tracker.track(
+            PostSource::new(&extra.published_dir).with_trash(trash.clone()))));
+        let metadata_tracker = Tracker::new();
+        let metadata_cache = Arc::new(Cache::new(10, metadata_tracker.track(
+            MetadataSource::new(&extra.published_dir)
+                .with_trash(trash.clone()))));
+        let comment_tracker = Tracker::new();
+        let comment_cache = Arc::new(Cache::new(10, comment_tracker.track(
+            CommentSource::new(&extra.published_dir)
+                .with_trash(trash.clone()))));
+        let mut translations = Translations::new(&extra.default_language);
+        let mut translation_trackers = Vec::new();
+        for lang in extra.languages.iter() {
+            let post_tracker = Tracker::new();
+            let post_cache = Arc::new(Cache::new(10, post_tracker.track(
+                PostSource::with_language(&extra.published_dir, lang)
+                    .with_trash(trash.clone()))));
+            let metadata_tracker = Tracker::new();
+            let metadata_cache = Arc::new(Cache::new(10,
+                metadata_tracker.track(
+                    MetadataSource::with_language(&extra.published_dir, lang)
+                        .with_trash(trash.clone()))));
+            translations.insert(lang, post_cache.clone(),
+                metadata_cache.clone());
+            translations.set_history(lang, Arc::new(History::new(
+                &extra.published_dir, &format!("content.{}.md", lang))));
+            translation_trackers.push((lang, post_cache, post_tracker,
+                metadata_cache, metadata_tracker));
+        }
+        let resource_tracker = Tracker::new();
+        let resource_cache = Arc::new(Cache::new(10, resource_tracker.track(
+            ResourceSource::new(&extra.published_dir)
+                .with_trash(trash.clone()))));
+        if extra.watch {
+            let mut watcher = Watcher::new(&extra.published_dir);
+            watcher.set_post_cache(post_cache.clone(), post_tracker);
+            watcher.set_metadata_cache(metadata_cache.clone(), metadata_tracker);
+            watcher.set_comment_cache(comment_cache.clone(), comment_tracker);
+            watcher.set_resource_cache(resource_cache.clone(), resource_tracker);
+            for (lang, post_cache, post_tracker, metadata_cache,
+                metadata_tracker) in translation_trackers {
+                watcher.add_translation(lang, post_cache, post_tracker,
+                    metadata_cache, metadata_tracker);
+            }
+            watcher.set_indices(indices.clone());
+            for observer in observers.iter() {
+                watcher.add_observer(observer.clone());
+            }
+            if let Err(err) = watcher.spawn() {
+                warn!("Unable to watch '{}': {}", extra.published_dir, err);
+            }
+        }
 
         let mut post_api = PostApi::new();
         post_api.set_auth(extra.auth.clone());
         post_api.set_cache(post_cache.clone());
-        post_api.set_index(index.clone());
+        post_api.set_metadata_cache(metadata_cache.clone());
+        post_api.set_comment_cache(comment_cache.clone());
+        post_api.set_resource_cache(resource_cache.clone());
+        post_api.set_published_dir(&extra.published_dir);
+        if let Some(ref key) = extra.created_key {
+            post_api.set_created_key(key);
+        }
+        if let Some(ref key) = extra.modified_key {
+            post_api.set_modified_key(key);
+        }
+        post_api.set_timezone(extra.timezone);
+        post_api.set_permalink(extra.permalink.clone());
+        post_api.set_allowed_exts(extra.allowed_exts.clone());
+        post_api.set_trash(trash.clone());
+        post_api.set_translations(translations.clone());
+        post_api.set_indices(indices.clone());
+        post_api.set_filter_index(filter_index.clone());
+        post_api.set_schedule(schedule.clone());
+        post_api.set_related(related.clone());
+        let metadata_history = Arc::new(History::new(&extra.published_dir,
+            "metadata.json"));
+        post_api.set_history(Arc::new(History::new(&extra.published_dir,
+            "content.md")));
+        post_api.set_metadata_history(metadata_history.clone());
+        for observer in observers.iter() {
+            post_api.add_observer(observer.clone());
+        }
 
         let mut comment_api = CommentApi::new();
         comment_api.set_auth(extra.auth.clone());
         comment_api.set_cache(comment_cache.clone());
+        comment_api.set_schedule(schedule.clone());
+        comment_api.set_drafts(drafts.clone());
 
         let mut metadata_api = MetadataApi::new();
         metadata_api.set_auth(extra.auth.clone());
         metadata_api.set_cache(metadata_cache.clone());
-        metadata_api.set_index(index.clone());
+        metadata_api.set_indices(indices.clone());
+        metadata_api.set_schedule(schedule.clone());
+        metadata_api.set_history(metadata_history);
+        for observer in observers.iter() {
+            metadata_api.add_observer(observer.clone());
+        }
 
         let mut resource_api = ResourceApi::new();
         resource_api.set_auth(extra.auth.clone());
         resource_api.set_cache(resource_cache.clone());
         resource_api.set_published_dir(&extra.published_dir);
         resource_api.set_allowed_exts(extra.allowed_exts.clone());
+        resource_api.set_schedule(schedule.clone());
+        resource_api.set_drafts(drafts.clone());
+
+        let mut search_api = SearchApi::new(search.clone());
+        search_api.set_auth(extra.auth.clone());
+        search_api.set_schedule(schedule.clone());
+        search_api.set_entries_per_request(extra.entries_per_request as usize);
+
+        let mut draft_api = DraftApi::new(drafts);
+        draft_api.set_auth(extra.auth.clone());
+        draft_api.set_entries_per_request(extra.entries_per_request as usize);
+
+        let mut trash_api = TrashApi::new(trash);
+        trash_api.set_auth(extra.auth.clone());
+        trash_api.set_published_dir(&extra.published_dir);
+        trash_api.set_indices(indices.clone());
+        for observer in observers.iter() {
+            trash_api.add_observer(observer.clone());
+        }
 
-        let apis = Namespace::new(&["api", "v1"])
+        let mut archive_api = ArchiveApi::new(archive.clone());
+        archive_api.set_auth(extra.auth.clone());
+        archive_api.set_schedule(schedule.clone());
+        archive_api.set_entries_per_request(extra.entries_per_request as usize);
+
+        let mut apis = Namespace::new(&["api", "v1"])
             .with_api(post_api)
             .with_api(comment_api)
             .with_api(metadata_api)
-            .with_api(resource_api);
+            .with_api(resource_api)
+            .with_api(search_api)
+            .with_api(archive_api)
+            .with_api(draft_api)
+            .with_api(trash_api);
+        for taxonomy in taxonomies.iter() {
+            let mut taxonomy_api = TaxonomyApi::new(taxonomy.clone());
+            taxonomy_api.set_index(indices.default_index().clone());
+            taxonomy_api.set_auth(extra.auth.clone());
+            taxonomy_api.set_schedule(schedule.clone());
+            taxonomy_api.set_entries_per_request(
+                extra.entries_per_request as usize);
+            apis = apis.with_api(Namespace::new(&[taxonomy.key()])
+                .with_api(taxonomy_api));
+        }
 
-        let mut post_view = PostView::new();
-        post_view.set_post_cache(post_cache.clone());
-        post_view.set_metadata_cache(metadata_cache.clone());
-        let post_template = Template::from_file(&extra.template_dir, "post.html")
-            .unwrap_or_default();
-        post_view.set_template(post_template);
+        let (post_view, lang_post_views) = {
+            let make_post_view = || {
+                let mut post_view = PostView::new();
+                post_view.set_post_cache(post_cache.clone());
+                post_view.set_metadata_cache(metadata_cache.clone());
+                post_view.set_auth(extra.auth.clone());
+                post_view.set_schedule(schedule.clone());
+                post_view.set_index(indices.default_index().clone());
+                post_view.set_related(related.clone());
+                post_view.set_translations(translations.clone());
+                let post_template = Template::from_file(&extra.template_dir,
+                    "post.html").unwrap_or_default();
+                post_view.set_template(post_template);
+                post_view
+            };
+            let post_view = make_post_view();
+            // Articles in a specific language are at `/<lang>/posts/<id>`.
+            let lang_post_views: Vec<(String, PostView)> =
+                Some(&extra.default_language)
+                    .into_iter()
+                    .filter(|x| !x.is_empty())
+                    .chain(extra.languages.iter())
+                    .map(|lang| {
+                        let mut post_view = make_post_view();
+                        post_view.set_language(lang);
+                        (lang.to_owned(), post_view)
+                    })
+                    .collect();
+            (post_view, lang_post_views)
+        };
+
+        let mut search_view = SearchView::new(search);
+        search_view.set_post_cache(post_cache.clone());
+        search_view.set_metadata_cache(metadata_cache.clone());
+        search_view.set_auth(extra.auth.clone());
+        search_view.set_schedule(schedule.clone());
+        search_view.set_digest_template(
+            Template::from_file(&extra.template_dir, "digest.html")
+                .unwrap_or_default());
+        search_view.set_search_template(
+            Template::from_file(&extra.template_dir, "search.html")
+                .unwrap_or_default());
+        search_view.set_entries_per_request(extra.entries_per_request as usize);
+
+        let mut archive_view = ArchiveView::new(archive);
+        archive_view.set_post_cache(post_cache.clone());
+        archive_view.set_metadata_cache(metadata_cache.clone());
+        archive_view.set_auth(extra.auth.clone());
+        archive_view.set_schedule(schedule.clone());
+        archive_view.set_digest_template(
+            Template::from_file(&extra.template_dir, "digest.html")
+                .unwrap_or_default());
+        archive_view.set_archive_template(
+            Template::from_file(&extra.template_dir, "archive.html")
+                .unwrap_or_default());
+        archive_view.set_bucket_template(
+            Template::from_file(&extra.template_dir, "archive_bucket.html")
+                .unwrap_or_default());
+        archive_view.set_entries_per_request(extra.entries_per_request as usize);
+
+        let mut views = Namespace::new(&[])
+            .with_api(post_view)
+            .with_api(search_view)
+            .with_api(archive_view);
+        for taxonomy in taxonomies.iter() {
+            let mut taxonomy_view = TaxonomyView::new(taxonomy.clone());
+            taxonomy_view.set_post_cache(post_cache.clone());
+            taxonomy_view.set_metadata_cache(metadata_cache.clone());
+            taxonomy_view.set_digest_template(
+                Template::from_file(&extra.template_dir, "digest.html")
+                    .unwrap_or_default());
+            taxonomy_view.set_term_template(
+                Template::from_file(&extra.template_dir, "tag.html")
+                    .unwrap_or_default());
+            taxonomy_view.set_index(indices.default_index().clone());
+            taxonomy_view.set_auth(extra.auth.clone());
+            taxonomy_view.set_schedule(schedule.clone());
+            taxonomy_view.set_entries_per_request(
+                extra.entries_per_request as usize);
+            views = views.with_api(Namespace::new(&[taxonomy.key()])
+                .with_api(taxonomy_view));
+        }
+        for (lang, post_view) in lang_post_views {
+            views = views.with_api(Namespace::new(&[&lang[..]])
+                .with_api(post_view));
+        }
 
         let mut root_view = RootView::new();
         root_view.set_post_cache(post_cache);
@@ -79,12 +390,14 @@ impl From<Extra> for Namespace {
         let index_template = Template::from_file(&extra.template_dir, "index.html")
             .unwrap_or_default();
         root_view.set_index_template(index_template);
-        root_view.set_index(index.clone());
+        root_view.set_indices(indices.clone());
+        root_view.set_filter_index(filter_index);
+        root_view.set_auth(extra.auth.clone());
+        root_view.set_schedule(schedule);
         root_view.set_entries_per_request(extra.entries_per_request as usize);
 
-        let views = Namespace::new(&[])
-            .with_api(post_view)
-            .with_api(root_view);
+        // Root view matches everything, so it must be the last one.
+        let views = views.with_api(root_view);
 
         Namespace::new(&[])
             .with_api(apis)
@@ -98,16 +411,110 @@ impl From<TomlValue> for Extra {
     }
 }
 
+fn make_indices(extra: &Extra) -> Indices {
+    let mut indices = Indices::new(&extra.default_index, Index::default());
+    for (name, cfg) in extra.indices.iter() {
+        info!("Making index '{}' with key '{}' of type '{}'.", name, cfg.key,
+            cfg.key_type);
+        indices.insert(name, Index::with_timezone(&cfg.key, &cfg.key_type,
+            extra.timezone, None));
+    }
+    indices
+}
+
+/// Generate all the indices and data derived from articles in one pass.
+/// Articles unchanged since the last run are restored from the index snapshot.
+fn index_posts(extra: &Extra, indices: &Indices,
+    observers: &[Arc<PostObserver>]) {
+    let snapshot_path = path_buf![&extra.cache_dir, SNAPSHOT_FILE_NAME];
+    // Snapshots taken with other settings of indices and observers are
+    // discarded.
+    let signature = format!("{:?}", (&extra.indices, extra.timezone,
+        &extra.taxonomies, &extra.search_fields, &extra.archive_key,
+        &extra.related_key, observers.len()));
+    let mut snapshot = if extra.reindex {
+        info!("Reindexing is forced, index snapshot is ignored.");
+        Snapshot::new(&signature)
+    } else {
+        Snapshot::load(&snapshot_path, &signature)
+    };
+    info!("Indexing files in '{}'.", extra.published_dir);
+    snapshot.walk(&extra.published_dir, indices, observers);
+    if !snapshot.is_stale() {
+        return
+    }
+    if let Err(err) = snapshot.save(&snapshot_path) {
+        warn!("Unable to save index snapshot to '{}': {}",
+            snapshot_path.to_string_lossy(), err);
+    }
+}
+
 fn raw_to_extra(extra: RawExtra) -> Extra {
+    let mut indices = extra.indices.unwrap_or_default();
+    if !indices.contains_key(DEFAULT_INDEX_NAME) {
+        indices.insert(DEFAULT_INDEX_NAME.to_owned(), IndexConfig {
+            key: extra.index_key.unwrap_or("published".to_string()),
+            key_type: extra.index_key_type.unwrap_or("-datetime".to_string()),
+        });
+    }
+    let default_index = extra.default_index
+        .unwrap_or(DEFAULT_INDEX_NAME.to_owned());
+    if !indices.contains_key(&default_index) {
+        panic!("Default index '{}' is not defined in `indices`.",
+            default_index);
+    }
+    let languages = extra.languages.unwrap_or_default();
+    for lang in languages.iter() {
+        let is_valid = !lang.is_empty() &&
+            lang.chars().all(|x| x.is_ascii_alphanumeric() || x == '-') &&
+            Some(lang) != extra.default_language.as_ref();
+        if !is_valid {
+            panic!("Language '{}' in field `languages` should be a language \
+                tag other than `default_language`.", lang);
+        }
+    }
     Extra {
         published_dir: extra.published_dir.unwrap_or("./published".to_string()),
+        cache_dir: extra.cache_dir.unwrap_or("./cache".to_string()),
+        reindex: false,
+        watch: extra.watch.unwrap_or(false),
         auth: if let Some(token) = extra.auth_token.as_ref() {
             Arc::new(SimpleAuthority::new(token))
         } else {
             Arc::new(SimpleAuthority::default())
         },
-        index_key: extra.index_key.unwrap_or("published".to_string()),
-        index_key_type: extra.index_key_type.unwrap_or("-datetime".to_string()),
+        indices: indices,
+        default_index: default_index,
+        timezone: extra.timezone.as_ref()
+            .map(|x| parse_timezone(x)
+                .expect("Unable to parse timezone in field `timezone`."))
+            .unwrap_or(FixedOffset::east(0)),
+        taxonomies: extra.taxonomies.unwrap_or(vec![
+            "tags".to_owned(),
+            "categories".to_owned(),
+        ]),
+        archive_key: extra.archive_key.unwrap_or("published".to_owned()),
+        related_key: extra.related_key.unwrap_or("tags".to_owned()),
+        related_count: extra.related_count.unwrap_or(5),
+        created_key: extra.created_key,
+        modified_key: extra.modified_key,
+        trash_retention: match extra.trash_retention_days.unwrap_or(30) {
+            days if days > 0 => Some(Duration::days(days)),
+            _ => None,
+        },
+        default_language: extra.default_language.unwrap_or_default(),
+        languages: languages,
+        permalink: Permalink::parse(extra.permalink.as_ref()
+                .map(|x| &x[..])
+                .unwrap_or("{year}/{month}/{slug}"))
+            .expect("Unable to parse permalink pattern in field `permalink`, \
+                placeholders can only be `{year}`, `{month}`, `{day}` and \
+                `{slug}`."),
+        search_fields: extra.search_fields.unwrap_or(vec![
+            "title".to_owned(),
+            "tags".to_owned(),
+            "categories".to_owned(),
+        ]),
         entries_per_request: extra.entries_per_request.unwrap_or(5),
         allowed_exts: extra.allowed_exts.unwrap_or_default()
             .into_iter()
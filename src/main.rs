@@ -15,6 +15,7 @@ extern crate writium_cache;
 extern crate chrono;
 extern crate pulldown_cmark;
 extern crate walkdir;
+extern crate notify;
 #[macro_use]
 extern crate serde_derive;
 extern crate serde;
@@ -41,6 +42,7 @@ pub mod api;
 pub mod view;
 pub mod service;
 pub mod static_page;
+pub mod watcher;
 
 use writium::Writium;
 use writium::prelude::*;
@@ -99,7 +101,8 @@ fn main() {
     }
     // Load all Writium v1 APIs.
     info!("Loading Writus APIs.");
-    let extra = ::config::v1::Extra::from(extra);
+    let mut extra = ::config::v1::Extra::from(extra);
+    extra.reindex = cfg.reindex;
     let v1: Namespace = extra.into();
     writium.bind(v1);
 
@@ -1,13 +1,13 @@
 use std::collections::{BTreeMap, HashMap};
 use writium::prelude::*;
 use writium_cache::CacheSource;
-use super::FileAccessor;
+use super::{FileAccessor, Trash};
 
 const ERR_IO: &str = "Resource accessed but error occured during IO.";
 const ERR_BROKEN_JSON: &str = "Local JSON file is broken. Try replacing the \
     invalid data before other operations.";
 
-#[derive(Clone, Deserialize, Serialize)]
+#[derive(Clone, Deserialize, PartialEq, Serialize)]
 pub struct Comment {
     pub metadata: HashMap<String, String>,
     pub content: String,
@@ -22,12 +22,24 @@ impl CommentSource {
             accessor: FileAccessor::with_fixed_file_name(dir, "comments.json"),
         }
     }
+    /// Move removed comments to `trash` instead of removing them permanently.
+    pub fn with_trash(mut self, trash: Trash) -> CommentSource {
+        self.accessor.trash = trash;
+        self
+    }
 }
 impl CacheSource for CommentSource {
     type Value = Comments;
     fn load(&self, id: &str, create: bool) -> Result<Comments> {
         use std::io::Read;
-        let mut reader = self.accessor.read(id)?;
+        let mut reader = match self.accessor.read(id) {
+            Ok(rd) => rd,
+            Err(err) => return if create {
+                Ok(Comments::new())
+            } else {
+                Err(err)
+            }
+        };
         let mut json_vec = Vec::new();
         reader.read_to_end(&mut json_vec)
             .map_err(|err| Error::internal(ERR_BROKEN_JSON).with_cause(err))?;
@@ -41,9 +53,10 @@ impl CacheSource for CommentSource {
         }
     }
     fn unload(&self, id: &str, obj: &Comments) -> Result<()> {
-        let writer = self.accessor.write(id)?;
-        ::serde_json::to_writer_pretty(writer, obj)
-            .map_err(|err| Error::internal(ERR_IO).with_cause(err))
+        self.accessor.write(id, |writer| {
+            ::serde_json::to_writer_pretty(writer, obj)
+                .map_err(::std::io::Error::from)
+        })
     }
     fn remove(&self, id: &str) -> Result<()> {
         self.accessor.remove(id)
@@ -0,0 +1,194 @@
+///! Revision history of article files.
+///! Revisions of a file are kept in `.history/<file name>` of the article
+///! directory, each in a file named by its revision number, along with a log
+///! listing when and by whom the revisions were saved.
+use std::fs::{create_dir_all, remove_dir_all, rename, File};
+use std::io::{BufReader, Read, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use chrono::Utc;
+use writium::prelude::*;
+use super::{clean_id, write_atomic, Trash};
+
+const ERR_IO: &str = "Resource accessed but error occured during IO.";
+const ERR_BROKEN_LOG: &str = "Revision log is broken.";
+const ERR_REVISION: &str = "No such revision.";
+
+/// Name of directories in which revisions are kept. Hidden directories are
+/// ignored when articles are indexed.
+pub const HISTORY_DIR: &str = ".history";
+const LOG_FILE_NAME: &str = "log.json";
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Revision {
+    /// Revision number, counting from 1.
+    pub revision: usize,
+    /// RFC 3339 datetime when the revision was saved.
+    pub time: String,
+    /// Author of the revision, if known.
+    pub author: Option<String>,
+}
+
+pub struct History {
+    /// Root directory of articles. Nothing is recorded if it's `None`.
+    dir: Option<PathBuf>,
+    file_name: String,
+    /// Revisions are recorded one at a time, so that logs are not corrupted.
+    lock: Mutex<()>,
+}
+impl History {
+    /// Make a new history of file `file_name` of each article in `dir`.
+    pub fn new(dir: &str, file_name: &str) -> History {
+        History {
+            dir: Some(PathBuf::from(dir)),
+            file_name: file_name.to_owned(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    fn make_dir(&self, id: &str) -> Option<PathBuf> {
+        self.dir.as_ref()
+            .map(|dir| {
+                path_buf![dir, clean_id(id), HISTORY_DIR, &self.file_name]
+            })
+    }
+    fn io_err(err: ::std::io::Error) -> Error {
+        Error::internal(ERR_IO).with_cause(err)
+    }
+
+    /// All the revisions of article `id`, oldest first.
+    pub fn revisions(&self, id: &str) -> Result<Vec<Revision>> {
+        let dir = match self.make_dir(id) {
+            Some(dir) => dir,
+            None => return Ok(Vec::new()),
+        };
+        let path = path_buf![&dir, LOG_FILE_NAME];
+        if !path.exists() {
+            return Ok(Vec::new())
+        }
+        let file = File::open(&path).map_err(History::io_err)?;
+        ::serde_json::from_reader(BufReader::new(file))
+            .map_err(|err| Error::internal(ERR_BROKEN_LOG).with_cause(err))
+    }
+    /// Text of revision `rev` of article `id`.
+    pub fn get(&self, id: &str, rev: usize) -> Result<String> {
+        let path = self.make_dir(id)
+            .map(|dir| path_buf![&dir, rev.to_string()])
+            .ok_or(Error::not_found(ERR_REVISION))?;
+        let mut text = String::new();
+        File::open(&path)
+            .map_err(|err| Error::not_found(ERR_REVISION).with_cause(err))?
+            .read_to_string(&mut text)
+            .map_err(History::io_err)?;
+        Ok(text)
+    }
+    /// Save `text` as a new revision of article `id`.
+    pub fn record(&self, id: &str, text: &str, author: Option<&str>)
+        -> Result<Option<Revision>> {
+        let dir = match self.make_dir(id) {
+            Some(dir) => dir,
+            None => return Ok(None),
+        };
+        let _guard = self.lock.lock().unwrap();
+        let mut revisions = self.revisions(id)?;
+        let revision = Revision {
+            revision: revisions.last().map(|x| x.revision + 1).unwrap_or(1),
+            time: Utc::now().to_rfc3339(),
+            author: author.map(|x| x.to_owned()),
+        };
+        create_dir_all(&dir).map_err(History::io_err)?;
+        write_atomic(&path_buf![&dir, revision.revision.to_string()],
+            |writer| writer.write_all(text.as_bytes()))
+            .map_err(History::io_err)?;
+        revisions.push(revision.clone());
+        write_atomic(&path_buf![&dir, LOG_FILE_NAME], |writer| {
+            ::serde_json::to_writer_pretty(writer, &revisions)
+                .map_err(::std::io::Error::from)
+        }).map_err(History::io_err)?;
+        info!("Saved revision {} of '{}' of article '{}'.", revision.revision,
+            self.file_name, id);
+        Ok(Some(revision))
+    }
+    /// Record the change of article `id` from `old` to `new`. If the article
+    /// has no history yet, `old` is recorded first so that it's never lost.
+    pub fn record_change(&self, id: &str, old: &str, new: &str,
+        author: Option<&str>) -> Result<()> {
+        if self.dir.is_none() {
+            return Ok(())
+        }
+        if self.revisions(id)?.is_empty() && !old.is_empty() {
+            self.record(id, old, None)?;
+        }
+        if old != new {
+            self.record(id, new, author)?;
+        }
+        Ok(())
+    }
+}
+/// Move the revision history of article `id` in `dir` to article `new_id`,
+/// along with the article.
+pub fn move_history(dir: &str, id: &str, new_id: &str) -> Result<()> {
+    let path = path_buf![dir, clean_id(id), HISTORY_DIR];
+    if dir.is_empty() || !path.exists() {
+        return Ok(())
+    }
+    let new_parent = path_buf![dir, clean_id(new_id)];
+    create_dir_all(&new_parent).map_err(History::io_err)?;
+    rename(&path, path_buf![&new_parent, HISTORY_DIR])
+        .map_err(History::io_err)
+}
+
+/// Check if article `id` in `dir` has any revision history.
+pub fn has_history(dir: &str, id: &str) -> bool {
+    !dir.is_empty() && path_buf![dir, clean_id(id), HISTORY_DIR].exists()
+}
+/// Remove the revision history of article `id` in `dir`, to `trash` if it's
+/// enabled.
+pub fn remove_history(dir: &str, id: &str, trash: &Trash) -> Result<()> {
+    if !has_history(dir, id) {
+        return Ok(())
+    }
+    if trash.is_enabled() {
+        trash.put(&format!("{}/{}", clean_id(id), HISTORY_DIR))?;
+        return Ok(())
+    }
+    remove_dir_all(path_buf![dir, clean_id(id), HISTORY_DIR])
+        .map_err(History::io_err)
+}
+
+impl Default for History {
+    /// Make a `History` recording nothing.
+    fn default() -> History {
+        History {
+            dir: None,
+            file_name: String::new(),
+            lock: Mutex::new(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::remove_dir_all;
+    use super::History;
+
+    #[test]
+    fn test_history() {
+        let dir = ::std::env::temp_dir()
+            .join(format!("writus-history-{}", ::std::process::id()));
+        let history = History::new(&dir.to_string_lossy(), "content.md");
+        assert!(history.revisions("foo").unwrap().is_empty());
+        history.record_change("foo", "Old", "New", Some("alice")).unwrap();
+        history.record_change("foo", "New", "New", Some("bob")).unwrap();
+        history.record_change("foo", "New", "Newer", None).unwrap();
+        let revisions = history.revisions("foo").unwrap();
+        assert_eq!(revisions.len(), 3);
+        assert_eq!(revisions[0].author, None);
+        assert_eq!(revisions[1].author, Some("alice".to_owned()));
+        assert_eq!(revisions[2].revision, 3);
+        assert_eq!(history.get("foo", 1).unwrap(), "Old");
+        assert_eq!(history.get("foo", 3).unwrap(), "Newer");
+        assert!(history.get("foo", 4).is_err());
+        remove_dir_all(&dir).unwrap();
+    }
+}
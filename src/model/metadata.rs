@@ -1,7 +1,7 @@
 use writium::prelude::*;
 use writium_cache::CacheSource;
 use serde_json::Value as JsonValue;
-use super::FileAccessor;
+use super::{FileAccessor, Trash};
 
 const ERR_IO: &str = "Resource accessed but error occured during IO.";
 const ERR_BROKEN_JSON: &str = "Local JSON file is broken. Try replacing the\
@@ -16,6 +16,19 @@ impl MetadataSource {
             accessor: FileAccessor::with_fixed_file_name(dir, "metadata.json"),
         }
     }
+    /// Make a source of metadata translated into language `lang`, which are
+    /// kept in `metadata.<lang>.json`.
+    pub fn with_language(dir: &str, lang: &str) -> MetadataSource {
+        MetadataSource {
+            accessor: FileAccessor::with_fixed_file_name(dir,
+                &format!("metadata.{}.json", lang)),
+        }
+    }
+    /// Move removed metadata to `trash` instead of removing them permanently.
+    pub fn with_trash(mut self, trash: Trash) -> MetadataSource {
+        self.accessor.trash = trash;
+        self
+    }
 }
 impl CacheSource for MetadataSource {
     type Value = JsonValue;
@@ -53,9 +66,10 @@ impl CacheSource for MetadataSource {
         }
     }
     fn unload(&self, id: &str, val: &Self::Value) -> Result<()> {
-        let writer = self.accessor.write(id)?;
-        ::serde_json::to_writer_pretty(writer, val)
-            .map_err(|err| Error::internal(ERR_IO).with_cause(err))
+        self.accessor.write(id, |writer| {
+            ::serde_json::to_writer_pretty(writer, val)
+                .map_err(::std::io::Error::from)
+        })
     }
     fn remove(&self, id: &str) -> Result<()> {
         self.accessor.remove(id)
@@ -2,16 +2,24 @@ pub mod post;
 pub mod metadata;
 pub mod comment;
 pub mod resource;
+pub mod tracked;
+pub mod history;
+pub mod trash;
 
 pub use self::post::PostSource;
 pub use self::metadata::MetadataSource;
 pub use self::comment::CommentSource;
-pub use self::resource::ResourceSource;
+pub use self::resource::{is_post_file, list_resources, ResourceSource};
+pub use self::tracked::{Tracked, Tracker};
+pub use self::history::{has_history, move_history, remove_history, History,
+    Revision};
+pub use self::trash::{Trash, TrashItem};
 
-use std::io::{BufReader, BufWriter};
-use std::fs::{create_dir_all, remove_file, read_dir, remove_dir};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::fs::{create_dir_all, remove_file, read_dir, remove_dir, rename};
 use std::fs::{File, OpenOptions};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use writium::prelude::*;
 
 const ERR_ACCESS: &str = "Unable to access local resource.";
@@ -21,19 +29,23 @@ const ERR_BUILD_DIR: &str = "Unable to build ancestor directory.";
 /// have fixed names due to Writus design.
 struct FileAccessor {
     dir: PathBuf,
-    fixed_file_name: Option<&'static str>,
+    fixed_file_name: Option<String>,
+    /// Trash removed files are moved to.
+    trash: Trash,
 }
 impl FileAccessor {
     pub fn new(dir: &str) -> FileAccessor {
         FileAccessor {
             dir: Path::new(dir).to_owned(),
             fixed_file_name: None,
+            trash: Trash::default(),
         }
     }
-    pub fn with_fixed_file_name(dir: &str, file: &'static str) -> FileAccessor {
+    pub fn with_fixed_file_name(dir: &str, file: &str) -> FileAccessor {
         FileAccessor {
             dir: Path::new(dir).to_owned(),
-            fixed_file_name: Some(file),
+            fixed_file_name: Some(file.to_owned()),
+            trash: Trash::default(),
         }
     }
 
@@ -43,7 +55,7 @@ impl FileAccessor {
         // Clean ID, any leading '/' will lead operations to seek for files from
         // the root.
         path.push(clean_id(id));
-        if let Some(file_name) = self.fixed_file_name {
+        if let Some(ref file_name) = self.fixed_file_name {
             path.push(file_name);
         }
         path
@@ -57,13 +69,16 @@ impl FileAccessor {
             .map(|file| BufReader::new(file))
             .map_err(|err| {
                 warn!("Unable to read from file: {}", id);
-                if let Some(file) = self.fixed_file_name {
+                if let Some(ref file) = self.fixed_file_name {
                     warn!("Fixed file name is: {}", file);
                 }
                 Error::internal(ERR_ACCESS).with_cause(err)
             })
     }
-    pub fn write(&self, id: &str) -> Result<BufWriter<File>> {
+    /// Write the file of `id` with `f`. The file is replaced atomically, so
+    /// it's never left partially written, see `write_atomic`.
+    pub fn write<F>(&self, id: &str, f: F) -> Result<()>
+        where F: FnOnce(&mut BufWriter<File>) -> io::Result<()> {
         let path = self.make_path(id);
         if let Some(parent) = path.parent() {
             if !parent.exists() {
@@ -75,15 +90,10 @@ impl FileAccessor {
                     })?
             }
         }
-        OpenOptions::new()
-            .create(true)
-            .truncate(true)
-            .write(true)
-            .open(&path)
-            .map(|file| BufWriter::new(file))
+        write_atomic(&path, f)
             .map_err(|err| {
                 warn!("Unable to write to file: {}", id);
-                if let Some(file) = self.fixed_file_name {
+                if let Some(ref file) = self.fixed_file_name {
                     warn!("Fixed file name is: {}", file);
                 }
                 Error::internal(ERR_ACCESS).with_cause(err)
@@ -97,11 +107,21 @@ impl FileAccessor {
                 path.to_string_lossy());
             return Ok(())
         }
-        remove_file(path)
-            .map_err(|err| {
-                warn!("Unable to write to file: {}", id);
-                Error::internal(ERR_ACCESS).with_cause(err)
-            })?;
+        if self.trash.is_enabled() {
+            let rel_path = path.strip_prefix(&self.dir)
+                .map(|x| x.components()
+                    .map(|x| x.as_os_str().to_string_lossy().into_owned())
+                    .collect::<Vec<_>>()
+                    .join("/"))
+                .map_err(|_| Error::internal(ERR_ACCESS))?;
+            self.trash.put(&rel_path)?;
+        } else {
+            remove_file(path)
+                .map_err(|err| {
+                    warn!("Unable to write to file: {}", id);
+                    Error::internal(ERR_ACCESS).with_cause(err)
+                })?;
+        }
         loop {
             path = match path.parent() {
                 Some(parent) => parent,
@@ -129,6 +149,45 @@ impl FileAccessor {
     }
 }
 
+/// Write file `path` with `f` atomically. Data is written to a hidden
+/// temporary file in the same directory, which is synced to disk and then
+/// renamed to `path`. Thus `path` is either left intact or completely replaced,
+/// even if the process crashes or the disk is full halfway. The temporary file
+/// is removed on failure.
+fn write_atomic<F>(path: &Path, f: F) -> io::Result<()>
+    where F: FnOnce(&mut BufWriter<File>) -> io::Result<()> {
+    // Concurrent writes to the same file never share a temporary file.
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let file_name = path.file_name()
+        .ok_or(io::Error::new(io::ErrorKind::InvalidInput,
+            "Path to write doesn't end with a file name."))?
+        .to_string_lossy()
+        .into_owned();
+    let tmp_path = path.with_file_name(format!(".{}.{}-{}.tmp", file_name,
+        ::std::process::id(), COUNTER.fetch_add(1, Ordering::Relaxed)));
+    let res = File::create(&tmp_path)
+        .and_then(|file| {
+            let mut writer = BufWriter::new(file);
+            f(&mut writer)?;
+            writer.flush()?;
+            writer.get_ref().sync_all()
+        })
+        .and_then(|_| rename(&tmp_path, path));
+    if let Err(err) = res {
+        if let Err(err) = remove_file(&tmp_path) {
+            warn!("Unable to remove temporary file '{}': {}",
+                tmp_path.to_string_lossy(), err);
+        }
+        return Err(err)
+    }
+    // The rename itself is durable only after the directory is synced. It's
+    // not supported on every platform, so failures are ignored.
+    if let Some(parent) = path.parent() {
+        let _ = File::open(parent).and_then(|dir| dir.sync_all());
+    }
+    Ok(())
+}
+
 #[inline]
 fn clean_id<'a>(id: &'a str) -> &'a str {
     let pos_non_slash = id.bytes()
@@ -136,3 +195,34 @@ fn clean_id<'a>(id: &'a str) -> &'a str {
         .unwrap_or(0);
     &id[pos_non_slash..]
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{create_dir_all, read_dir, remove_dir_all, File};
+    use std::io::{self, Read, Write};
+    use super::write_atomic;
+
+    #[test]
+    fn test_write_atomic() {
+        let dir = ::std::env::temp_dir()
+            .join(format!("writus-write-{}", ::std::process::id()));
+        create_dir_all(&dir).unwrap();
+        let path = dir.join("metadata.json");
+        let read = || {
+            let mut text = String::new();
+            File::open(&path).unwrap().read_to_string(&mut text).unwrap();
+            text
+        };
+        write_atomic(&path, |w| w.write_all(b"{}")).unwrap();
+        assert_eq!(read(), "{}");
+        // A failed write leaves the original file untouched.
+        let res = write_atomic(&path, |w| {
+            w.write_all(b"{\"title\":")?;
+            Err(io::Error::new(io::ErrorKind::Other, "Disk is full."))
+        });
+        assert!(res.is_err());
+        assert_eq!(read(), "{}");
+        assert_eq!(read_dir(&dir).unwrap().count(), 1);
+        remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,6 +1,6 @@
 use writium_cache::CacheSource;
 use writium::prelude::*;
-use super::FileAccessor;
+use super::{FileAccessor, Trash};
 
 const ERR_IO: &str = "Resource accessed but error occured during IO.";
 const ERR_PARENT: &str = "Parent of requested post cannot be created. Maybe \
@@ -15,6 +15,19 @@ impl PostSource {
             accessor: FileAccessor::with_fixed_file_name(dir, "content.md"),
         }
     }
+    /// Make a source of contents translated into language `lang`, which are
+    /// kept in `content.<lang>.md`.
+    pub fn with_language(dir: &str, lang: &str) -> PostSource {
+        PostSource {
+            accessor: FileAccessor::with_fixed_file_name(dir,
+                &format!("content.{}.md", lang)),
+        }
+    }
+    /// Move contents of removed articles to `trash` instead of removing them permanently.
+    pub fn with_trash(mut self, trash: Trash) -> PostSource {
+        self.accessor.trash = trash;
+        self
+    }
 }
 impl CacheSource for PostSource {
     type Value = String;
@@ -47,9 +60,7 @@ impl CacheSource for PostSource {
     }
     fn unload(&self, id: &str, val: &String) -> Result<()> {
         use std::io::Write;
-        let mut writer = self.accessor.write(id)?;
-        writer.write_all(val.as_bytes())
-            .map_err(|err| Error::internal(ERR_IO).with_cause(err))
+        self.accessor.write(id, |writer| writer.write_all(val.as_bytes()))
     }
     fn remove(&self, id: &str) -> Result<()> {
         self.accessor.remove(id)
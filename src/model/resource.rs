@@ -1,6 +1,8 @@
+use std::path::Path;
+use walkdir::WalkDir;
 use writium_cache::CacheSource;
 use writium::prelude::*;
-use super::FileAccessor;
+use super::{clean_id, FileAccessor, Trash};
 
 const ERR_IO: &str = "Resource accessed but error occured during IO.";
 
@@ -13,6 +15,11 @@ impl ResourceSource {
             accessor: FileAccessor::new(dir),
         }
     }
+    /// Move removed resources to `trash` instead of removing them permanently.
+    pub fn with_trash(mut self, trash: Trash) -> ResourceSource {
+        self.accessor.trash = trash;
+        self
+    }
 }
 impl CacheSource for ResourceSource {
     type Value = Vec<u8>;
@@ -38,11 +45,49 @@ impl CacheSource for ResourceSource {
     }
     fn unload(&self, id: &str, data: &Vec<u8>) -> Result<()> {
         use std::io::Write;
-        let mut writer = self.accessor.write(id)?;
-        writer.write_all(&data)
-            .map_err(|err| Error::internal(ERR_IO).with_cause(err))
+        self.accessor.write(id, |writer| writer.write_all(&data))
     }
     fn remove(&self, id: &str) -> Result<()> {
         self.accessor.remove(id)
     }
 }
+
+/// IDs of all the resources of article `id` in `dir`, i.e., files in the
+/// article directory and its subdirectories, except files of the article
+/// itself and its translations, hidden files and nested articles.
+pub fn list_resources(dir: &str, id: &str) -> Vec<String> {
+    let root = path_buf![dir, clean_id(id)];
+    if dir.is_empty() || !root.is_dir() {
+        return Vec::new()
+    }
+    let is_post_dir = |path: &Path| path_buf![path, "content.md"].is_file();
+    WalkDir::new(&root)
+        .min_depth(1)
+        .into_iter()
+        .filter_entry(|x| !x.file_name().to_string_lossy().starts_with('.') &&
+            !(x.file_type().is_dir() && is_post_dir(x.path())))
+        .filter_map(|x| x.ok())
+        .filter(|x| x.file_type().is_file())
+        .filter(|x| x.depth() > 1 || !is_post_file(&x.file_name()
+            .to_string_lossy()))
+        .map(|x| {
+            let rel = x.path().strip_prefix(&root).unwrap()
+                .components()
+                .map(|x| x.as_os_str().to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+                .join("/");
+            format!("{}/{}", clean_id(id), rel)
+        })
+        .collect()
+}
+
+/// Check if `file_name` is a file of an article, including translations like
+/// `content.<lang>.md`.
+pub fn is_post_file(file_name: &str) -> bool {
+    match file_name {
+        "content.md" | "metadata.json" | "comments.json" => true,
+        _ => (file_name.starts_with("content.") && file_name.ends_with(".md")) ||
+            (file_name.starts_with("metadata.") &&
+                file_name.ends_with(".json")),
+    }
+}
@@ -0,0 +1,72 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use writium::prelude::*;
+use writium_cache::CacheSource;
+
+/// Records the items currently loaded by a cache, as they are on local storage,
+/// so that the items can be refreshed without loading anything new into the
+/// cache, or overwriting changes not written back yet.
+pub struct Tracker<T> {
+    loaded: Arc<Mutex<HashMap<String, T>>>,
+}
+impl<T> Clone for Tracker<T> {
+    fn clone(&self) -> Tracker<T> {
+        Tracker { loaded: self.loaded.clone() }
+    }
+}
+impl<T> Default for Tracker<T> {
+    fn default() -> Tracker<T> {
+        Tracker { loaded: Arc::new(Mutex::new(HashMap::new())) }
+    }
+}
+impl<T: Clone + PartialEq> Tracker<T> {
+    pub fn new() -> Tracker<T> {
+        Tracker::default()
+    }
+    /// Wrap `src` so that items loaded from it are tracked by this tracker.
+    pub fn track<Src: CacheSource<Value=T>>(&self, src: Src) -> Tracked<Src> {
+        Tracked {
+            src: src,
+            tracker: self.clone(),
+        }
+    }
+    /// Check if item `id` is currently loaded.
+    pub fn is_loaded(&self, id: &str) -> bool {
+        self.loaded.lock().unwrap().contains_key(id)
+    }
+    /// Check if item `id` is loaded and `val` in cache is still the same as
+    /// on local storage, i.e., it has not been changed in cache since loaded.
+    pub fn is_unchanged(&self, id: &str, val: &T) -> bool {
+        self.loaded.lock().unwrap().get(id)
+            .map(|x| x == val)
+            .unwrap_or(false)
+    }
+    /// Record that item `id` in cache has been replaced by `val` reloaded from
+    /// local storage.
+    pub fn reload(&self, id: &str, val: T) {
+        self.loaded.lock().unwrap().insert(id.to_owned(), val);
+    }
+}
+
+/// Cache source whose loaded items are tracked by a `Tracker`.
+pub struct Tracked<Src: CacheSource> {
+    src: Src,
+    tracker: Tracker<Src::Value>,
+}
+impl<Src: CacheSource> CacheSource for Tracked<Src>
+    where Src::Value: Clone + PartialEq {
+    type Value = Src::Value;
+    fn load(&self, id: &str, create: bool) -> Result<Self::Value> {
+        let val = self.src.load(id, create)?;
+        self.tracker.reload(id, val.clone());
+        Ok(val)
+    }
+    fn unload(&self, id: &str, val: &Self::Value) -> Result<()> {
+        self.tracker.loaded.lock().unwrap().remove(id);
+        self.src.unload(id, val)
+    }
+    fn remove(&self, id: &str) -> Result<()> {
+        self.tracker.loaded.lock().unwrap().remove(id);
+        self.src.remove(id)
+    }
+}
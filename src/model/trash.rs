@@ -0,0 +1,239 @@
+///! Trash of removed files.
+///! Each removed file or directory is kept in `.trash/<item ID>/data` of the
+///! published directory, along with `info.json` recording where it was and
+///! when it was removed, until it's restored or purged.
+use std::fs::{create_dir_all, read_dir, remove_dir_all, rename, File};
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use chrono::{DateTime, Duration, Utc};
+use writium::prelude::*;
+use super::{clean_id, write_atomic};
+
+const ERR_IO: &str = "Resource accessed but error occured during IO.";
+const ERR_ITEM: &str = "No such item in trash.";
+const ERR_RESTORE_CONFLICT: &str = "Unable to restore because another file \
+    has taken the place.";
+
+/// Name of the directory in which trash is kept. Hidden directories are
+/// ignored when articles are indexed.
+pub const TRASH_DIR: &str = ".trash";
+const INFO_FILE_NAME: &str = "info.json";
+const DATA_FILE_NAME: &str = "data";
+/// Interval between purges of expired items while the server is running.
+const PURGE_INTERVAL_SECS: u64 = 3600;
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct TrashItem {
+    /// ID of the item in trash.
+    pub id: String,
+    /// Path of the removed file, relative to the published directory.
+    pub path: String,
+    /// RFC 3339 datetime when the file was removed.
+    pub removed: String,
+}
+
+#[derive(Clone, Default)]
+pub struct Trash {
+    /// Published directory. Files are removed permanently if it's `None`.
+    dir: Option<PathBuf>,
+    /// Items are purged after being kept for this period, or kept forever if
+    /// it's `None`.
+    retention: Option<Duration>,
+    lock: Arc<Mutex<()>>,
+}
+impl Trash {
+    /// Make a new trash of files in `dir`, in which items are kept for
+    /// `retention`.
+    pub fn new(dir: &str, retention: Option<Duration>) -> Trash {
+        Trash {
+            dir: Some(PathBuf::from(dir)),
+            retention: retention,
+            lock: Arc::new(Mutex::new(())),
+        }
+    }
+    /// Check if removed files are kept in trash.
+    pub fn is_enabled(&self) -> bool {
+        self.dir.is_some()
+    }
+
+    fn io_err(err: ::std::io::Error) -> Error {
+        Error::internal(ERR_IO).with_cause(err)
+    }
+    fn make_item_dir(&self, item_id: &str) -> Result<PathBuf> {
+        let is_valid = !item_id.is_empty() &&
+            item_id.chars().all(|x| x.is_ascii_alphanumeric() || x == '-');
+        match self.dir {
+            Some(ref dir) if is_valid => Ok(path_buf![dir, TRASH_DIR, item_id]),
+            _ => Err(Error::not_found(ERR_ITEM)),
+        }
+    }
+    fn read_item(&self, item_id: &str) -> Result<TrashItem> {
+        let path = path_buf![self.make_item_dir(item_id)?, INFO_FILE_NAME];
+        let file = File::open(&path)
+            .map_err(|err| Error::not_found(ERR_ITEM).with_cause(err))?;
+        ::serde_json::from_reader(BufReader::new(file))
+            .map_err(|err| Error::internal(ERR_IO).with_cause(err))
+    }
+
+    /// Move file or directory `path`, relative to the published directory, to
+    /// trash. Nothing is done if the trash is disabled.
+    pub fn put(&self, path: &str) -> Result<Option<TrashItem>> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let dir = match self.dir {
+            Some(ref dir) => dir,
+            None => return Ok(None),
+        };
+        let _guard = self.lock.lock().unwrap();
+        let now = Utc::now();
+        let item = TrashItem {
+            id: format!("{}-{}", now.format("%Y%m%d%H%M%S%f"),
+                COUNTER.fetch_add(1, Ordering::Relaxed)),
+            path: clean_id(path).to_owned(),
+            removed: now.to_rfc3339(),
+        };
+        let item_dir = path_buf![dir, TRASH_DIR, &item.id];
+        create_dir_all(&item_dir).map_err(Trash::io_err)?;
+        write_atomic(&path_buf![&item_dir, INFO_FILE_NAME], |writer| {
+            ::serde_json::to_writer_pretty(writer, &item)
+                .map_err(::std::io::Error::from)
+        }).map_err(Trash::io_err)?;
+        rename(path_buf![dir, &item.path], path_buf![&item_dir, DATA_FILE_NAME])
+            .map_err(|err| {
+                let _ = remove_dir_all(&item_dir);
+                Trash::io_err(err)
+            })?;
+        info!("Moved '{}' to trash as '{}'.", item.path, item.id);
+        Ok(Some(item))
+    }
+    /// All the items in trash, removed earliest first.
+    pub fn items(&self) -> Result<Vec<TrashItem>> {
+        let dir = match self.dir {
+            Some(ref dir) => path_buf![dir, TRASH_DIR],
+            None => return Ok(Vec::new()),
+        };
+        if !dir.exists() {
+            return Ok(Vec::new())
+        }
+        let mut items: Vec<TrashItem> = read_dir(&dir)
+            .map_err(Trash::io_err)?
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let item_id = entry.file_name().to_string_lossy().into_owned();
+                self.read_item(&item_id)
+                    .map_err(|err| warn!("Unable to read trash item '{}': {}",
+                        item_id, err))
+                    .ok()
+            })
+            .collect();
+        items.sort_by(|a, b| a.removed.cmp(&b.removed)
+            .then_with(|| a.id.cmp(&b.id)));
+        Ok(items)
+    }
+    /// Move item `item_id` back to where it was.
+    pub fn restore(&self, item_id: &str) -> Result<TrashItem> {
+        let _guard = self.lock.lock().unwrap();
+        let item = self.read_item(item_id)?;
+        let item_dir = self.make_item_dir(item_id)?;
+        let path = path_buf![self.dir.as_ref().unwrap(), &item.path];
+        if path.exists() {
+            return Err(Error::new(StatusCode::Conflict, ERR_RESTORE_CONFLICT))
+        }
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent).map_err(Trash::io_err)?;
+        }
+        rename(path_buf![&item_dir, DATA_FILE_NAME], &path)
+            .map_err(Trash::io_err)?;
+        remove_dir_all(&item_dir).map_err(Trash::io_err)?;
+        info!("Restored '{}' from trash.", item.path);
+        Ok(item)
+    }
+    /// Remove item `item_id` permanently.
+    pub fn purge(&self, item_id: &str) -> Result<()> {
+        let _guard = self.lock.lock().unwrap();
+        let item_dir = self.make_item_dir(item_id)?;
+        if !item_dir.exists() {
+            return Err(Error::not_found(ERR_ITEM))
+        }
+        remove_dir_all(&item_dir).map_err(Trash::io_err)?;
+        info!("Purged '{}' from trash.", item_id);
+        Ok(())
+    }
+    /// Remove items kept longer than the retention period permanently. The
+    /// number of items purged is returned.
+    pub fn purge_expired(&self) -> Result<usize> {
+        let retention = match self.retention {
+            Some(retention) => retention,
+            None => return Ok(0),
+        };
+        let now = Utc::now();
+        let expired: Vec<TrashItem> = self.items()?
+            .into_iter()
+            .filter(|item| match DateTime::parse_from_rfc3339(&item.removed) {
+                Ok(removed) => now.signed_duration_since(removed) > retention,
+                Err(_) => false,
+            })
+            .collect();
+        for item in expired.iter() {
+            self.purge(&item.id)?;
+        }
+        Ok(expired.len())
+    }
+    /// Purge expired items in a new thread every `PURGE_INTERVAL_SECS`
+    /// seconds, so that they are not kept much longer than the retention
+    /// period even if the trash is never listed. Nothing is done if items are
+    /// kept forever.
+    pub fn spawn(&self) {
+        if self.dir.is_none() || self.retention.is_none() {
+            return
+        }
+        let trash = self.clone();
+        let interval = ::std::time::Duration::from_secs(PURGE_INTERVAL_SECS);
+        thread::spawn(move || loop {
+            thread::sleep(interval);
+            match trash.purge_expired() {
+                Ok(0) => {},
+                Ok(n) => info!("Purged {} expired items in trash.", n),
+                Err(err) => warn!("Unable to purge expired items in trash: {}",
+                    err),
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{create_dir_all, remove_dir_all, File};
+    use std::io::Write;
+    use chrono::Duration;
+    use super::Trash;
+
+    #[test]
+    fn test_trash() {
+        let dir = ::std::env::temp_dir()
+            .join(format!("writus-trash-{}", ::std::process::id()));
+        create_dir_all(dir.join("foo")).unwrap();
+        File::create(dir.join("foo/content.md")).unwrap()
+            .write_all(b"# Foo").unwrap();
+        let trash = Trash::new(&dir.to_string_lossy(), None);
+        let item = trash.put("/foo/content.md").unwrap().unwrap();
+        assert_eq!(item.path, "foo/content.md");
+        assert!(!dir.join("foo/content.md").exists());
+        assert_eq!(trash.items().unwrap(), vec![item.clone()]);
+        // Nothing is purged without a retention period.
+        assert_eq!(trash.purge_expired().unwrap(), 0);
+        trash.restore(&item.id).unwrap();
+        assert!(dir.join("foo/content.md").exists());
+        assert!(trash.items().unwrap().is_empty());
+        assert!(trash.restore(&item.id).is_err());
+        assert!(trash.restore("../foo").is_err());
+        let trash = Trash::new(&dir.to_string_lossy(),
+            Some(Duration::seconds(-1)));
+        trash.put("foo").unwrap();
+        assert_eq!(trash.purge_expired().unwrap(), 1);
+        assert!(trash.items().unwrap().is_empty());
+        remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,225 @@
+use std::sync::Arc;
+use serde_json::Value as JsonValue;
+use writium::prelude::*;
+use writium_auth::{Authority, DumbAuthority};
+use writium_cache::{Cache, DumbCacheSource};
+use api::archive::{parse_period, Archive};
+use api::schedule::Schedule;
+use api::etag::respond;
+use super::listing::*;
+use super::template::*;
+
+const ERR_NOT_FOUND: &'static str = "No article is archived in the requested \
+    period.";
+
+/// Archive pages, i.e., `/archive`, `/archive/<year>` and
+/// `/archive/<year>/<month>`.
+pub struct ArchiveView {
+    archive: Archive,
+    archive_template: Template,
+    bucket_template: Template,
+    digest_template: Template,
+    post_cache: Arc<Cache<String>>,
+    metadata_cache: Arc<Cache<JsonValue>>,
+    auth: Arc<Authority<Privilege=()>>,
+    schedule: Schedule,
+    entries_per_request: usize,
+}
+impl ArchiveView {
+    pub fn new(archive: Archive) -> ArchiveView {
+        ArchiveView {
+            archive: archive,
+            archive_template: Template::default(),
+            bucket_template: Template::default(),
+            digest_template: Template::default(),
+            post_cache: Arc::new(Cache::new(0, DumbCacheSource::new())),
+            metadata_cache: Arc::new(Cache::new(0, DumbCacheSource::new())),
+            auth: Arc::new(DumbAuthority::new()),
+            schedule: Schedule::default(),
+            entries_per_request: 5,
+        }
+    }
+    pub fn set_post_cache(&mut self, cache: Arc<Cache<String>>) {
+        self.post_cache = cache;
+    }
+    pub fn set_metadata_cache(&mut self, cache: Arc<Cache<JsonValue>>) {
+        self.metadata_cache = cache;
+    }
+    pub fn set_digest_template(&mut self, template: Template) {
+        self.digest_template = template;
+    }
+    pub fn set_archive_template(&mut self, template: Template) {
+        self.archive_template = template;
+    }
+    /// Template of each year or month listed in an archive page.
+    pub fn set_bucket_template(&mut self, template: Template) {
+        self.bucket_template = template;
+    }
+    /// Authority of requests listing articles not published yet.
+    pub fn set_auth(&mut self, auth: Arc<Authority<Privilege=()>>) {
+        self.auth = auth;
+    }
+    pub fn set_schedule(&mut self, schedule: Schedule) {
+        self.schedule = schedule;
+    }
+    pub fn set_entries_per_request(&mut self, epr: usize) {
+        self.entries_per_request = epr;
+    }
+
+    fn render_bucket(&self, year: i32, month: Option<u32>, count: usize)
+        -> String {
+        let (link, month) = match month {
+            Some(month) => (format!("/archive/{}/{:02}", year, month),
+                format!("{:02}", month)),
+            None => (format!("/archive/{}", year), String::new()),
+        };
+        self.bucket_template.render(&JsonValue::Null, &[
+            ("link", &link),
+            ("year", &year.to_string()),
+            ("month", &month),
+            ("count", &count.to_string()),
+        ])
+    }
+    fn render(&self, req: &mut Request) -> ApiResult {
+        use self::header::ContentType;
+        #[derive(Deserialize)]
+        struct Param {
+            /// The current page number.
+            page: Option<usize>,
+        }
+        let param = req.to_param::<Param>()?;
+        let hidden = self.schedule.hidden_from(req, &*self.auth);
+        let mut vars = Vec::new();
+        if req.path_segs().len() == 0 {
+            // All the years are listed.
+            let years = self.archive.years(&hidden);
+            let buckets: String = years.iter()
+                .map(|x| self.render_bucket(x.year, None, x.count))
+                .collect();
+            let count: usize = years.iter().map(|x| x.count).sum();
+            vars.push(("buckets", buckets));
+            vars.push(("count", count.to_string()));
+        } else {
+            let (year, month) = parse_period(req.path_segs())?;
+            let ids = self.archive.posts(year, month, &hidden);
+            if ids.is_empty() {
+                return Err(Error::not_found(ERR_NOT_FOUND))
+            }
+            // Months are listed in year pages.
+            let buckets: String = if month.is_none() {
+                self.archive.years(&hidden).into_iter()
+                    .filter(|x| x.year == year)
+                    .flat_map(|x| x.months.into_iter())
+                    .map(|x| self.render_bucket(year, Some(x.month), x.count))
+                    .collect()
+            } else {
+                String::new()
+            };
+            let pager = Pager::new(ids.len(), self.entries_per_request,
+                param.page);
+            let page_ids: Vec<String> = ids.iter()
+                .skip(pager.skip())
+                .take(pager.take())
+                .cloned()
+                .collect();
+            let digests = render_digests(&self.digest_template,
+                &self.post_cache, &self.metadata_cache, &page_ids)?;
+            vars.extend(pager.vars(""));
+            vars.push(("buckets", buckets));
+            vars.push(("digests", digests));
+            vars.push(("count", ids.len().to_string()));
+            vars.push(("year", year.to_string()));
+            vars.push(("month", month.map(|x| format!("{:02}", x))
+                .unwrap_or_default()));
+        }
+        let res = Response::new()
+            .with_header(ContentType(
+                "text/html; charset=UTF-8".parse().unwrap())
+            );
+        let body = self.archive_template.render(&JsonValue::Null,
+                &borrow_vars(&vars));
+        Ok(respond(req, res, body.into_bytes()))
+    }
+}
+impl Api for ArchiveView {
+    fn name(&self) -> &[&str] {
+        &["archive"]
+    }
+    fn route(&self, req: &mut Request) -> ApiResult {
+        use self::header::Allow;
+        match req.method() {
+            Method::Get => self.render(req),
+            Method::Options => {
+                let res = Response::new()
+                    .with_header(Allow(vec![Method::Options, Method::Get]));
+                Ok(res)
+            },
+            _ => Err(Error::method_not_allowed())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use writium::prelude::*;
+    use self::header::{Authorization, Bearer};
+    use api::archive::Archive;
+    use api::observer::PostObserver;
+    use api::schedule::Schedule;
+    use api::test_common::*;
+    use auth::SimpleAuthority;
+    use super::super::template::Template;
+    use super::ArchiveView;
+
+    fn view() -> ArchiveView {
+        let archive = Archive::new("published");
+        let schedule = Schedule::new("published");
+        let metas = vec![
+            ("foo", json!({ "published": "2999-01-01T00:00:00+08:00" })),
+            ("bar", json!({ "published": "2018-01-01T00:00:00+08:00" })),
+        ];
+        for &(id, ref meta) in metas.iter() {
+            archive.update_metadata(id, meta);
+            schedule.update_metadata(id, meta);
+        }
+        let mut view = ArchiveView::new(archive);
+        view.set_post_cache(cache_of(vec![
+            ("foo", "# Foo".to_owned()),
+            ("bar", "# Bar".to_owned()),
+        ]));
+        view.set_metadata_cache(cache_of(metas));
+        view.set_auth(Arc::new(SimpleAuthority::new("PASSWORD")));
+        view.set_schedule(schedule);
+        view.set_digest_template(Template::from_text("<?var id?>;"));
+        view.set_bucket_template(Template::from_text("<?var year?>;"));
+        view.set_archive_template(Template::from_text(
+            "<?var count?>|<?var buckets?>|<?var digests?>"));
+        view
+    }
+
+    #[test]
+    fn test_render_scheduled() {
+        let view = view();
+        let req = Request::new(Method::Get);
+        let res = test_ok(&view, req);
+        check_content(&res, "1|2018;|");
+        let req = Request::new(Method::Get)
+            .with_path_segs(&["2999"]);
+        let err = test_err(&view, req);
+        assert_eq!(err.status(), StatusCode::NotFound);
+        let req = Request::new(Method::Get)
+            .with_header(Authorization(Bearer {
+                token: "PASSWORD".to_owned(),
+            }));
+        let res = test_ok(&view, req);
+        check_content(&res, "2|2999;2018;|");
+        let req = Request::new(Method::Get)
+            .with_path_segs(&["2999"])
+            .with_header(Authorization(Bearer {
+                token: "PASSWORD".to_owned(),
+            }));
+        let res = test_ok(&view, req);
+        check_content(&res, "1|2999;|foo;");
+    }
+}
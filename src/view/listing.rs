@@ -0,0 +1,142 @@
+///! Utilities shared by views listing article digests in pages.
+use std::sync::Arc;
+use serde_json::Value as JsonValue;
+use pulldown_cmark::Parser;
+use pulldown_cmark::{Options as ParserOptions, OPTION_ENABLE_TABLES};
+use writium::prelude::*;
+use writium_cache::Cache;
+use super::template::Template;
+
+pub fn md_to_html(md: &str) -> String {
+    let mut buf = String::with_capacity(md.len());
+    let mut opts = ParserOptions::empty();
+    opts.insert(OPTION_ENABLE_TABLES);
+    let parser = Parser::new_ext(&md, opts);
+    ::pulldown_cmark::html::push_html(&mut buf, parser);
+    buf
+}
+
+/// Get the title and the first paragraph of an article.
+pub fn get_digest(full_text: &str) -> (String, String) {
+    let mut lines = full_text.lines();
+    let title = lines
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .skip_while(|ch| ch == &'#')
+        .skip_while(|ch| ch == &' ')
+        .collect();
+    let mut content = String::new();
+    lines
+        .skip_while(|line| line.trim().len() == 0)
+        .take_while(|line| line.trim().len() > 0)
+        .for_each(|x| content += x);
+    (title, content)
+}
+
+pub fn render_digest(template: &Template, id: &str, post: &str,
+    meta: &JsonValue, extra: &[(&str, &str)]) -> String {
+    let path = format!("/posts/{}", id);
+    let (title, content) = get_digest(&post);
+    let content = md_to_html(&content);
+    let mut vars = vec![
+        ("link", &path[..]),
+        ("id", id),
+        ("title", &title[..]),
+        ("content", &content[..]),
+    ];
+    vars.extend_from_slice(extra);
+    template.render(meta, &vars)
+}
+
+/// Render digests of articles listed in `ids` one after another.
+pub fn render_digests(template: &Template, post_cache: &Arc<Cache<String>>,
+    metadata_cache: &Arc<Cache<JsonValue>>, ids: &[String]) -> Result<String> {
+    let mut digests = String::new();
+    for id in ids {
+        let post_cache = post_cache.get(&id)?;
+        let post_guard = post_cache.read().unwrap();
+        let post: &str = post_guard.as_ref();
+        let metadata_cache = metadata_cache.get(&id)?;
+        let metadata_guard = metadata_cache.read().unwrap();
+        let metadata: &JsonValue = &metadata_guard;
+        digests.push_str(&render_digest(template, &id, post, metadata, &[]));
+    }
+    Ok(digests)
+}
+
+/// Borrow owned template variables so that they can be used in rendering.
+pub fn borrow_vars<'a>(vars: &'a [(&'a str, String)]) -> Vec<(&'a str, &'a str)> {
+    vars.iter()
+        .map(|&(key, ref val)| (key, &val[..]))
+        .collect()
+}
+
+/// Percent-encode `text` so that it can be used as a value in query strings.
+pub fn encode_query(text: &str) -> String {
+    let mut rv = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-_.~".contains(&byte) {
+            rv.push(byte as char);
+        } else {
+            rv.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    rv
+}
+
+/// Page calculation for a list of `len` entries.
+pub struct Pager {
+    page: usize,
+    max_page: usize,
+    entries_per_page: usize,
+}
+impl Pager {
+    /// `page` counts from 1. Pages out of range are clamped.
+    pub fn new(len: usize, entries_per_page: usize, page: Option<usize>)
+        -> Pager {
+        let max_page = if len % entries_per_page == 0 {
+            len / entries_per_page
+        } else {
+            len / entries_per_page + 1
+        };
+        let page = page.unwrap_or_default()
+            .min(max_page)
+            .max(1);
+        Pager {
+            page: page,
+            max_page: max_page,
+            entries_per_page: entries_per_page,
+        }
+    }
+    /// Number of entries before the current page.
+    pub fn skip(&self) -> usize {
+        (self.page - 1) * self.entries_per_page
+    }
+    pub fn take(&self) -> usize {
+        self.entries_per_page
+    }
+    /// Template variables `current`, `previous`, `previous_link`, `next` and
+    /// `next_link`. `query` is appended to the links, it should be empty or
+    /// started with `&`.
+    pub fn vars(&self, query: &str) -> Vec<(&'static str, String)> {
+        let page = self.page;
+        let (prev, prev_link) = if page - 1 > 0 {
+            ((page - 1).to_string(), format!("?page={}{}", page - 1, query))
+        } else {
+            (String::new(), String::new())
+        };
+        let (next, next_link) = if page + 1 <= self.max_page {
+            ((page + 1).to_string(), format!("?page={}{}", page + 1, query))
+        } else {
+            (String::new(), String::new())
+        };
+        vec![
+            ("current", page.to_string()),
+            ("previous_link", prev_link),
+            ("previous", prev),
+            ("next_link", next_link),
+            ("next", next),
+        ]
+    }
+}
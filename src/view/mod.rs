@@ -1,7 +1,14 @@
 mod template;
+pub mod listing;
+mod archive;
 mod post;
 mod root;
+mod search;
+mod taxonomy;
 
 pub use self::template::Template;
+pub use self::archive::ArchiveView;
 pub use self::post::PostView;
 pub use self::root::RootView;
+pub use self::search::SearchView;
+pub use self::taxonomy::TaxonomyView;
@@ -1,15 +1,32 @@
+use std::collections::HashSet;
 use std::sync::Arc;
 use serde_json::Value as JsonValue;
-use pulldown_cmark::Parser;
-use pulldown_cmark::{Options as ParserOptions, OPTION_ENABLE_TABLES};
 use writium::prelude::*;
+use writium_auth::{Authority, DumbAuthority};
 use writium_cache::{Cache, DumbCacheSource};
+use api::index::{is_draft, Index};
+use api::language::{vary_language, Translations};
+use api::related::Related;
+use api::schedule::Schedule;
+use api::etag::respond;
+use api::post::redirect_of;
+use super::listing::{get_digest, md_to_html};
 use super::template::*;
 
+const ERR_NOT_PUBLISHED: &'static str = "The article is not published yet.";
+
 pub struct PostView {
     template: Template,
     post_cache: Arc<Cache<String>>,
     metadata_cache: Arc<Cache<JsonValue>>,
+    auth: Arc<Authority<Privilege=()>>,
+    schedule: Schedule,
+    index: Index,
+    related: Related,
+    translations: Translations,
+    /// Language of articles, given by the URL prefix this view is mounted at.
+    /// The language is negotiated with each request if it's `None`.
+    language: Option<String>,
 }
 impl PostView {
     pub fn new() -> PostView {
@@ -17,6 +34,12 @@ impl PostView {
             template: Template::default(),
             post_cache: Arc::new(Cache::new(0, DumbCacheSource::new())),
             metadata_cache: Arc::new(Cache::new(0, DumbCacheSource::new())),
+            auth: Arc::new(DumbAuthority::new()),
+            schedule: Schedule::default(),
+            index: Index::default(),
+            related: Related::default(),
+            translations: Translations::default(),
+            language: None,
         }
     }
     pub fn set_post_cache(&mut self, cache: Arc<Cache<String>>) {
@@ -28,6 +51,101 @@ impl PostView {
     pub fn set_template(&mut self, template: Template) {
         self.template = template;
     }
+    /// Authority of requests previewing articles not published yet.
+    pub fn set_auth(&mut self, auth: Arc<Authority<Privilege=()>>) {
+        self.auth = auth;
+    }
+    pub fn set_schedule(&mut self, schedule: Schedule) {
+        self.schedule = schedule;
+    }
+    /// Set the index in which the previous and next articles are looked up.
+    pub fn set_index(&mut self, index: Index) {
+        self.index = index;
+    }
+    pub fn set_related(&mut self, related: Related) {
+        self.related = related;
+    }
+    pub fn set_translations(&mut self, translations: Translations) {
+        self.translations = translations;
+    }
+    /// Always render articles in language `lang`, regardless of `?lang=` and
+    /// `Accept-Language`.
+    pub fn set_language(&mut self, lang: &str) {
+        self.language = Some(lang.to_owned());
+    }
+    /// Languages article `id` is available in and the links to them, which are
+    /// at `/<lang>/posts/<id>`. Nothing is listed if the article is not
+    /// translated.
+    fn translation_items(&self, id: &str) -> Vec<(String, String)> {
+        let default = self.translations.default_language();
+        let mut langs = self.translations.translated(id);
+        if !langs.is_empty() && !default.is_empty() {
+            langs.insert(0, default.to_owned());
+        }
+        langs.into_iter()
+            .map(|lang| {
+                let link = format!("/{}/posts/{}", lang, id);
+                (lang, link)
+            })
+            .collect()
+    }
+    /// Related articles of article `id` excluding those in `hidden`, as a list
+    /// of metadata, each extended with `id`, `link` and `title`.
+    fn related_items(&self, id: &str, hidden: &HashSet<String>) -> JsonValue {
+        let items = self.related.related(id)
+            .into_iter()
+            .filter(|x| !hidden.contains(x))
+            .map(|id| {
+                let mut item = match self.metadata_cache.get(&id) {
+                    Ok(meta) => {
+                        let obj = meta.read().unwrap().as_object().cloned();
+                        obj.unwrap_or_default()
+                    },
+                    Err(_) => Default::default(),
+                };
+                if let Ok(post_cache) = self.post_cache.get(&id) {
+                    let (title, _) = get_digest(&post_cache.read().unwrap());
+                    item.insert("title".to_owned(), JsonValue::String(title));
+                }
+                item.insert("link".to_owned(),
+                    JsonValue::String(format!("/posts/{}", id)));
+                item.insert("id".to_owned(), JsonValue::String(id));
+                JsonValue::Object(item)
+            })
+            .collect();
+        JsonValue::Array(items)
+    }
+    /// Variables of a neighboring article, named with `prefix`:
+    ///
+    /// * `<prefix>_link` and `<prefix>_title`;
+    /// * `<prefix>.<key>` for each metadata key.
+    fn neighbor_vars(&self, prefix: &str, id: Option<String>)
+        -> Vec<(String, String)> {
+        let id = match id {
+            Some(id) => id,
+            None => return Vec::new(),
+        };
+        let mut vars = vec![
+            (format!("{}_link", prefix), format!("/posts/{}", id)),
+        ];
+        if let Ok(post_cache) = self.post_cache.get(&id) {
+            let (title, _) = get_digest(&post_cache.read().unwrap());
+            vars.push((format!("{}_title", prefix), title));
+        }
+        if let Ok(metadata_cache) = self.metadata_cache.get(&id) {
+            let guard = metadata_cache.read().unwrap();
+            if let Some(obj) = guard.as_object() {
+                for (key, val) in obj.iter() {
+                    let val = match val.as_str() {
+                        Some(string) => string.to_owned(),
+                        None => val.to_string(),
+                    };
+                    vars.push((format!("{}.{}", prefix, key), val));
+                }
+            }
+        }
+        vars
+    }
     pub fn render(&self, req: &mut Request) -> ApiResult {
         fn get_post(full_text: &str) -> (String, String) {
             let mut lines = full_text.lines();
@@ -45,34 +163,91 @@ impl PostView {
             });
             (title, content)
         }
-        fn md_to_html(md: &str) -> String {
-            let mut buf = String::with_capacity(md.len());
-            let mut opts = ParserOptions::empty();
-            opts.insert(OPTION_ENABLE_TABLES);
-            let parser = Parser::new_ext(&md, opts);
-            ::pulldown_cmark::html::push_html(&mut buf, parser);
-            buf
+        #[derive(Deserialize)]
+        struct Param {
+            /// Language the article is rendered in.
+            lang: Option<String>,
         }
         use self::header::ContentType;
+        let param = req.to_param::<Param>()?;
         let id = req.path_segs().join("/");
-        let post_cache = self.post_cache.get(&id)?;
-        let content_guard = post_cache.read().unwrap();
-        let (title, content) = get_post(content_guard.as_ref());
         let metadata_cache = self.metadata_cache.get(&id)?;
         let metadata_guard = metadata_cache.read().unwrap();
-        let metadata: &JsonValue = &metadata_guard;
+        let mut metadata: JsonValue = metadata_guard.clone();
+        // Moved articles are redirected unless another one takes the place.
+        if let Some(new_id) = redirect_of(&metadata) {
+            if self.post_cache.get(&id).is_err() {
+                return Ok(Response::new()
+                    .with_status(StatusCode::MovedPermanently)
+                    .with_header(header::Location::new(
+                        format!("/posts/{}", new_id))))
+            }
+        }
+        let is_authorized = self.auth.authorize((), &req).is_ok();
+        if (is_draft(&metadata) || self.schedule.is_hidden(&id)) &&
+            !is_authorized {
+            return Err(Error::not_found(ERR_NOT_PUBLISHED))
+        }
+        let lang = match self.language {
+            Some(ref lang) => lang.clone(),
+            None => self.translations.negotiate(req,
+                param.lang.as_ref().map(|x| &x[..])),
+        };
+        // Articles not translated yet are rendered in the default language.
+        let (text, lang) = match self.translations.content(&lang, &id) {
+            Some(text) => {
+                metadata = self.translations.metadata(&lang, &id, &metadata);
+                (text, lang)
+            },
+            None => (self.post_cache.get(&id)?.read().unwrap().clone(),
+                self.translations.default_language().to_owned()),
+        };
+        let (title, content) = get_post(&text);
         let path = format!("/posts/{}", id);
-        let res = Response::new()
+        let content = md_to_html(&content);
+        // Articles not published yet are not linked for anonymous readers.
+        let hidden = if is_authorized {
+            HashSet::new()
+        } else {
+            self.schedule.hidden()
+        };
+        let (prev, next) = self.index.neighbors(&id, &hidden);
+        let translations = self.translation_items(&id);
+        // Related articles are looped over with `<?each related?>`, and
+        // translations with `<?each translations?>`.
+        if let Some(obj) = metadata.as_object_mut() {
+            obj.insert("related".to_owned(), self.related_items(&id, &hidden));
+            let items = translations.iter()
+                .map(|&(ref lang, ref link)| json!({
+                    "lang": lang,
+                    "link": link,
+                }))
+                .collect();
+            obj.insert("translations".to_owned(), JsonValue::Array(items));
+        }
+        let mut neighbor_vars = self.neighbor_vars("prev", prev);
+        neighbor_vars.extend(self.neighbor_vars("next", next));
+        // Translations are linked with `hreflang.<lang>`.
+        neighbor_vars.extend(translations.into_iter()
+            .map(|(lang, link)| (format!("hreflang.{}", lang), link)));
+        let mut vars = vec![
+            ("link", &path[..]),
+            ("id", &id[..]),
+            ("lang", &lang[..]),
+            ("title", &title[..]),
+            ("content", &content[..]),
+        ];
+        vars.extend(neighbor_vars.iter()
+            .map(|&(ref key, ref val)| (&key[..], &val[..])));
+        let mut res = Response::new()
             .with_header(ContentType(
                 "text/html; charset=UTF-8".parse().unwrap()
-            ))
-            .with_body(self.template.render(&metadata, &[
-                ("link", &path),
-                ("id", &id),
-                ("title", &title),
-                ("content", &md_to_html(&content)),
-            ]));
-        Ok(res)
+            ));
+        if self.language.is_none() {
+            res = res.with_header(vary_language());
+        }
+        let body = self.template.render(&metadata, &vars);
+        Ok(respond(req, res, body.into_bytes()))
     }
 }
 impl Api for PostView {
@@ -92,3 +267,105 @@ impl Api for PostView {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use writium::prelude::*;
+    use api::Index;
+    use api::test_common::*;
+    use super::super::template::Template;
+    use super::PostView;
+
+    fn view() -> PostView {
+        let index = Index::new("key", "+integer", None);
+        let metas = vec![
+            ("foo", json!({ "key": 0, "author": "alice" })),
+            ("bar", json!({ "key": 1, "author": "bob" })),
+            ("baz", json!({ "key": 2, "author": "carol" })),
+        ];
+        for &(id, ref meta) in metas.iter() {
+            index.update(id, meta);
+        }
+        let mut view = PostView::new();
+        view.set_post_cache(cache_of(vec![
+            ("foo", "# Foo".to_owned()),
+            ("bar", "# Bar".to_owned()),
+            ("baz", "# Baz".to_owned()),
+        ]));
+        view.set_metadata_cache(cache_of(metas));
+        view.set_index(index);
+        view.set_template(Template::from_text(concat!(
+            "<?var prev_link?>|<?var prev_title?>|<?var prev.author?>;",
+            "<?var next_link?>|<?var next_title?>|<?var next.author?>")));
+        view
+    }
+    fn render(view: &PostView, id: &str) -> String {
+        let req = Request::new(Method::Get)
+            .with_path_segs(&[id]);
+        test_ok(view, req).to_str().unwrap().to_owned()
+    }
+    /// `foo` is translated into `zh`.
+    fn translated_view() -> PostView {
+        use api::Translations;
+        let mut translations = Translations::new("en");
+        translations.insert("zh",
+            cache_of(vec![("foo", "# 福".to_owned())]),
+            cache_of(vec![("foo", json!({ "author": "艾丽斯" }))]));
+        let mut view = view();
+        view.set_translations(translations);
+        view.set_template(Template::from_text(concat!(
+            "<?var lang?>|<?var title?>|<?var author?>;",
+            "<?var hreflang.en?>|<?var hreflang.zh?>")));
+        view
+    }
+
+    #[test]
+    fn test_neighbors() {
+        let view = view();
+        assert_eq!(render(&view, "foo"), "||;/posts/bar|Bar|bob");
+        assert_eq!(render(&view, "bar"),
+            "/posts/foo|Foo|alice;/posts/baz|Baz|carol");
+        assert_eq!(render(&view, "baz"), "/posts/bar|Bar|bob;||");
+    }
+    #[test]
+    fn test_hreflang() {
+        let view = translated_view();
+        assert_eq!(render(&view, "foo"),
+            "en|Foo|alice;/en/posts/foo|/zh/posts/foo");
+        // Articles not translated are not linked to translations.
+        assert_eq!(render(&view, "bar"), "en|Bar|bob;|");
+    }
+    #[test]
+    fn test_negotiate() {
+        use self::header::{qitem, AcceptLanguage, Vary};
+        let req = || Request::new(Method::Get)
+            .with_path_segs(&["foo"])
+            .with_header(AcceptLanguage(vec![qitem("zh".parse().unwrap())]));
+        let res = test_ok(&translated_view(), req());
+        check_content(&res, "zh|福|艾丽斯;/en/posts/foo|/zh/posts/foo");
+        assert_eq!(res.header::<Vary>().map(|x| x.to_string()),
+            Some("Accept-Language".to_owned()));
+        let res = test_ok(&translated_view(), req().with_query("lang=en"));
+        check_content(&res, "en|Foo|alice;/en/posts/foo|/zh/posts/foo");
+    }
+    #[test]
+    fn test_language_prefix() {
+        use self::header::{qitem, AcceptLanguage, Vary};
+        // Views at `/en/posts` always render articles in English.
+        let mut view = translated_view();
+        view.set_language("en");
+        let req = Request::new(Method::Get)
+            .with_path_segs(&["foo"])
+            .with_query("lang=zh")
+            .with_header(AcceptLanguage(vec![qitem("zh".parse().unwrap())]));
+        let res = test_ok(&view, req);
+        check_content(&res, "en|Foo|alice;/en/posts/foo|/zh/posts/foo");
+        assert!(res.header::<Vary>().is_none());
+        let mut view = translated_view();
+        view.set_language("zh");
+        assert_eq!(render(&view, "foo"),
+            "zh|福|艾丽斯;/en/posts/foo|/zh/posts/foo");
+        // Articles not translated yet are rendered in the default language.
+        assert_eq!(render(&view, "bar"), "en|Bar|bob;|");
+    }
+}
@@ -1,18 +1,27 @@
+use std::collections::BTreeMap;
 use std::sync::Arc;
 use serde_json::Value as JsonValue;
-use pulldown_cmark::Parser;
-use pulldown_cmark::{Options as ParserOptions, OPTION_ENABLE_TABLES};
 use writium::prelude::*;
+use writium_auth::{Authority, DumbAuthority};
 use writium_cache::{Cache, DumbCacheSource};
-use api::index::Index;
+use api::filter::{Filter, FilterIndex};
+use api::index::{Index, Indices};
+use api::schedule::Schedule;
+use api::etag::respond;
+use super::listing::*;
 use super::template::*;
 
+const ERR_INDEX: &'static str = "No index matches the requested name.";
+
 pub struct RootView {
     index_template: Template,
     digest_template: Template,
     post_cache: Arc<Cache<String>>,
     metadata_cache: Arc<Cache<JsonValue>>,
-    index: Index,
+    indices: Indices,
+    filter_index: FilterIndex,
+    auth: Arc<Authority<Privilege=()>>,
+    schedule: Schedule,
     entries_per_request: usize,
 }
 impl RootView {
@@ -22,7 +31,10 @@ impl RootView {
             digest_template: Template::default(),
             post_cache: Arc::new(Cache::new(0, DumbCacheSource::new())),
             metadata_cache: Arc::new(Cache::new(0, DumbCacheSource::new())),
-            index: Index::default(),
+            indices: Indices::default(),
+            filter_index: FilterIndex::default(),
+            auth: Arc::new(DumbAuthority::new()),
+            schedule: Schedule::default(),
             entries_per_request: 5,
         }
     }
@@ -39,105 +51,85 @@ impl RootView {
         self.index_template = template;
     }
     pub fn set_index(&mut self, index: Index) {
-        self.index = index;
+        self.indices = Indices::from(index);
+    }
+    pub fn set_indices(&mut self, indices: Indices) {
+        self.indices = indices;
+    }
+    /// Set the metadata fields listed articles are filtered by.
+    pub fn set_filter_index(&mut self, filter_index: FilterIndex) {
+        self.filter_index = filter_index;
     }
     pub fn set_entries_per_request(&mut self, epr: usize) {
         self.entries_per_request = epr;
     }
-
-    fn render_digest(&self, id: &str, post: &str, meta: &JsonValue) -> String {
-        fn get_digest(full_text: &str) -> (String, String) {
-            let mut lines = full_text.lines();
-            let title = lines
-                .next()
-                .unwrap_or_default()
-                .chars()
-                .skip_while(|ch| ch == &'#')
-                .skip_while(|ch| ch == &' ')
-                .collect();
-            let mut content = String::new();
-            lines
-                .skip_while(|line| line.trim().len() == 0)
-                .take_while(|line| line.trim().len() > 0)
-                .for_each(|x| content += x);
-            (title, content)
-        }
-        fn md_to_html(md: &str) -> String {
-            let mut buf = String::with_capacity(md.len());
-            let mut opts = ParserOptions::empty();
-            opts.insert(OPTION_ENABLE_TABLES);
-            let parser = Parser::new_ext(&md, opts);
-            ::pulldown_cmark::html::push_html(&mut buf, parser);
-            buf
-        }
-        let path = format!("/posts/{}", id);
-        let (title, content) = get_digest(&post);
-        self.digest_template.render(meta, &[
-            ("link", &path),
-            ("id", &id),
-            ("title", &title),
-            ("content", &md_to_html(&content)),
-        ])
+    /// Authority of requests previewing articles not published yet.
+    pub fn set_auth(&mut self, auth: Arc<Authority<Privilege=()>>) {
+        self.auth = auth;
+    }
+    pub fn set_schedule(&mut self, schedule: Schedule) {
+        self.schedule = schedule;
     }
+
     fn render_index(&self, req: &mut Request) -> ApiResult {
         use self::header::ContentType;
         #[derive(Deserialize)]
         struct Param {
             /// The current page number.
             page: Option<usize>,
+            /// The name of index to be listed.
+            index: Option<String>,
         }
         let param = req.to_param::<Param>()?;
 
-        let guard = self.index.read().unwrap();
-        let max_page = {
-            let len = guard.len();
-            if len % self.entries_per_request == 0 {
-                len / self.entries_per_request
-            } else {
-                len / self.entries_per_request + 1
-            }
-        };
-        let page = param.page.unwrap_or_default()
-            .min(max_page)
-            .max(1);
-        let skip = (page - 1) * self.entries_per_request;
-        let take = self.entries_per_request;
-
-        let ids = guard.get_range(skip, take);
-        let mut digests = String::new();
-        for id in ids {
-            let post_cache = self.post_cache.get(&id)?;
-            let post_guard = post_cache.read().unwrap();
-            let post: &str = post_guard.as_ref();
-            let metadata_cache = self.metadata_cache.get(&id)?;
-            let metadata_guard = metadata_cache.read().unwrap();
-            let metadata: &JsonValue = &metadata_guard;
-            digests.push_str(&self.render_digest(&id, post, metadata));
-        }
-        let current = page.to_string();
-        let (prev, prev_link) = if page - 1 > 0 {
-            ((page - 1).to_string(), format!("?page={}", page - 1))
+        let index = self.indices.get(param.index.as_ref().map(|x| &x[..]))
+            .ok_or(Error::not_found(ERR_INDEX))?;
+        // Parameters other than filters are ignored, e.g., those added by
+        // tracking links.
+        let params = req.to_param::<BTreeMap<String, String>>()?
+            .into_iter()
+            .filter(|&(ref key, _)| Filter::is_param(key))
+            .collect();
+        let filter = Filter::from_params(params, &[], index)?;
+        let hidden = self.schedule.hidden_from(req, &*self.auth);
+        let (pager, ids) = if filter.is_empty() {
+            let pager = Pager::new(index.visible_len(&hidden),
+                self.entries_per_request, param.page);
+            let ids = index.get_visible_range(pager.skip(), pager.take(),
+                &hidden);
+            (pager, ids)
         } else {
-            (String::new(), String::new())
+            let ids = index.get_visible_range(0, index.visible_len(&hidden),
+                &hidden);
+            let ids = filter.filter(ids, &self.filter_index);
+            let pager = Pager::new(ids.len(), self.entries_per_request,
+                param.page);
+            let ids = ids.into_iter()
+                .skip(pager.skip())
+                .take(pager.take())
+                .collect();
+            (pager, ids)
         };
-        let (next, next_link) = if page + 1 <= max_page {
-            ((page + 1).to_string(), format!("?page={}", page + 1))
-        } else {
-            (String::new(), String::new())
+        let digests = render_digests(&self.digest_template, &self.post_cache,
+            &self.metadata_cache, &ids)?;
+        // Keep the selected index and filter when navigating between pages.
+        let mut query = match param.index {
+            Some(ref name) => format!("&index={}", encode_query(name)),
+            None => String::new(),
         };
+        for &(ref key, ref value) in filter.params() {
+            query.push_str(&format!("&{}={}", encode_query(key),
+                encode_query(value)));
+        }
+        let mut vars = pager.vars(&query);
+        vars.push(("digests", digests));
         let res = Response::new()
             .with_header(ContentType(
                 "text/html; charset=UTF-8".parse().unwrap())
-            )
-            .with_body(self.index_template.render(&JsonValue::Null, &[
-                ("digests", &digests),
-                ("current", &current),
-                ("previous_link", &prev_link),
-                ("previous", &prev),
-                ("next_link", &next_link),
-                ("next", &next),
-            ]));
-        Ok(res)
+            );
+        let body = self.index_template.render(&JsonValue::Null,
+                &borrow_vars(&vars));
+        Ok(respond(req, res, body.into_bytes()))
     }
 }
 impl Api for RootView {
@@ -172,3 +164,71 @@ impl Api for RootView {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use writium::prelude::*;
+    use api::Index;
+    use api::filter::FilterIndex;
+    use api::observer::PostObserver;
+    use api::test_common::*;
+    use super::super::template::Template;
+    use super::RootView;
+
+    fn view() -> RootView {
+        let index = Index::new("key", "+integer", None);
+        let filter_index = FilterIndex::new();
+        let metas = vec![
+            ("foo", json!({ "key": 0, "author": "alice" })),
+            ("bar", json!({ "key": 1, "author": "bob" })),
+            ("baz", json!({ "key": 2, "author": "alice", "series": "a" })),
+        ];
+        for &(id, ref meta) in metas.iter() {
+            index.update(id, meta);
+            filter_index.update_metadata(id, meta);
+        }
+        let mut view = RootView::new();
+        view.set_post_cache(cache_of(vec![
+            ("foo", "# Foo".to_owned()),
+            ("bar", "# Bar".to_owned()),
+            ("baz", "# Baz".to_owned()),
+        ]));
+        view.set_metadata_cache(cache_of(metas));
+        view.set_index(index);
+        view.set_filter_index(filter_index);
+        view.set_digest_template(Template::from_text("<?var id?>;"));
+        view.set_index_template(
+            Template::from_text("<?var digests?>|<?var next_link?>"));
+        view.set_entries_per_request(1);
+        view
+    }
+    fn render(view: &RootView, query: &str) -> String {
+        let req = Request::new(Method::Get)
+            .with_query(query);
+        test_ok(view, req).to_str().unwrap().to_owned()
+    }
+
+    #[test]
+    fn test_filter() {
+        let view = view();
+        assert_eq!(render(&view, ""), "foo;|?page=2");
+        // The filter is kept in links to other pages.
+        assert_eq!(render(&view, "filter.author=alice"),
+            "foo;|?page=2&filter.author=alice");
+        assert_eq!(render(&view, "page=2&filter.author=alice"), "baz;|");
+        assert_eq!(render(&view, "has=series"), "baz;|");
+        assert_eq!(render(&view, "after=1&filter.author=alice"), "baz;|");
+        assert_eq!(render(&view, "before=1&filter.author=bob"), "|");
+        // Other parameters are ignored.
+        assert_eq!(render(&view, "utm_source=feed&fbclid=x"), "foo;|?page=2");
+        assert_eq!(render(&view, "lang=zh"), "foo;|?page=2");
+    }
+    #[test]
+    fn fail_filter() {
+        let view = view();
+        let req = Request::new(Method::Get)
+            .with_query("after=yesterday");
+        let err = test_err(&view, req);
+        assert_eq!(err.status(), StatusCode::BadRequest);
+    }
+}
@@ -0,0 +1,183 @@
+use std::sync::Arc;
+use serde_json::Value as JsonValue;
+use writium::prelude::*;
+use writium_auth::{Authority, DumbAuthority};
+use writium_cache::{Cache, DumbCacheSource};
+use api::schedule::Schedule;
+use api::search::{Query, SearchIndex};
+use api::search::text::escape_html;
+use api::etag::respond;
+use super::listing::*;
+use super::template::*;
+
+/// Search result page, i.e., `/search?{q}[page]`.
+pub struct SearchView {
+    search: SearchIndex,
+    search_template: Template,
+    digest_template: Template,
+    post_cache: Arc<Cache<String>>,
+    metadata_cache: Arc<Cache<JsonValue>>,
+    auth: Arc<Authority<Privilege=()>>,
+    schedule: Schedule,
+    entries_per_request: usize,
+}
+impl SearchView {
+    pub fn new(search: SearchIndex) -> SearchView {
+        SearchView {
+            search: search,
+            search_template: Template::default(),
+            digest_template: Template::default(),
+            post_cache: Arc::new(Cache::new(0, DumbCacheSource::new())),
+            metadata_cache: Arc::new(Cache::new(0, DumbCacheSource::new())),
+            auth: Arc::new(DumbAuthority::new()),
+            schedule: Schedule::default(),
+            entries_per_request: 5,
+        }
+    }
+    pub fn set_post_cache(&mut self, cache: Arc<Cache<String>>) {
+        self.post_cache = cache;
+    }
+    pub fn set_metadata_cache(&mut self, cache: Arc<Cache<JsonValue>>) {
+        self.metadata_cache = cache;
+    }
+    pub fn set_digest_template(&mut self, template: Template) {
+        self.digest_template = template;
+    }
+    pub fn set_search_template(&mut self, template: Template) {
+        self.search_template = template;
+    }
+    /// Authority of requests searching articles not published yet.
+    pub fn set_auth(&mut self, auth: Arc<Authority<Privilege=()>>) {
+        self.auth = auth;
+    }
+    pub fn set_schedule(&mut self, schedule: Schedule) {
+        self.schedule = schedule;
+    }
+    pub fn set_entries_per_request(&mut self, epr: usize) {
+        self.entries_per_request = epr;
+    }
+
+    fn render(&self, req: &mut Request) -> ApiResult {
+        use self::header::ContentType;
+        #[derive(Deserialize)]
+        struct Param {
+            /// The search query.
+            q: Option<String>,
+            /// The current page number.
+            page: Option<usize>,
+        }
+        let param = req.to_param::<Param>()?;
+        let q = param.q.unwrap_or_default();
+        let query = Query::parse(&q);
+        let hidden = self.schedule.hidden_from(req, &*self.auth);
+        let hits: Vec<(String, f64)> = self.search.search(&query).into_iter()
+            .filter(|&(ref id, _)| !hidden.contains(id))
+            .collect();
+        let pager = Pager::new(hits.len(), self.entries_per_request,
+            param.page);
+        // Digests are rendered with the snippet and score of each hit.
+        let mut results = String::new();
+        for &(ref id, score) in hits.iter()
+            .skip(pager.skip())
+            .take(pager.take()) {
+            let result = match self.search.result(&query, id, score) {
+                Some(result) => result,
+                None => continue,
+            };
+            // Hits removed since they were indexed are skipped rather than
+            // failing the whole page.
+            let post_cache = match self.post_cache.get(&id) {
+                Ok(cache) => cache,
+                Err(_) => continue,
+            };
+            let post_guard = post_cache.read().unwrap();
+            let metadata = self.metadata_cache.get(&id)
+                .map(|x| x.read().unwrap().clone())
+                .unwrap_or(json!({}));
+            let score = score.to_string();
+            results.push_str(&render_digest(&self.digest_template, &id,
+                &post_guard, &metadata, &[
+                    ("snippet", &result.snippet),
+                    ("score", &score),
+                ]));
+        }
+        let mut vars = pager.vars(&format!("&q={}", encode_query(&q)));
+        vars.push(("results", results));
+        vars.push(("query", escape_html(&q)));
+        vars.push(("total", hits.len().to_string()));
+        let res = Response::new()
+            .with_header(ContentType(
+                "text/html; charset=UTF-8".parse().unwrap())
+            );
+        let body = self.search_template.render(&JsonValue::Null,
+                &borrow_vars(&vars));
+        Ok(respond(req, res, body.into_bytes()))
+    }
+}
+impl Api for SearchView {
+    fn name(&self) -> &[&str] {
+        &["search"]
+    }
+    fn route(&self, req: &mut Request) -> ApiResult {
+        use self::header::Allow;
+        match req.method() {
+            Method::Get => self.render(req),
+            Method::Options => {
+                let res = Response::new()
+                    .with_header(Allow(vec![Method::Options, Method::Get]));
+                Ok(res)
+            },
+            _ => Err(Error::method_not_allowed())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use writium::prelude::*;
+    use self::header::{Authorization, Bearer};
+    use api::observer::PostObserver;
+    use api::schedule::Schedule;
+    use api::search::SearchIndex;
+    use api::test_common::*;
+    use auth::SimpleAuthority;
+    use super::super::template::Template;
+    use super::SearchView;
+
+    #[test]
+    fn test_render_scheduled() {
+        let search = SearchIndex::new(&[]);
+        let schedule = Schedule::new("published");
+        for &(id, published) in [
+            ("foo", "2999-01-01T00:00:00+08:00"),
+            ("bar", "2018-01-01T00:00:00+08:00"),
+        ].iter() {
+            let meta = json!({ "published": published });
+            search.update_content(id, "# Rust");
+            search.update_metadata(id, &meta);
+            schedule.update_metadata(id, &meta);
+        }
+        let mut view = SearchView::new(search);
+        view.set_post_cache(cache_of(vec![
+            ("foo", "# Rust".to_owned()),
+            ("bar", "# Rust".to_owned()),
+        ]));
+        view.set_auth(Arc::new(SimpleAuthority::new("PASSWORD")));
+        view.set_schedule(schedule);
+        view.set_digest_template(Template::from_text("<?var id?>;"));
+        view.set_search_template(
+            Template::from_text("<?var total?>:<?var results?>"));
+        let req = Request::new(Method::Get)
+            .with_query("q=rust");
+        let res = test_ok(&view, req);
+        check_content(&res, "1:bar;");
+        let req = Request::new(Method::Get)
+            .with_query("q=rust")
+            .with_header(Authorization(Bearer {
+                token: "PASSWORD".to_owned(),
+            }));
+        let res = test_ok(&view, req);
+        assert!(res.to_str().unwrap().starts_with("2:"));
+    }
+}
@@ -0,0 +1,184 @@
+use std::sync::Arc;
+use serde_json::Value as JsonValue;
+use writium::prelude::*;
+use writium_auth::{Authority, DumbAuthority};
+use writium_cache::{Cache, DumbCacheSource};
+use api::index::Index;
+use api::schedule::Schedule;
+use api::taxonomy::Taxonomy;
+use api::etag::respond;
+use super::listing::*;
+use super::template::*;
+
+const ERR_NOT_FOUND: &'static str = "No article is classified by the \
+    requested term.";
+
+/// Listing of articles classified by a term, i.e., `/<taxonomy>/<term..>`.
+pub struct TaxonomyView {
+    taxonomy: Taxonomy,
+    term_template: Template,
+    digest_template: Template,
+    post_cache: Arc<Cache<String>>,
+    metadata_cache: Arc<Cache<JsonValue>>,
+    index: Index,
+    auth: Arc<Authority<Privilege=()>>,
+    schedule: Schedule,
+    entries_per_request: usize,
+}
+impl TaxonomyView {
+    pub fn new(taxonomy: Taxonomy) -> TaxonomyView {
+        TaxonomyView {
+            taxonomy: taxonomy,
+            term_template: Template::default(),
+            digest_template: Template::default(),
+            post_cache: Arc::new(Cache::new(0, DumbCacheSource::new())),
+            metadata_cache: Arc::new(Cache::new(0, DumbCacheSource::new())),
+            index: Index::default(),
+            auth: Arc::new(DumbAuthority::new()),
+            schedule: Schedule::default(),
+            entries_per_request: 5,
+        }
+    }
+    pub fn set_post_cache(&mut self, cache: Arc<Cache<String>>) {
+        self.post_cache = cache;
+    }
+    pub fn set_metadata_cache(&mut self, cache: Arc<Cache<JsonValue>>) {
+        self.metadata_cache = cache;
+    }
+    pub fn set_digest_template(&mut self, template: Template) {
+        self.digest_template = template;
+    }
+    pub fn set_term_template(&mut self, template: Template) {
+        self.term_template = template;
+    }
+    /// Set the index by which articles are ordered.
+    pub fn set_index(&mut self, index: Index) {
+        self.index = index;
+    }
+    /// Authority of requests listing articles not published yet.
+    pub fn set_auth(&mut self, auth: Arc<Authority<Privilege=()>>) {
+        self.auth = auth;
+    }
+    pub fn set_schedule(&mut self, schedule: Schedule) {
+        self.schedule = schedule;
+    }
+    pub fn set_entries_per_request(&mut self, epr: usize) {
+        self.entries_per_request = epr;
+    }
+
+    fn render_term(&self, req: &mut Request) -> ApiResult {
+        use self::header::ContentType;
+        #[derive(Deserialize)]
+        struct Param {
+            /// The current page number.
+            page: Option<usize>,
+        }
+        let param = req.to_param::<Param>()?;
+        let term = req.path_segs().join("/");
+        let hidden = self.schedule.hidden_from(req, &*self.auth);
+        let ids = self.taxonomy.posts(&term, &self.index, &hidden)
+            .ok_or(Error::not_found(ERR_NOT_FOUND))?;
+        let pager = Pager::new(ids.len(), self.entries_per_request,
+            param.page);
+        let ids: Vec<String> = ids.into_iter()
+            .skip(pager.skip())
+            .take(pager.take())
+            .collect();
+        let digests = render_digests(&self.digest_template, &self.post_cache,
+            &self.metadata_cache, &ids)?;
+        let mut vars = pager.vars("");
+        vars.push(("digests", digests));
+        vars.push(("taxonomy", self.taxonomy.key().to_owned()));
+        vars.push(("term", term));
+        let res = Response::new()
+            .with_header(ContentType(
+                "text/html; charset=UTF-8".parse().unwrap())
+            );
+        let body = self.term_template.render(&JsonValue::Null,
+                &borrow_vars(&vars));
+        Ok(respond(req, res, body.into_bytes()))
+    }
+}
+impl Api for TaxonomyView {
+    fn name(&self) -> &[&str] {
+        // Bound in a namespace named after the taxonomy key.
+        &[]
+    }
+    fn route(&self, req: &mut Request) -> ApiResult {
+        use self::header::Allow;
+        match req.method() {
+            Method::Get => if req.path_segs().len() == 0 {
+                Err(Error::not_found(ERR_NOT_FOUND))
+            } else {
+                self.render_term(req)
+            },
+            Method::Options => {
+                let res = Response::new()
+                    .with_header(Allow(vec![Method::Options, Method::Get]));
+                Ok(res)
+            },
+            _ => Err(Error::method_not_allowed())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use writium::prelude::*;
+    use self::header::{Authorization, Bearer};
+    use api::index::Index;
+    use api::observer::PostObserver;
+    use api::schedule::Schedule;
+    use api::taxonomy::Taxonomy;
+    use api::test_common::*;
+    use auth::SimpleAuthority;
+    use super::super::template::Template;
+    use super::TaxonomyView;
+
+    #[test]
+    fn test_render_scheduled() {
+        let taxonomy = Taxonomy::new("tags");
+        let index = Index::new("key", "+integer", None);
+        let schedule = Schedule::new("published");
+        let metas = vec![
+            ("foo", json!({
+                "key": 0,
+                "tags": ["rust"],
+                "published": "2999-01-01T00:00:00+08:00",
+            })),
+            ("bar", json!({
+                "key": 1,
+                "tags": ["rust"],
+                "published": "2018-01-01T00:00:00+08:00",
+            })),
+        ];
+        for &(id, ref meta) in metas.iter() {
+            taxonomy.update_metadata(id, meta);
+            index.update(id, meta);
+            schedule.update_metadata(id, meta);
+        }
+        let mut view = TaxonomyView::new(taxonomy);
+        view.set_post_cache(cache_of(vec![
+            ("foo", "# Foo".to_owned()),
+            ("bar", "# Bar".to_owned()),
+        ]));
+        view.set_metadata_cache(cache_of(metas));
+        view.set_index(index);
+        view.set_auth(Arc::new(SimpleAuthority::new("PASSWORD")));
+        view.set_schedule(schedule);
+        view.set_digest_template(Template::from_text("<?var id?>;"));
+        view.set_term_template(Template::from_text("<?var digests?>"));
+        let req = Request::new(Method::Get)
+            .with_path_segs(&["rust"]);
+        let res = test_ok(&view, req);
+        check_content(&res, "bar;");
+        let req = Request::new(Method::Get)
+            .with_path_segs(&["rust"])
+            .with_header(Authorization(Bearer {
+                token: "PASSWORD".to_owned(),
+            }));
+        let res = test_ok(&view, req);
+        check_content(&res, "foo;bar;");
+    }
+}
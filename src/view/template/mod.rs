@@ -31,10 +31,13 @@ fn concat_subfragments(base: &Path, mut template: String)
                     let frag_path = parts[1].trim();
                     let subfrag_path = path_buf![&base, &frag_path];
                     rv += &load_fragement(base, &subfrag_path)?;
-                } else if parts[0] == "var" {
-                    // Keep variables for the next stage (compilation).
+                } else if parts[0] == "var" || parts[0] == "each" {
+                    // Keep variables and loops for the next stage
+                    // (compilation).
                     extend = true;
                 }
+            } else if parts[0].trim() == "end" {
+                extend = true;
             }
             // Ignore unknown processing instructions.
         }
@@ -54,16 +57,22 @@ fn load_fragement(base: &Path, file_path: &Path) -> Result<String, String> {
         .map_err(|err| format!("Unable to read from template file: {}", err))?;
     concat_subfragments(base, buf)
 }
-fn compile(mut concated: String) -> Vec<Box<TemplateSection>> {
+fn compile(mut concated: String)
+    -> Result<Vec<Box<TemplateSection>>, String> {
     let mut rv: Vec<Box<TemplateSection>> = Vec::new();
+    // Enclosing loops, each with its key and the sections before it.
+    let mut loops: Vec<(String, Vec<Box<TemplateSection>>)> = Vec::new();
     loop {
         if let Some(beg) = concated.find("<?") {
             let string = concated.drain(..beg).collect();
             rv.push(Box::new(StringSection::new(string)));
         } else {
             // No more processing instructions, get out of the loop.
+            if !loops.is_empty() {
+                return Err("Unclosed loop.".to_owned())
+            }
             rv.push(Box::new(StringSection::new(concated)));
-            return rv
+            return Ok(rv)
         }
         // There should be no invalid syntax present (after
         // concat_subfragments).
@@ -72,9 +81,20 @@ fn compile(mut concated: String) -> Vec<Box<TemplateSection>> {
             let parts: Vec<&str> = concated[2..end]
                 .splitn(2, ' ')
                 .collect();
-            // `parts[0]` can be nothing other than 'var'.
-            rv.push(Box::new(MetadataSection::new(parts[1].to_owned())));
-            // Ignore unknown processing instructions.
+            // `parts[0]` can be nothing other than 'var', 'each' and 'end'.
+            match parts[0].trim() {
+                "each" => {
+                    let outer = ::std::mem::replace(&mut rv, Vec::new());
+                    loops.push((parts[1].trim().to_owned(), outer));
+                },
+                "end" => {
+                    let (key, outer) = loops.pop()
+                        .ok_or("Loop ending without beginning.".to_owned())?;
+                    let body = ::std::mem::replace(&mut rv, outer);
+                    rv.push(Box::new(LoopSection::new(key, body)));
+                },
+                _ => rv.push(Box::new(MetadataSection::new(parts[1].to_owned()))),
+            }
         }
         concated.drain(..(end + 2));
     }
@@ -86,14 +106,20 @@ pub struct Template {
 impl Template {
     pub fn from_file(base: &str, path: &str) -> Option<Template> {
         info!("Loading template from file: {}", [base, path].join("/"));
-        let concated = match load_fragement(Path::new(base), Path::new(path)) {
-            Ok(concated) => concated,
+        let sections = load_fragement(Path::new(base), Path::new(path))
+            .and_then(compile);
+        match sections {
+            Ok(sections) => Some(Template { sections: sections }),
             Err(err) => {
                 error!("Cannot compile template: {}", err);
-                return None
+                None
             },
-        };
-        Some(Template { sections: compile(concated) })
+        }
+    }
+    /// Compile template `text`, in which fragments are not supported.
+    #[cfg(test)]
+    pub fn from_text(text: &str) -> Template {
+        Template { sections: compile(text.to_owned()).unwrap() }
     }
     pub fn render(&self, meta: &JsonValue, extra: &[(&str, &str)]) -> String {
         let mut rv = String::new();
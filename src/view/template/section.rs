@@ -44,3 +44,28 @@ impl TemplateSection for MetadataSection {
         }
     }
 }
+/// Repeat `body` for each item of the array at metadata key `key`, with the
+/// item as metadata.
+pub struct LoopSection {
+    key: String,
+    body: Vec<Box<TemplateSection>>,
+}
+impl LoopSection {
+    pub fn new(key: String, body: Vec<Box<TemplateSection>>) -> LoopSection {
+        LoopSection {
+            key: key,
+            body: body,
+        }
+    }
+}
+impl TemplateSection for LoopSection {
+    fn get_section(&self, meta: &JsonValue, extra: &[(&str, &str)], out: &mut String) {
+        if let Some(&JsonValue::Array(ref items)) = meta.get(&self.key) {
+            for item in items.iter() {
+                for sec in self.body.iter() {
+                    sec.get_section(item, extra, out);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,535 @@
+///! Watcher of `published_dir`, keeping caches, indices and data derived from
+///! articles in sync with changes made directly to local storage, e.g., by a
+///! `git pull` or a text editor.
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+use notify::{self, DebouncedEvent, RecommendedWatcher, RecursiveMode};
+use notify::Watcher as NotifyWatcher;
+use serde_json::Value as JsonValue;
+use writium_cache::{Cache, CacheSource, DumbCacheSource};
+use api::{Indices, PostObserver};
+use api::post::translation_of;
+use model::*;
+
+/// Events are not handled until no more change is made for this period.
+const DEBOUNCE_DELAY_MS: u64 = 500;
+
+/// A cache together with the tracker of items it has loaded, and a source to
+/// load fresh values from.
+struct Watched<T: 'static> {
+    cache: Arc<Cache<T>>,
+    tracker: Tracker<T>,
+    src: Box<CacheSource<Value=T> + Send + Sync>,
+}
+impl<T: 'static + Clone + PartialEq> Watched<T> {
+    fn new<Src>(src: Src) -> Watched<T>
+        where Src: 'static + CacheSource<Value=T> + Send + Sync {
+        Watched {
+            cache: Arc::new(Cache::new(0, DumbCacheSource::new())),
+            tracker: Tracker::new(),
+            src: Box::new(src),
+        }
+    }
+    fn set_cache(&mut self, cache: Arc<Cache<T>>, tracker: Tracker<T>) {
+        self.cache = cache;
+        self.tracker = tracker;
+    }
+    /// Replace the cached value of item `id` with the one on local storage.
+    /// Items not loaded are ignored so nothing new is loaded into the cache,
+    /// and so are items changed in cache but not written back yet, which are
+    /// newer than those on local storage. Returns true if the cache is
+    /// changed.
+    fn refresh(&self, id: &str, exists: bool) -> bool {
+        if !self.tracker.is_loaded(id) {
+            return false
+        }
+        let cache = match self.cache.get(id) {
+            Ok(cache) => cache,
+            Err(_) => return false,
+        };
+        if !self.tracker.is_unchanged(id, &cache.read().unwrap()) {
+            warn!("'{}' is changed both in cache and on local storage, the \
+                change in cache is kept.", id);
+            return false
+        }
+        if !exists {
+            // The file has already been removed so it's safe to remove it
+            // again through the cache.
+            return match self.cache.remove(id) {
+                Ok(_) => true,
+                Err(err) => {
+                    warn!("Unable to remove '{}' from cache: {}", id, err);
+                    false
+                },
+            }
+        }
+        let val = match self.src.load(id, false) {
+            Ok(val) => val,
+            Err(err) => {
+                warn!("Unable to reload '{}': {}", id, err);
+                return false
+            },
+        };
+        let mut guard = cache.write().unwrap();
+        if *guard == val {
+            false
+        } else {
+            *guard = val.clone();
+            self.tracker.reload(id, val);
+            true
+        }
+    }
+}
+
+pub struct Watcher {
+    dir: PathBuf,
+    post: Watched<String>,
+    metadata: Watched<JsonValue>,
+    comment: Watched<Comments>,
+    resource: Watched<Vec<u8>>,
+    /// Translated content and metadata by language.
+    translations: BTreeMap<String, (Watched<String>, Watched<JsonValue>)>,
+    indices: Indices,
+    observers: Vec<Arc<PostObserver>>,
+    /// IDs of articles on local storage, used to find articles removed when
+    /// all articles are reloaded.
+    posts: BTreeSet<String>,
+}
+impl Watcher {
+    pub fn new(dir: &str) -> Watcher {
+        // Paths in events are prefixed with the canonical path.
+        let path = Path::new(dir);
+        Watcher {
+            dir: path.canonicalize().unwrap_or(path.to_owned()),
+            post: Watched::new(PostSource::new(dir)),
+            metadata: Watched::new(MetadataSource::new(dir)),
+            comment: Watched::new(CommentSource::new(dir)),
+            resource: Watched::new(ResourceSource::new(dir)),
+            translations: BTreeMap::new(),
+            indices: Indices::default(),
+            observers: Vec::new(),
+            posts: ::api::index::list_posts(dir).into_iter().collect(),
+        }
+    }
+    pub fn set_post_cache(&mut self, cache: Arc<Cache<String>>,
+        tracker: Tracker<String>) {
+        self.post.set_cache(cache, tracker);
+    }
+    pub fn set_metadata_cache(&mut self, cache: Arc<Cache<JsonValue>>,
+        tracker: Tracker<JsonValue>) {
+        self.metadata.set_cache(cache, tracker);
+    }
+    pub fn set_comment_cache(&mut self, cache: Arc<Cache<Comments>>,
+        tracker: Tracker<Comments>) {
+        self.comment.set_cache(cache, tracker);
+    }
+    pub fn set_resource_cache(&mut self, cache: Arc<Cache<Vec<u8>>>,
+        tracker: Tracker<Vec<u8>>) {
+        self.resource.set_cache(cache, tracker);
+    }
+    /// Watch translations into language `lang`, whose content and metadata
+    /// are kept in `post_cache` and `metadata_cache` respectively.
+    pub fn add_translation(&mut self, lang: &str,
+        post_cache: Arc<Cache<String>>, post_tracker: Tracker<String>,
+        metadata_cache: Arc<Cache<JsonValue>>,
+        metadata_tracker: Tracker<JsonValue>) {
+        let dir = self.dir.to_string_lossy().into_owned();
+        let mut post = Watched::new(PostSource::with_language(&dir, lang));
+        post.set_cache(post_cache, post_tracker);
+        let mut metadata = Watched::new(
+            MetadataSource::with_language(&dir, lang));
+        metadata.set_cache(metadata_cache, metadata_tracker);
+        self.translations.insert(lang.to_owned(), (post, metadata));
+    }
+    pub fn set_indices(&mut self, indices: Indices) {
+        self.indices = indices;
+    }
+    pub fn add_observer(&mut self, observer: Arc<PostObserver>) {
+        self.observers.push(observer);
+    }
+
+    /// Start watching in a new thread.
+    pub fn spawn(mut self) -> notify::Result<()> {
+        let (tx, rx) = channel();
+        let delay = Duration::from_millis(DEBOUNCE_DELAY_MS);
+        let mut watcher: RecommendedWatcher = notify::watcher(tx, delay)?;
+        watcher.watch(&self.dir, RecursiveMode::Recursive)?;
+        info!("Watching changes in '{}'.", self.dir.to_string_lossy());
+        thread::spawn(move || {
+            // The watcher stops watching once dropped.
+            let _watcher = watcher;
+            while let Some(paths) = recv_batch(&rx, delay) {
+                self.handle(paths);
+            }
+            warn!("Watcher of '{}' has stopped.", self.dir.to_string_lossy());
+        });
+        Ok(())
+    }
+
+    fn handle(&mut self, paths: Option<BTreeSet<PathBuf>>) {
+        let paths = match paths {
+            Some(paths) => paths,
+            None => {
+                info!("Too many changes are made, reloading all articles.");
+                return self.reload_all()
+            },
+        };
+        // Each article is reloaded only once, even if both its metadata and
+        // content were changed.
+        let mut metadata_ids = BTreeSet::new();
+        let mut content_ids = BTreeSet::new();
+        for path in paths.iter() {
+            let id = match self.make_id(path) {
+                Some(id) => id,
+                None => continue,
+            };
+            let exists = path.exists();
+            if path.is_dir() {
+                // A moved article directory.
+                metadata_ids.insert(id.clone());
+                content_ids.insert(id);
+                continue
+            }
+            let (parent_id, file_name) = match id.rfind('/') {
+                Some(pos) => (&id[..pos], &id[pos + 1..]),
+                None => ("", &id[..]),
+            };
+            match file_name {
+                "metadata.json" => {
+                    if self.metadata.refresh(parent_id, exists) {
+                        info!("Reloaded metadata of '{}' in cache.", parent_id);
+                    }
+                    metadata_ids.insert(parent_id.to_owned());
+                },
+                "content.md" => {
+                    if self.post.refresh(parent_id, exists) {
+                        info!("Reloaded content of '{}' in cache.", parent_id);
+                    }
+                    content_ids.insert(parent_id.to_owned());
+                },
+                "comments.json" => {
+                    if self.comment.refresh(parent_id, exists) {
+                        info!("Reloaded comments of '{}' in cache.", parent_id);
+                    }
+                },
+                _ if is_translation(file_name) => {
+                    if self.refresh_translation(parent_id, file_name, exists) {
+                        info!("Reloaded translation '{}' of '{}' in cache.",
+                            file_name, parent_id);
+                    }
+                },
+                _ => {
+                    if self.resource.refresh(&id, exists) {
+                        info!("Reloaded resource '{}' in cache.", id);
+                    }
+                    // Maybe a removed directory of articles. Other removed
+                    // files are only resources.
+                    if !exists {
+                        let prefix = format!("{}/", id);
+                        metadata_ids.extend(self.posts.iter()
+                            .filter(|x| **x == id || x.starts_with(&prefix))
+                            .cloned());
+                    }
+                },
+            }
+        }
+        for id in metadata_ids.iter() {
+            self.reload_metadata(id);
+        }
+        for id in content_ids.iter() {
+            self.reload_content(id);
+        }
+    }
+    /// Refresh translated file `file_name` of article `id` in cache. Returns
+    /// true if the cache is changed.
+    fn refresh_translation(&self, id: &str, file_name: &str, exists: bool)
+        -> bool {
+        if let Some(lang) = translation_of(file_name, "content.", ".md") {
+            return self.translations.get(lang)
+                .map(|&(ref post, _)| post.refresh(id, exists))
+                .unwrap_or(false)
+        }
+        if let Some(lang) = translation_of(file_name, "metadata.", ".json") {
+            return self.translations.get(lang)
+                .map(|&(_, ref metadata)| metadata.refresh(id, exists))
+                .unwrap_or(false)
+        }
+        false
+    }
+    fn reload_metadata(&mut self, id: &str) {
+        let path = path_buf![&self.dir, id, "metadata.json"];
+        let meta = match ::std::fs::File::open(&path) {
+            Ok(file) => ::serde_json::from_reader::<_, JsonValue>(file).ok(),
+            Err(_) => None,
+        };
+        match meta {
+            Some(meta) => {
+                self.posts.insert(id.to_owned());
+                self.indices.update(id, &meta);
+                for observer in self.observers.iter() {
+                    observer.update_metadata(id, &meta);
+                }
+                info!("Reindexed article '{}'.", id);
+            },
+            None => if !path.exists() {
+                self.remove_post(id);
+            } else {
+                warn!("Metadata of article '{}' is broken, indices are not \
+                    updated.", id);
+            },
+        }
+    }
+    fn reload_content(&self, id: &str) {
+        use std::io::Read;
+        let path = path_buf![&self.dir, id, "content.md"];
+        let mut content = String::new();
+        if let Ok(mut file) = ::std::fs::File::open(&path) {
+            if let Err(err) = file.read_to_string(&mut content) {
+                warn!("Unable to read content of article '{}': {}", id, err);
+                return
+            }
+        }
+        for observer in self.observers.iter() {
+            observer.update_content(id, &content);
+        }
+        info!("Reindexed content of article '{}'.", id);
+    }
+    fn remove_post(&mut self, id: &str) {
+        self.posts.remove(id);
+        self.indices.remove(id);
+        for observer in self.observers.iter() {
+            observer.remove(id);
+        }
+        info!("Removed article '{}' from indices.", id);
+    }
+    fn reload_all(&mut self) {
+        let dir = self.dir.to_string_lossy().into_owned();
+        // Articles whose metadata is broken are not removed.
+        let posts: BTreeSet<String> = ::api::index::list_posts(&dir)
+            .into_iter()
+            .collect();
+        {
+            let indices = &self.indices;
+            let observers = &self.observers;
+            ::api::index::walk_posts(&dir, |id, meta, content| {
+                indices.update(id, meta);
+                for observer in observers.iter() {
+                    observer.update_metadata(id, meta);
+                    observer.update_content(id, content);
+                }
+            });
+        }
+        // Articles removed are dropped from caches as well.
+        let removed: Vec<String> = self.posts.difference(&posts)
+            .cloned()
+            .collect();
+        for id in removed {
+            self.post.refresh(&id, false);
+            self.metadata.refresh(&id, false);
+            self.comment.refresh(&id, false);
+            for &(ref post, ref metadata) in self.translations.values() {
+                post.refresh(&id, false);
+                metadata.refresh(&id, false);
+            }
+            self.remove_post(&id);
+        }
+        self.posts = posts;
+    }
+
+    /// Convert `path` to the ID used by caches, i.e., the path relative to
+    /// `published_dir` joined by `/`. Hidden files and directories are
+    /// ignored.
+    fn make_id(&self, path: &Path) -> Option<String> {
+        let rel = path.strip_prefix(&self.dir).ok()?;
+        let mut segs = Vec::new();
+        for comp in rel.components() {
+            match comp {
+                Component::Normal(seg) => {
+                    let seg = seg.to_str()?;
+                    if seg.starts_with('.') {
+                        return None
+                    }
+                    segs.push(seg.to_owned());
+                },
+                _ => return None,
+            }
+        }
+        if segs.is_empty() {
+            None
+        } else {
+            Some(segs.join("/"))
+        }
+    }
+}
+
+/// Check if `file_name` is of translated content or metadata.
+fn is_translation(file_name: &str) -> bool {
+    translation_of(file_name, "content.", ".md").is_some() ||
+        translation_of(file_name, "metadata.", ".json").is_some()
+}
+
+/// Receive a batch of changed paths. The batch is closed when no event is
+/// received in `delay`, so a burst of changes is handled all at once. The
+/// inner `None` is returned if the watched directory need to be rescanned,
+/// and the outer `None` is returned if the watcher has stopped.
+fn recv_batch(rx: &Receiver<DebouncedEvent>, delay: Duration)
+    -> Option<Option<BTreeSet<PathBuf>>> {
+    let mut paths = Some(BTreeSet::new());
+    let mut event = rx.recv().ok()?;
+    loop {
+        match event {
+            DebouncedEvent::Create(path) |
+            DebouncedEvent::Write(path) |
+            DebouncedEvent::Remove(path) => {
+                if let Some(ref mut paths) = paths {
+                    paths.insert(path);
+                }
+            },
+            DebouncedEvent::Rename(from, to) => {
+                if let Some(ref mut paths) = paths {
+                    paths.insert(from);
+                    paths.insert(to);
+                }
+            },
+            DebouncedEvent::Rescan => paths = None,
+            DebouncedEvent::Error(err, path) => {
+                warn!("Error occured watching '{}': {}", path
+                    .map(|x| x.to_string_lossy().into_owned())
+                    .unwrap_or_default(), err);
+            },
+            _ => {},
+        }
+        event = match rx.recv_timeout(delay) {
+            Ok(event) => event,
+            Err(RecvTimeoutError::Timeout) => return Some(paths),
+            Err(RecvTimeoutError::Disconnected) => return None,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeSet, HashSet};
+    use std::fs::{create_dir_all, remove_dir_all, File};
+    use std::io::Write;
+    use std::path::Path;
+    use std::sync::Arc;
+    use api::{Index, Indices, Taxonomy};
+    use super::Watcher;
+
+    fn write_post(dir: &Path, id: &str, key: i64) {
+        create_dir_all(dir.join(id)).unwrap();
+        File::create(dir.join(id).join("content.md")).unwrap()
+            .write_all(b"# Title").unwrap();
+        File::create(dir.join(id).join("metadata.json")).unwrap()
+            .write_all(format!(r#"{{"key":{},"tags":["rust"]}}"#, key)
+                .as_bytes())
+            .unwrap();
+    }
+
+    #[test]
+    fn test_handle() {
+        let dir = ::std::env::temp_dir()
+            .join(format!("writus-watcher-{}", ::std::process::id()));
+        create_dir_all(&dir).unwrap();
+        let dir = dir.canonicalize().unwrap();
+        write_post(&dir, "foo", 0);
+        write_post(&dir, "bar", 1);
+        let index = Index::new("key", "+integer", None);
+        let taxonomy = Taxonomy::new("tags");
+        let mut watcher = Watcher::new(&dir.to_string_lossy());
+        watcher.set_indices(Indices::from(index.clone()));
+        watcher.add_observer(Arc::new(taxonomy.clone()));
+        let ids = || index.read().unwrap().get_range(0, 5);
+        let paths = |paths: &[&str]| Some(paths.iter()
+            .map(|x| dir.join(x))
+            .collect::<BTreeSet<_>>());
+
+        watcher.handle(None);
+        assert_eq!(ids(), vec!["foo", "bar"]);
+        // Changed metadata.
+        write_post(&dir, "foo", 2);
+        watcher.handle(paths(&["foo/metadata.json"]));
+        assert_eq!(ids(), vec!["bar", "foo"]);
+        // New article.
+        write_post(&dir, "baz", 3);
+        watcher.handle(paths(&["baz/content.md", "baz/metadata.json"]));
+        assert_eq!(ids(), vec!["bar", "foo", "baz"]);
+        // Removed resource.
+        File::create(dir.join("baz/img.png")).unwrap();
+        ::std::fs::remove_file(dir.join("baz/img.png")).unwrap();
+        watcher.handle(paths(&["baz/img.png"]));
+        assert_eq!(ids(), vec!["bar", "foo", "baz"]);
+        // Removed article directory.
+        remove_dir_all(dir.join("baz")).unwrap();
+        watcher.handle(paths(&["baz"]));
+        assert_eq!(ids(), vec!["bar", "foo"]);
+        // Articles removed are dropped on rescan.
+        remove_dir_all(dir.join("bar")).unwrap();
+        watcher.handle(None);
+        assert_eq!(ids(), vec!["foo"]);
+        assert_eq!(taxonomy.terms(&HashSet::new()).get("rust"), Some(&1));
+        remove_dir_all(&dir).unwrap();
+    }
+    #[test]
+    fn test_handle_translation() {
+        use writium_cache::Cache;
+        use model::{MetadataSource, PostSource, Tracker};
+        let dir = ::std::env::temp_dir()
+            .join(format!("writus-watcher-zh-{}", ::std::process::id()));
+        create_dir_all(&dir).unwrap();
+        let dir = dir.canonicalize().unwrap();
+        write_post(&dir, "foo", 0);
+        let write = |text: &[u8]| File::create(dir.join("foo/content.zh.md"))
+            .unwrap()
+            .write_all(text)
+            .unwrap();
+        write(b"# Old");
+        let path = dir.to_string_lossy().into_owned();
+        let post_tracker = Tracker::new();
+        let post_cache = Arc::new(Cache::new(10,
+            post_tracker.track(PostSource::with_language(&path, "zh"))));
+        let metadata_tracker = Tracker::new();
+        let metadata_cache = Arc::new(Cache::new(10, metadata_tracker.track(
+            MetadataSource::with_language(&path, "zh"))));
+        let mut watcher = Watcher::new(&path);
+        watcher.add_translation("zh", post_cache.clone(), post_tracker,
+            metadata_cache, metadata_tracker);
+        assert_eq!(*post_cache.get("foo").unwrap().read().unwrap(), "# Old");
+        write(b"# New");
+        let mut paths = BTreeSet::new();
+        paths.insert(dir.join("foo/content.zh.md"));
+        watcher.handle(Some(paths));
+        assert_eq!(*post_cache.get("foo").unwrap().read().unwrap(), "# New");
+        remove_dir_all(&dir).unwrap();
+    }
+    #[test]
+    fn test_handle_unwritten_change() {
+        use writium_cache::Cache;
+        use model::{PostSource, Tracker};
+        let dir = ::std::env::temp_dir()
+            .join(format!("writus-watcher-dirty-{}", ::std::process::id()));
+        create_dir_all(&dir).unwrap();
+        let dir = dir.canonicalize().unwrap();
+        write_post(&dir, "foo", 0);
+        let path = dir.to_string_lossy().into_owned();
+        let tracker = Tracker::new();
+        let cache = Arc::new(Cache::new(10,
+            tracker.track(PostSource::new(&path))));
+        let mut watcher = Watcher::new(&path);
+        watcher.set_post_cache(cache.clone(), tracker);
+        // Changed in cache, not written back yet.
+        *cache.get("foo").unwrap().write().unwrap() = "# Cached".to_owned();
+        File::create(dir.join("foo/content.md")).unwrap()
+            .write_all(b"# Disk").unwrap();
+        let mut paths = BTreeSet::new();
+        paths.insert(dir.join("foo/content.md"));
+        watcher.handle(Some(paths));
+        assert_eq!(*cache.get("foo").unwrap().read().unwrap(), "# Cached");
+        remove_dir_all(&dir).unwrap();
+    }
+}